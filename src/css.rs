@@ -1,42 +1,223 @@
-#[derive(Debug)]
+use crate::properties::PropertyRegistry;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub};
+
+#[derive(Debug, Default, PartialEq)]
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    // The `@keyframes` blocks of the stylesheet. Nothing is animated; they're only parsed.
+    pub keyframes: Vec<Keyframes>,
+}
+
+/// An `@keyframes <name> { ... }` block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframes {
+    pub name: String,
+    pub stops: Vec<KeyframeStop>,
 }
 
-#[derive(Debug)]
+/// A stop of a `@keyframes` block, like `50% { ... }`. `from` and `to` are 0% and 100%.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyframeStop {
+    pub percent: f32,
+    pub declarations: Vec<Declaration>,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    // The condition of the `@media` block containing this rule, if any.
+    pub media: Option<MediaQuery>,
+}
+
+/// A media query on the width of the viewport, like `(min-width: 600px)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaQuery {
+    MinWidth(f32),
+    MaxWidth(f32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// `<ancestor> <simple>`: matches elements that match the simple selector and have an
+    /// ancestor matching the other selector.
+    Descendant(Box<Selector>, SimpleSelector),
+    /// `<parent> > <simple>`: matches elements that match the simple selector and whose parent
+    /// matches the other selector.
+    Child(Box<Selector>, SimpleSelector),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub attributes: Vec<AttributeSelector>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    /// If set, the selector styles this pseudo-element of matching elements, not the elements.
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// An attribute selector, like `[disabled]` or `[type="text"]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeSelector {
+    /// The attribute name, lowercased.
+    pub name: String,
+    /// The exact value the attribute must have, or `None` if it only has to be present.
+    pub value: Option<String>,
 }
 
-#[derive(Debug)]
+/// A pseudo-element, like `::first-line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoElement {
+    FirstLine,
+    FirstLetter,
+    /// Generated content before the element's own, from `content`.
+    Before,
+    /// Generated content after the element's own, from `content`.
+    After,
+}
+
+/// A pseudo-class. The dynamic ones are matched against an element's `ElementState`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    Hover,
+    Active,
+    Focus,
+    /// `:not(...)`: matches elements that don't match the inner selector.
+    Not(Box<SimpleSelector>),
+    /// A pseudo-class we don't support, like `:visited`, as written. It never matches.
+    Unsupported(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
-    ColorValue(Color),
+    Color(Color),
+    /// Several space-separated values, as in `box-shadow: 4px 4px black`.
+    List(Vec<Value>),
+    /// Several comma-separated values, as in `font-family: "Times New Roman", serif`.
+    CommaList(Vec<Value>),
+    /// A quoted string, without its quotes.
+    Str(String),
+    /// The address in `url(...)`, without any quotes.
+    Url(String),
+    /// A number without a unit, as in `z-index: 2`.
+    Number(f32),
+    /// A math function of lengths, as in `min(50%, 300px)`. Resolved at layout time.
+    Math(MathFunction, Vec<Value>),
+    /// Any other function, with its comma-separated arguments, as in `counter(section)`. It's up
+    /// to the property to make sense of it.
+    Function(String, Vec<Value>),
+}
+
+/// Values are written as CSS: `10px`, `#ff0000`, `min(50%, 300px)`, and so on.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Write `values` separated by `separator`.
+        let join = |f: &mut fmt::Formatter, values: &[Value], separator| {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(separator)?;
+                }
+                write!(f, "{}", value)?;
+            }
+            Ok(())
+        };
+        match self {
+            Value::Keyword(keyword) => f.write_str(keyword),
+            Value::Length(length, unit) => {
+                let unit = match unit {
+                    Unit::Px => "px",
+                    Unit::Em => "em",
+                    Unit::Rem => "rem",
+                    Unit::Percent => "%",
+                    Unit::Vw => "vw",
+                    Unit::Vh => "vh",
+                };
+                write!(f, "{}{}", length, unit)
+            }
+            Value::Color(Color { r, g, b, a: 255 }) => {
+                write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+            }
+            Value::Color(Color { r, g, b, a }) => {
+                write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+            }
+            Value::List(values) => join(f, values, " "),
+            Value::CommaList(values) => join(f, values, ", "),
+            // Strings can't contain escapes, so use whichever quote the string doesn't.
+            Value::Str(s) if s.contains('"') => write!(f, "'{}'", s),
+            Value::Str(s) => write!(f, "\"{}\"", s),
+            Value::Url(url) if url.contains(|c: char| c == ')' || c.is_whitespace()) => {
+                write!(f, "url({})", Value::Str(url.clone()))
+            }
+            Value::Url(url) => write!(f, "url({})", url),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Math(function, args) => {
+                let name = match function {
+                    MathFunction::Min => "min",
+                    MathFunction::Max => "max",
+                    MathFunction::Clamp => "clamp",
+                };
+                write!(f, "{}(", name)?;
+                join(f, args, ", ")?;
+                f.write_str(")")
+            }
+            Value::Function(name, args) => {
+                write!(f, "{}(", name)?;
+                join(f, args, ", ")?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MathFunction {
+    /// The smallest of the arguments.
+    Min,
+    /// The largest of the arguments.
+    Max,
+    /// `clamp(min, preferred, max)`: the preferred value, kept between min and max.
+    Clamp,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
+    /// Relative to the element's font size.
+    Em,
+    /// Relative to the root element's font size.
+    Rem,
+    /// Relative to the containing block's width.
+    Percent,
+    /// Relative to the viewport's width.
+    Vw,
+    /// Relative to the viewport's height.
+    Vh,
+}
+
+/// Everything needed to turn a length in any unit into px.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResolutionContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    /// The viewport's (width, height).
+    pub viewport: (f32, f32),
+    pub containing_width: f32,
+    /// The height of the containing block, or `None` if it depends on the contents.
+    pub containing_height: Option<f32>,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -47,107 +228,917 @@ pub struct Color {
     pub a: u8,
 }
 
-pub type Specificity = (usize, usize, usize);
+/// The specificity of a selector: its number of (ids, classes, attributes, and pseudo-classes,
+/// tag names).
+/// Specificities compare lexicographically, so a single id outweighs any number of classes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+impl Specificity {
+    pub fn from_selector(selector: &Selector) -> Specificity {
+        // http://www.w3.org/TR/selectors/#specificity
+        match selector {
+            Selector::Simple(simple) => Specificity::from_simple_selector(simple),
+            // A combinator adds up the specificities of its parts.
+            Selector::Descendant(ancestor, simple) | Selector::Child(ancestor, simple) => {
+                let Specificity(a, b, c) = Specificity::from_selector(ancestor);
+                let Specificity(d, e, f) = Specificity::from_simple_selector(simple);
+                Specificity(a + d, b + e, c + f)
+            }
+        }
+    }
+
+    fn from_simple_selector(simple: &SimpleSelector) -> Specificity {
+        let a = simple.id.iter().count() as u32;
+        // Attribute selectors count as classes.
+        let b = (simple.class.len() + simple.attributes.len()) as u32;
+        // A pseudo-element counts as a tag name.
+        let c = (simple.tag_name.iter().count() + simple.pseudo_element.iter().count()) as u32;
+        // `:not` counts as its argument; other pseudo-classes count as classes.
+        simple
+            .pseudo_classes
+            .iter()
+            .fold(Specificity(a, b, c), |sum, pseudo_class| {
+                let Specificity(a, b, c) = match pseudo_class {
+                    PseudoClass::Not(inner) => Specificity::from_simple_selector(inner),
+                    _ => Specificity(0, 1, 0),
+                };
+                Specificity(sum.0 + a, sum.1 + b, sum.2 + c)
+            })
+    }
+}
+
+/// The specificity of a simple selector.
+pub fn compute_specificity(selector: &SimpleSelector) -> Specificity {
+    Specificity::from_simple_selector(selector)
+}
 
 impl Selector {
     pub fn specificity(&self) -> Specificity {
-        // http://www.w3.org/TR/selectors/#specificity
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
-        (a, b, c)
+        Specificity::from_selector(self)
+    }
+
+    /// The simple selector that the matched element itself must match: the last one.
+    pub fn subject(&self) -> &SimpleSelector {
+        match self {
+            Selector::Simple(simple)
+            | Selector::Descendant(_, simple)
+            | Selector::Child(_, simple) => simple,
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::Simple(simple) => write!(f, "{}", simple),
+            Selector::Descendant(ancestor, simple) => write!(f, "{} {}", ancestor, simple),
+            Selector::Child(parent, simple) => write!(f, "{} > {}", parent, simple),
+        }
+    }
+}
+
+/// Simple selectors are written as in CSS, like `div#main.note:hover::before`, or `*` if they
+/// match every element.
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == SimpleSelector::default() {
+            return f.write_str("*");
+        }
+        if let Some(tag_name) = &self.tag_name {
+            f.write_str(tag_name)?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, "#{}", id)?;
+        }
+        for class in &self.class {
+            write!(f, ".{}", class)?;
+        }
+        for attribute in &self.attributes {
+            match &attribute.value {
+                Some(value) => write!(f, "[{}={}]", attribute.name, Value::Str(value.clone()))?,
+                None => write!(f, "[{}]", attribute.name)?,
+            }
+        }
+        for pseudo_class in &self.pseudo_classes {
+            match pseudo_class {
+                PseudoClass::Hover => f.write_str(":hover")?,
+                PseudoClass::Active => f.write_str(":active")?,
+                PseudoClass::Focus => f.write_str(":focus")?,
+                PseudoClass::Not(inner) => write!(f, ":not({})", inner)?,
+                PseudoClass::Unsupported(name) => write!(f, ":{}", name)?,
+            }
+        }
+        match self.pseudo_element {
+            Some(PseudoElement::FirstLine) => f.write_str("::first-line"),
+            Some(PseudoElement::FirstLetter) => f.write_str("::first-letter"),
+            Some(PseudoElement::Before) => f.write_str("::before"),
+            Some(PseudoElement::After) => f.write_str("::after"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)?;
+        if self.important {
+            f.write_str(" !important")?;
+        }
+        f.write_str(";")
+    }
+}
+
+impl Stylesheet {
+    /// Write the stylesheet back out as CSS, a rule per line, which parses to an equal
+    /// stylesheet. Comments and formatting aren't kept, and neither are the rules of at-rules
+    /// that were dropped while parsing.
+    pub fn to_css(&self) -> String {
+        let mut css = String::new();
+        let mut media = None;
+        for rule in &self.rules {
+            // Consecutive rules in the same `@media` block are written in one block again.
+            if rule.media != media {
+                if media.is_some() {
+                    css.push_str("}\n");
+                }
+                if let Some(query) = rule.media {
+                    css.push_str(&format!("@media {} {{\n", query));
+                }
+                media = rule.media;
+            }
+            let indent = if media.is_some() { "  " } else { "" };
+            let selectors: Vec<String> = rule.selectors.iter().map(|s| s.to_string()).collect();
+            css.push_str(&format!("{}{} {{", indent, selectors.join(", ")));
+            write_declarations(&mut css, &rule.declarations);
+        }
+        if media.is_some() {
+            css.push_str("}\n");
+        }
+        for keyframes in &self.keyframes {
+            css.push_str(&format!("@keyframes {} {{\n", keyframes.name));
+            for stop in &keyframes.stops {
+                css.push_str(&format!("  {}% {{", stop.percent));
+                write_declarations(&mut css, &stop.declarations);
+            }
+            css.push_str("}\n");
+        }
+        css
+    }
+}
+
+// Append ` name: value; ... }` and a newline to `css`.
+fn write_declarations(css: &mut String, declarations: &[Declaration]) {
+    for declaration in declarations {
+        css.push_str(&format!(" {}", declaration));
+    }
+    css.push_str(" }\n");
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+}
+
+// Named color keywords and their values.
+const NAMED_COLORS: &[(&str, Color)] = &[
+    (
+        "transparent",
+        Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        },
+    ),
+    ("black", Color::rgb(0, 0, 0)),
+    ("silver", Color::rgb(192, 192, 192)),
+    ("gray", Color::rgb(128, 128, 128)),
+    ("grey", Color::rgb(128, 128, 128)),
+    ("white", Color::rgb(255, 255, 255)),
+    ("maroon", Color::rgb(128, 0, 0)),
+    ("red", Color::rgb(255, 0, 0)),
+    ("purple", Color::rgb(128, 0, 128)),
+    ("fuchsia", Color::rgb(255, 0, 255)),
+    ("green", Color::rgb(0, 128, 0)),
+    ("lime", Color::rgb(0, 255, 0)),
+    ("olive", Color::rgb(128, 128, 0)),
+    ("yellow", Color::rgb(255, 255, 0)),
+    ("navy", Color::rgb(0, 0, 128)),
+    ("blue", Color::rgb(0, 0, 255)),
+    ("teal", Color::rgb(0, 128, 128)),
+    ("aqua", Color::rgb(0, 255, 255)),
+    ("orange", Color::rgb(255, 165, 0)),
+];
+
+// The color given by the arguments of `rgb()` or `rgba()`: red, green, and blue, as numbers from
+// 0 to 255 or percentages, and optionally alpha, as a number from 0 to 1 or a percentage.
+fn rgb_color(args: &[Value]) -> Option<Color> {
+    let channel = |value: &Value, max: f32| {
+        let fraction = match *value {
+            Value::Length(percent, Unit::Percent) => percent / 100.0,
+            _ => value.to_number()? / max,
+        };
+        Some((fraction.clamp(0.0, 1.0) * 255.0).round() as u8)
+    };
+    let (r, g, b, a) = match args {
+        [r, g, b] => (r, g, b, None),
+        [r, g, b, a] => (r, g, b, Some(a)),
+        _ => return None,
+    };
+    Some(Color {
+        r: channel(r, 255.0)?,
+        g: channel(g, 255.0)?,
+        b: channel(b, 255.0)?,
+        a: a.map_or(Some(255), |a| channel(a, 1.0))?,
+    })
+}
+
+/// Look up a named color keyword like `red` or `transparent`, ignoring case.
+pub fn named_color(name: &str) -> Option<Color> {
+    NAMED_COLORS
+        .iter()
+        .find(|(color_name, _)| color_name.eq_ignore_ascii_case(name))
+        .map(|&(_, color)| color)
+}
+
+impl fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaQuery::MinWidth(width) => write!(f, "(min-width: {}px)", width),
+            MediaQuery::MaxWidth(width) => write!(f, "(max-width: {}px)", width),
+        }
+    }
+}
+
+impl MediaQuery {
+    /// Whether the query holds for a viewport `viewport_width` px wide.
+    pub fn matches(&self, viewport_width: f32) -> bool {
+        match *self {
+            MediaQuery::MinWidth(width) => viewport_width >= width,
+            MediaQuery::MaxWidth(width) => viewport_width <= width,
+        }
     }
 }
 
 impl Value {
-    /// Return the size of a length in px, or zero for non-lengths.
+    /// A length in px.
+    pub fn px(size: f32) -> Value {
+        Value::Length(size, Unit::Px)
+    }
+
+    /// The sum of two px lengths, or `None` if either isn't one.
+    pub fn checked_add(&self, other: &Value) -> Option<Value> {
+        match (self, other) {
+            (Value::Length(a, Unit::Px), Value::Length(b, Unit::Px)) => Some(Value::px(a + b)),
+            _ => None,
+        }
+    }
+
+    /// The difference of two px lengths, or `None` if either isn't one.
+    pub fn checked_sub(&self, other: &Value) -> Option<Value> {
+        match (self, other) {
+            (Value::Length(a, Unit::Px), Value::Length(b, Unit::Px)) => Some(Value::px(a - b)),
+            _ => None,
+        }
+    }
+
+    /// Is this a length (in any unit, including percentages) or a math function of lengths? Other
+    /// values, like colors, resolve to zero, so layout checks this before using a value as a size.
+    pub fn is_length(&self) -> bool {
+        matches!(self, Value::Length(..) | Value::Math(..))
+    }
+
+    /// Is this a percentage, or a math function of one? Its size depends on the containing block.
+    pub fn has_percentage(&self) -> bool {
+        self.has_unit(&Unit::Percent)
+    }
+
+    /// Is this a length in `unit`, or a math function of one?
+    pub fn has_unit(&self, unit: &Unit) -> bool {
+        match self {
+            Value::Length(_, u) => u == unit,
+            Value::Math(_, args) => args.iter().any(|arg| arg.has_unit(unit)),
+            _ => false,
+        }
+    }
+
+    /// Return the size of a length in px, or zero for non-lengths and relative lengths.
     pub fn to_px(&self) -> f32 {
         match *self {
             Value::Length(f, Unit::Px) => f,
             _ => 0.0,
         }
     }
+
+    /// Like `to_px`, but percentages (including those in math functions) are of `containing`.
+    /// Lengths relative to the font or viewport are still zero.
+    pub fn to_px_with(&self, containing: f32) -> f32 {
+        let ctx = ResolutionContext {
+            containing_width: containing,
+            ..Default::default()
+        };
+        self.resolve(&ctx)
+    }
+
+    /// Return the size of a length in px, resolving relative units against `ctx`, or zero for
+    /// non-lengths. Percentages are of the containing block's width.
+    pub fn resolve(&self, ctx: &ResolutionContext) -> f32 {
+        self.resolve_against(ctx, ctx.containing_width)
+    }
+
+    /// Like `resolve`, but percentages are of the containing block's height, as for vertical
+    /// offsets like `top`, or zero if its height isn't definite.
+    pub fn resolve_height(&self, ctx: &ResolutionContext) -> f32 {
+        self.resolve_against(ctx, ctx.containing_height.unwrap_or(0.0))
+    }
+
+    fn resolve_against(&self, ctx: &ResolutionContext, percent_basis: f32) -> f32 {
+        match *self {
+            Value::Length(f, ref unit) => match unit {
+                Unit::Px => f,
+                Unit::Em => f * ctx.font_size,
+                Unit::Rem => f * ctx.root_font_size,
+                Unit::Percent => f / 100.0 * percent_basis,
+                Unit::Vw => f / 100.0 * ctx.viewport.0,
+                Unit::Vh => f / 100.0 * ctx.viewport.1,
+            },
+            Value::Math(function, ref args) => {
+                let mut args = args.iter().map(|a| a.resolve_against(ctx, percent_basis));
+                match function {
+                    MathFunction::Min => args.fold(f32::INFINITY, f32::min),
+                    MathFunction::Max => args.fold(f32::NEG_INFINITY, f32::max),
+                    MathFunction::Clamp => {
+                        let (min, preferred, max) = (
+                            args.next().unwrap(),
+                            args.next().unwrap(),
+                            args.next().unwrap(),
+                        );
+                        // As in CSS, `min` wins if it is larger than `max`.
+                        preferred.min(max).max(min)
+                    }
+                }
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The number this value is, if it's a number. A bare `0` is parsed as a length, so a zero
+    /// length counts as the number zero.
+    pub fn to_number(&self) -> Option<f32> {
+        match *self {
+            Value::Number(n) => Some(n),
+            Value::Length(0.0, Unit::Px) => Some(0.0),
+            _ => None,
+        }
+    }
+
+    /// Is this the keyword `keyword`? CSS keywords are ASCII case-insensitive.
+    pub fn keyword_eq(&self, keyword: &str) -> bool {
+        match *self {
+            Value::Keyword(ref k) => k.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    /// Return the color this value names, if it is a color or a color keyword.
+    pub fn to_color(&self) -> Option<Color> {
+        match *self {
+            Value::Color(color) => Some(color),
+            Value::Keyword(ref keyword) => named_color(&keyword.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+}
+
+/// Adds px lengths. Like `to_px`, anything else counts as zero.
+impl Add for &Value {
+    type Output = Value;
+
+    fn add(self, other: &Value) -> Value {
+        Value::px(self.to_px() + other.to_px())
+    }
 }
 
-/// Parse a whole css stylesheet.
+/// Subtracts px lengths. Like `to_px`, anything else counts as zero.
+impl Sub for &Value {
+    type Output = Value;
+
+    fn sub(self, other: &Value) -> Value {
+        Value::px(self.to_px() - other.to_px())
+    }
+}
+
+/// Px lengths are ordered by size. Other values are only comparable to equal values.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Length(a, Unit::Px), Value::Length(b, Unit::Px)) => a.partial_cmp(b),
+            _ if self == other => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+/// A likely mistake in a stylesheet, found by `validate`. `rule` is the index of the rule in the
+/// stylesheet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StyleWarning {
+    /// A property the engine doesn't know.
+    UnknownProperty { rule: usize, name: String },
+    /// A value of the wrong type for its property, like `color: 10px`.
+    InvalidValue {
+        rule: usize,
+        name: String,
+        value: Value,
+    },
+    /// A rule with no declarations.
+    EmptyRule { rule: usize },
+}
+
+impl fmt::Display for StyleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StyleWarning::UnknownProperty { rule, name } => {
+                write!(f, "rule {}: unknown property {:?}", rule, name)
+            }
+            StyleWarning::InvalidValue { rule, name, value } => {
+                write!(f, "rule {}: invalid value {:?} for {:?}", rule, value, name)
+            }
+            StyleWarning::EmptyRule { rule } => write!(f, "rule {}: no declarations", rule),
+        }
+    }
+}
+
+/// Check a stylesheet for unknown properties, values of the wrong type, and empty rules. The
+/// cascade silently ignores these, so this is a way for authors to find them.
+pub fn validate(stylesheet: &Stylesheet) -> Vec<StyleWarning> {
+    validate_with(stylesheet, PropertyRegistry::standard())
+}
+
+/// Like `validate`, against the properties of `registry` rather than those the engine knows.
+pub fn validate_with(stylesheet: &Stylesheet, registry: &PropertyRegistry) -> Vec<StyleWarning> {
+    let mut warnings = Vec::new();
+    for (rule, r) in stylesheet.rules.iter().enumerate() {
+        if r.declarations.is_empty() {
+            warnings.push(StyleWarning::EmptyRule { rule });
+        }
+        for declaration in &r.declarations {
+            let name = declaration.name.as_str();
+            let value = &declaration.value;
+            let valid = match registry.get(name) {
+                Some(property) => property.value_type.accepts(value),
+                None => {
+                    warnings.push(StyleWarning::UnknownProperty {
+                        rule,
+                        name: name.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let global = ["initial", "inherit", "unset"]
+                .iter()
+                .any(|keyword| value.keyword_eq(keyword));
+            if !valid && !global {
+                warnings.push(StyleWarning::InvalidValue {
+                    rule,
+                    name: name.to_string(),
+                    value: value.clone(),
+                });
+            }
+        }
+    }
+    warnings
+}
+
+/// Parse a whole css stylesheet. Panics if the stylesheet is malformed; see `try_parse`.
 pub fn parse(source: String) -> Stylesheet {
-    let mut parser = Parser {
-        pos: 0,
-        input: source,
-    };
-    Stylesheet {
-        rules: parser.parse_rules(),
+    try_parse(source).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// An error from `try_parse`: the stylesheet is malformed.
+#[derive(Debug, PartialEq)]
+pub struct CssParseError {
+    /// The byte where the parser gave up.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CssParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
     }
 }
 
+impl std::error::Error for CssParseError {}
+
+type ParseResult<T> = Result<T, CssParseError>;
+
+/// Like `parse`, but return an error instead of panicking if the stylesheet is malformed.
+pub fn try_parse(source: String) -> Result<Stylesheet, CssParseError> {
+    let mut parser = Parser::new(source);
+    let rules = parser.parse_rules()?;
+    Ok(Stylesheet {
+        rules,
+        keyframes: parser.keyframes,
+    })
+}
+
+/// Parse a stylesheet from a string slice. Like `parse`, at-rules other than `@media` (such as a
+/// leading `@charset`) are skipped.
+pub fn parse_stylesheet_str(source: &str) -> Stylesheet {
+    parse(source.to_string())
+}
+
+/// Parse a list of declarations without the braces around them, like the contents of an HTML
+/// `style` attribute. The last declaration needn't end with `;`. Panics if the list is
+/// malformed; see `try_parse_declaration_list`.
+pub fn parse_declaration_list(source: &str) -> Vec<Declaration> {
+    try_parse_declaration_list(source).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `parse_declaration_list`, but return an error instead of panicking if the list is
+/// malformed. Offsets count from the start of `source` with its leading whitespace removed.
+pub fn try_parse_declaration_list(source: &str) -> Result<Vec<Declaration>, CssParseError> {
+    let source = source.trim().trim_end_matches(';');
+    let mut parser = Parser::new(if source.is_empty() {
+        "{}".to_string()
+    } else {
+        format!("{{{};}}", source)
+    });
+    parser.parse_declarations().map_err(|err| CssParseError {
+        offset: err.offset.saturating_sub(1),
+        ..err
+    })
+}
+
+/// Parse a property value, such as `1px solid black`. Panics if the value is malformed.
+pub fn parse_value_str(source: &str) -> Value {
+    try_parse_value_str(source).unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn try_parse_value_str(source: &str) -> ParseResult<Value> {
+    // The value parser stops at the end of a declaration, so end it.
+    Parser::new(format!("{};", source.trim())).parse_values()
+}
+
 struct Parser {
     pos: usize,
     input: String,
+    // The `@keyframes` blocks parsed so far.
+    keyframes: Vec<Keyframes>,
 }
 
 impl Parser {
+    fn new(input: String) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            keyframes: Vec::new(),
+        }
+    }
+
+    /// An error at the current position.
+    fn error(&self, message: String) -> CssParseError {
+        CssParseError {
+            offset: self.pos,
+            message,
+        }
+    }
+
     /// Parse a list of rule sets, separated by optional whitespace.
-    fn parse_rules(&mut self) -> Vec<Rule> {
+    fn parse_rules(&mut self) -> ParseResult<Vec<Rule>> {
         let mut rules = Vec::new();
         loop {
-            self.consume_whitespace();
+            self.consume_whitespace_and_comments();
             if self.eof() {
                 break;
             }
-            rules.push(self.parse_rule());
+            if self.next_char()? == '@' {
+                rules.extend(self.parse_at_rule()?);
+            } else {
+                rules.push(self.parse_rule()?);
+            }
         }
-        rules
+        Ok(rules)
     }
 
     /// Parse a rule set: `<selectors> { <declarations> }`.
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    fn parse_rule(&mut self) -> ParseResult<Rule> {
+        Ok(Rule {
+            selectors: self.parse_selectors()?,
+            declarations: self.parse_declarations()?,
+            media: None,
+        })
+    }
+
+    /// Parse an at-rule, returning the rule sets it contains. Unsupported at-rules are skipped.
+    fn parse_at_rule(&mut self) -> ParseResult<Vec<Rule>> {
+        self.expect_char('@')?;
+        match self.parse_identifier().to_ascii_lowercase().as_str() {
+            "media" => self.parse_media_rule(),
+            "supports" => self.parse_supports_rule(),
+            "keyframes" => {
+                let keyframes = self.parse_keyframes()?;
+                self.keyframes.push(keyframes);
+                Ok(Vec::new())
+            }
+            _ => {
+                self.skip_at_rule()?;
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Skip the rest of an at-rule: up to and including its terminating `;`, or its block with
+    /// any nested blocks. Quoted strings may contain `;` and braces.
+    fn skip_at_rule(&mut self) -> ParseResult<()> {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char()? {
+                '"' | '\'' => {
+                    self.parse_string()?;
+                    continue;
+                }
+                ';' if depth == 0 => {
+                    self.consume_char()?;
+                    return Ok(());
+                }
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.consume_char()?;
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+            self.consume_char()?;
+        }
+        Ok(())
+    }
+
+    /// Parse `<media-query> { <rules> }` after `@media`. Nested at-rules aren't supported.
+    fn parse_media_rule(&mut self) -> ParseResult<Vec<Rule>> {
+        self.consume_whitespace_and_comments();
+        let query = self.parse_media_query()?;
+        self.consume_whitespace_and_comments();
+        self.expect_char('{')?;
+        let mut rules = Vec::new();
+        loop {
+            self.consume_whitespace_and_comments();
+            if self.next_char()? == '}' {
+                self.consume_char()?;
+                break;
+            }
+            let mut rule = self.parse_rule()?;
+            rule.media = Some(query);
+            rules.push(rule);
+        }
+        Ok(rules)
+    }
+
+    /// Parse `<condition> { <rules> }` after `@supports`. The rules are kept only if the engine
+    /// supports the condition.
+    fn parse_supports_rule(&mut self) -> ParseResult<Vec<Rule>> {
+        let supported = self.parse_supports_condition();
+        // Skip whatever is left of a condition we couldn't parse.
+        self.consume_while(|c| c != '{');
+        self.consume_whitespace_and_comments();
+        self.expect_char('{')?;
+        let mut rules = Vec::new();
+        loop {
+            self.consume_whitespace_and_comments();
+            match self.next_char()? {
+                '}' => {
+                    self.consume_char()?;
+                    break;
+                }
+                '@' => rules.extend(self.parse_at_rule()?),
+                _ => rules.push(self.parse_rule()?),
+            }
+        }
+        Ok(if supported { rules } else { Vec::new() })
+    }
+
+    /// Parse a `@supports` condition: `not <in-parens>`, or `<in-parens>` terms joined by `and`
+    /// or `or`. Anything we don't understand is false.
+    fn parse_supports_condition(&mut self) -> bool {
+        self.consume_whitespace_and_comments();
+        let start = self.pos;
+        if self.parse_identifier().eq_ignore_ascii_case("not") {
+            return !self.parse_supports_in_parens();
+        }
+        self.pos = start;
+        let mut result = self.parse_supports_in_parens();
+        loop {
+            self.consume_whitespace_and_comments();
+            match self.parse_identifier().to_ascii_lowercase().as_str() {
+                "" => return result,
+                "and" => result &= self.parse_supports_in_parens(),
+                "or" => result |= self.parse_supports_in_parens(),
+                _ => return false,
+            }
+        }
+    }
+
+    /// Parse `(<condition>)` or `(<property>: <value>)`. Functions such as `selector(...)`, and
+    /// values that don't parse, are skipped and never supported.
+    fn parse_supports_in_parens(&mut self) -> bool {
+        self.consume_whitespace_and_comments();
+        if !self.starts_with("(") {
+            self.parse_identifier();
+            if self.starts_with("(") {
+                self.consume_parenthesized();
+            }
+            return false;
+        }
+        let mut inner = Parser::new(self.consume_parenthesized());
+        inner.consume_whitespace_and_comments();
+        let name = inner.parse_identifier().to_ascii_lowercase();
+        inner.consume_whitespace_and_comments();
+        if !name.is_empty() && inner.starts_with(":") {
+            return try_parse_value_str(&inner.input[inner.pos + 1..])
+                .is_ok_and(|value| PropertyRegistry::standard().supports(&name, &value));
+        }
+        inner.pos = 0;
+        inner.parse_supports_condition()
+    }
+
+    /// Consume a parenthesized block, including any nested parentheses, and return what's inside.
+    /// The caller checks that the input is at a `(`.
+    fn consume_parenthesized(&mut self) -> String {
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                '(' => depth += 1,
+                _ => {}
+            }
+            self.pos += c.len_utf8();
+        }
+        let contents = self.input[start..self.pos].to_string();
+        if !self.eof() {
+            self.pos += 1;
+        }
+        contents
+    }
+
+    /// Parse `<name> { <stops> }` after `@keyframes`. Each stop is a comma-separated list of
+    /// percentages or `from`/`to`, then declarations.
+    fn parse_keyframes(&mut self) -> ParseResult<Keyframes> {
+        self.consume_whitespace_and_comments();
+        let name = match self.next_char()? {
+            '"' | '\'' => self.parse_quoted()?,
+            _ => self.parse_identifier(),
+        };
+        self.consume_whitespace_and_comments();
+        self.expect_char('{')?;
+        let mut stops = Vec::new();
+        loop {
+            self.consume_whitespace_and_comments();
+            if self.next_char()? == '}' {
+                self.consume_char()?;
+                break;
+            }
+            let mut percents = Vec::new();
+            loop {
+                percents.push(self.parse_keyframe_selector()?);
+                self.consume_whitespace_and_comments();
+                if self.next_char()? != ',' {
+                    break;
+                }
+                self.consume_char()?;
+                self.consume_whitespace_and_comments();
+            }
+            let declarations = self.parse_declarations()?;
+            stops.extend(percents.into_iter().map(|percent| KeyframeStop {
+                percent,
+                declarations: declarations.clone(),
+            }));
+        }
+        Ok(Keyframes { name, stops })
+    }
+
+    /// Parse a keyframe selector: a percentage, `from`, or `to`.
+    fn parse_keyframe_selector(&mut self) -> ParseResult<f32> {
+        if self.next_char()?.is_ascii_digit() {
+            return match self.parse_length()? {
+                Value::Length(percent, Unit::Percent) => Ok(percent),
+                value => Err(self.error(format!(
+                    "Expected a percentage in @keyframes, found {:?}",
+                    value
+                ))),
+            };
+        }
+        match self.parse_identifier().to_ascii_lowercase().as_str() {
+            "from" => Ok(0.0),
+            "to" => Ok(100.0),
+            name => Err(self.error(format!("Unexpected keyframe selector {}", name))),
+        }
+    }
+
+    /// Parse a media query: `(min-width: <length>)` or `(max-width: <length>)`.
+    fn parse_media_query(&mut self) -> ParseResult<MediaQuery> {
+        self.expect_char('(')?;
+        self.consume_whitespace_and_comments();
+        let feature = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace_and_comments();
+        self.expect_char(':')?;
+        self.consume_whitespace_and_comments();
+        // Font-relative lengths in media queries are of the default font size.
+        let width = self.parse_length()?.resolve(&ResolutionContext {
+            font_size: crate::style::DEFAULT_FONT_SIZE,
+            root_font_size: crate::style::DEFAULT_FONT_SIZE,
+            ..Default::default()
+        });
+        self.consume_whitespace_and_comments();
+        self.expect_char(')')?;
+        match feature.as_str() {
+            "min-width" => Ok(MediaQuery::MinWidth(width)),
+            "max-width" => Ok(MediaQuery::MaxWidth(width)),
+            _ => Err(self.error(format!("Unsupported media feature {}", feature))),
         }
     }
 
     /// Parse a comma-separated list of selectors.
-    fn parse_selectors(&mut self) -> Vec<Selector> {
+    fn parse_selectors(&mut self) -> ParseResult<Vec<Selector>> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
-            match self.next_char() {
+            // Skip empty groups, as in `h1, , h2` or a trailing comma.
+            while self.next_char()? == ',' {
+                self.consume_char()?;
+                self.consume_whitespace_and_comments();
+            }
+            if self.next_char()? == '{' {
+                break;
+            }
+            let mut selector = Selector::Simple(self.parse_simple_selector()?);
+            self.consume_whitespace_and_comments();
+            // Whitespace followed by another simple selector is the descendant combinator, and
+            // `>` the child combinator.
+            loop {
+                let c = self.next_char()?;
+                if c == '>' {
+                    self.consume_char()?;
+                    self.consume_whitespace_and_comments();
+                    selector = Selector::Child(Box::new(selector), self.parse_simple_selector()?);
+                } else if matches!(c, '*' | '#' | '.' | '[' | ':') || valid_identifier_char(c) {
+                    selector =
+                        Selector::Descendant(Box::new(selector), self.parse_simple_selector()?);
+                } else {
+                    break;
+                }
+                self.consume_whitespace_and_comments();
+            }
+            selectors.push(selector);
+            match self.next_char()? {
                 ',' => {
-                    self.consume_char();
-                    self.consume_whitespace();
+                    self.consume_char()?;
+                    self.consume_whitespace_and_comments();
                 }
                 '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
+                c => return Err(self.error(format!("Unexpected character {} in selector list", c))),
             }
         }
-        // Return selectors with highest specificity first, for use in matching.
+        // Return selectors in order of specificity, lowest first.
         selectors.sort_by_key(|s| s.specificity());
-        selectors
+        Ok(selectors)
     }
 
-    /// Parse one simple selector, e.g.: `type#id.class1.class2.class3`
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
-        let mut selector = SimpleSelector {
-            tag_name: None,
-            id: None,
-            class: Vec::new(),
-        };
-        while !self.eof() {
-            match self.next_char() {
+    /// Parse one simple selector, e.g.: `type#id.class1.class2[attr="value"]`
+    fn parse_simple_selector(&mut self) -> ParseResult<SimpleSelector> {
+        let mut selector = SimpleSelector::default();
+        while let Some(c) = self.peek() {
+            match c {
                 '#' => {
-                    self.consume_char();
+                    self.consume_char()?;
                     selector.id = Some(self.parse_identifier());
                 }
                 '.' => {
-                    self.consume_char();
+                    self.consume_char()?;
                     selector.class.push(self.parse_identifier());
                 }
+                '[' => {
+                    self.consume_char()?;
+                    selector.attributes.push(self.parse_attribute_selector()?);
+                }
+                ':' if self.starts_with("::") => {
+                    self.pos += 2;
+                    selector.pseudo_element = Some(self.parse_pseudo_element()?);
+                }
+                ':' => {
+                    self.consume_char()?;
+                    selector.pseudo_classes.push(self.parse_pseudo_class()?);
+                }
                 '*' => {
                     // universal selector
-                    self.consume_char();
+                    self.consume_char()?;
                 }
                 c if valid_identifier_char(c) => {
                     selector.tag_name = Some(self.parse_identifier());
@@ -155,79 +1146,309 @@ impl Parser {
                 _ => break,
             }
         }
-        selector
+        Ok(selector)
+    }
+
+    /// Parse an attribute selector, after its `[`: a name, and optionally `=` and a quoted or
+    /// unquoted value.
+    fn parse_attribute_selector(&mut self) -> ParseResult<AttributeSelector> {
+        self.consume_whitespace_and_comments();
+        let name = self.parse_identifier().to_ascii_lowercase();
+        self.consume_whitespace_and_comments();
+        let mut value = None;
+        if self.next_char()? == '=' {
+            self.consume_char()?;
+            self.consume_whitespace_and_comments();
+            value = Some(match self.next_char()? {
+                '"' | '\'' => self.parse_quoted()?,
+                _ => self.parse_identifier(),
+            });
+            self.consume_whitespace_and_comments();
+        }
+        self.expect_char(']')?;
+        Ok(AttributeSelector { name, value })
+    }
+
+    /// Parse a pseudo-class, after its `:`.
+    fn parse_pseudo_class(&mut self) -> ParseResult<PseudoClass> {
+        Ok(
+            match self.parse_identifier().to_ascii_lowercase().as_str() {
+                "hover" => PseudoClass::Hover,
+                "active" => PseudoClass::Active,
+                "focus" => PseudoClass::Focus,
+                "not" => {
+                    self.expect_char('(')?;
+                    self.consume_whitespace_and_comments();
+                    let inner = self.parse_simple_selector()?;
+                    self.consume_whitespace_and_comments();
+                    self.expect_char(')')?;
+                    PseudoClass::Not(Box::new(inner))
+                }
+                name => {
+                    let mut name = name.to_string();
+                    // Keep the arguments of a functional one, like `:nth-child(2n)`.
+                    if self.starts_with("(") {
+                        name = format!("{}({})", name, self.consume_parenthesized());
+                    }
+                    PseudoClass::Unsupported(name)
+                }
+            },
+        )
+    }
+
+    /// Parse a pseudo-element, after its `::`.
+    fn parse_pseudo_element(&mut self) -> ParseResult<PseudoElement> {
+        match self.parse_identifier().to_ascii_lowercase().as_str() {
+            "first-line" => Ok(PseudoElement::FirstLine),
+            "first-letter" => Ok(PseudoElement::FirstLetter),
+            "before" => Ok(PseudoElement::Before),
+            "after" => Ok(PseudoElement::After),
+            name => Err(self.error(format!("Unsupported pseudo-element ::{}", name))),
+        }
     }
 
     /// Parse a list of declarations enclosed in `{ ... }`.
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        self.expect_char('{');
+    fn parse_declarations(&mut self) -> ParseResult<Vec<Declaration>> {
+        self.expect_char('{')?;
         let mut declarations = Vec::new();
         loop {
-            self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
+            self.consume_whitespace_and_comments();
+            if self.next_char()? == '}' {
+                self.consume_char()?;
                 break;
             }
-            declarations.push(self.parse_declaration());
+            declarations.push(self.parse_declaration()?);
         }
-        declarations
+        Ok(declarations)
     }
 
-    /// Parse one `<property>: <value>;` declaration.
-    fn parse_declaration(&mut self) -> Declaration {
+    /// Parse one `<property>: <value> [!important];` declaration.
+    fn parse_declaration(&mut self) -> ParseResult<Declaration> {
         let name = self.parse_identifier();
-        self.consume_whitespace();
-        self.expect_char(':');
-        self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
-        self.expect_char(';');
+        self.consume_whitespace_and_comments();
+        self.expect_char(':')?;
+        self.consume_whitespace_and_comments();
+        let value = self.parse_values()?;
+        let important = self.parse_important()?;
+        // The semicolon after the last declaration in a block is optional.
+        if self.next_char()? != '}' {
+            self.expect_char(';')?;
+        }
+
+        Ok(Declaration {
+            name,
+            value,
+            important,
+        })
+    }
 
-        Declaration { name, value }
+    /// Parse an optional `!important` annotation.
+    fn parse_important(&mut self) -> ParseResult<bool> {
+        if self.next_char()? != '!' {
+            return Ok(false);
+        }
+        self.consume_char()?;
+        self.consume_whitespace_and_comments();
+        let keyword = self.parse_identifier();
+        if !keyword.eq_ignore_ascii_case("important") {
+            return Err(self.error(format!(
+                "Expected \"important\" after '!' but found {:?}",
+                keyword
+            )));
+        }
+        self.consume_whitespace_and_comments();
+        Ok(true)
     }
 
     // Methods for parsing values:
 
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
+    /// Parse one or more values, separated by spaces or commas, up to the end of the declaration.
+    fn parse_values(&mut self) -> ParseResult<Value> {
+        let mut groups = Vec::new();
+        let mut values = Vec::new();
+        loop {
+            let start = self.pos;
+            values.push(self.parse_value()?);
+            self.consume_whitespace_and_comments();
+            if self.next_char()? == ',' {
+                self.consume_char()?;
+                self.consume_whitespace_and_comments();
+                groups.push(Parser::space_separated(std::mem::take(&mut values)));
+                continue;
+            }
+            // Stop at the end of the declaration, or at anything that isn't a value.
+            if self.pos == start || matches!(self.next_char()?, ';' | '!' | '}') {
+                break;
+            }
+        }
+        let last = Parser::space_separated(values);
+        if groups.is_empty() {
+            Ok(last)
+        } else {
+            groups.push(last);
+            Ok(Value::CommaList(groups))
+        }
+    }
+
+    /// A single value as itself, or several space-separated ones as a `List`.
+    fn space_separated(mut values: Vec<Value>) -> Value {
+        if values.len() == 1 {
+            values.remove(0)
+        } else {
+            Value::List(values)
+        }
+    }
+
+    fn parse_value(&mut self) -> ParseResult<Value> {
+        match self.next_char()? {
             '0'..='9' => self.parse_length(),
+            '-' if self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                self.parse_length()
+            }
             '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier()),
+            '"' | '\'' => self.parse_string(),
+            // The separator in values like `aspect-ratio: 16 / 9`.
+            '/' => {
+                self.consume_char()?;
+                Ok(Value::Keyword("/".to_string()))
+            }
+            _ => {
+                let identifier = self.parse_identifier();
+                if !self.starts_with("(") {
+                    return Ok(Value::Keyword(identifier));
+                }
+                match identifier.to_ascii_lowercase().as_str() {
+                    "url" => self.parse_url(),
+                    "min" => self.parse_math(MathFunction::Min),
+                    "max" => self.parse_math(MathFunction::Max),
+                    "clamp" => self.parse_math(MathFunction::Clamp),
+                    "rgb" | "rgba" => {
+                        let args = self.parse_arguments()?;
+                        Ok(
+                            rgb_color(&args)
+                                .map_or(Value::Function(identifier, args), Value::Color),
+                        )
+                    }
+                    _ => Ok(Value::Function(identifier, self.parse_arguments()?)),
+                }
+            }
         }
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    /// Parse `(<address>)` after `url`. The address may be quoted.
+    fn parse_url(&mut self) -> ParseResult<Value> {
+        self.expect_char('(')?;
+        self.consume_whitespace_and_comments();
+        let url = match self.next_char()? {
+            '"' | '\'' => self.parse_quoted()?,
+            _ => self.consume_while(|c| c != ')' && !c.is_whitespace()),
+        };
+        self.consume_whitespace_and_comments();
+        self.expect_char(')')?;
+        Ok(Value::Url(url))
     }
 
-    fn parse_float(&mut self) -> f32 {
-        self.consume_while(|c| matches!(c, '0'..='9' | '.'))
-            .parse()
-            .unwrap()
+    /// Parse the comma-separated arguments of a math function like `min`. Arguments may be
+    /// lengths or nested math functions.
+    fn parse_math(&mut self, function: MathFunction) -> ParseResult<Value> {
+        let args = self.parse_arguments()?;
+        if function == MathFunction::Clamp && args.len() != 3 {
+            return Err(self.error(format!(
+                "Expected 3 arguments to clamp() but found {}",
+                args.len()
+            )));
+        }
+        Ok(Value::Math(function, args))
     }
 
-    fn parse_unit(&mut self) -> Unit {
-        match self.parse_identifier().to_ascii_lowercase().as_str() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+    /// Parse the comma-separated arguments of a function, in parentheses.
+    fn parse_arguments(&mut self) -> ParseResult<Vec<Value>> {
+        self.expect_char('(')?;
+        let mut args = Vec::new();
+        loop {
+            self.consume_whitespace_and_comments();
+            args.push(self.parse_value()?);
+            self.consume_whitespace_and_comments();
+            if self.next_char()? != ',' {
+                break;
+            }
+            self.consume_char()?;
         }
+        self.expect_char(')')?;
+        Ok(args)
     }
 
-    fn parse_color(&mut self) -> Value {
-        self.expect_char('#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
+    /// Parse a quoted string. Escapes aren't supported.
+    fn parse_string(&mut self) -> ParseResult<Value> {
+        Ok(Value::Str(self.parse_quoted()?))
+    }
+
+    fn parse_quoted(&mut self) -> ParseResult<String> {
+        let quote = self.consume_char()?;
+        let value = self.consume_while(|c| c != quote);
+        self.expect_char(quote)?;
+        Ok(value)
+    }
+
+    /// Parse a length, or a number if there's no unit.
+    fn parse_length(&mut self) -> ParseResult<Value> {
+        let size = self.parse_float()?;
+        if !self.peek().is_some_and(|c| c == '%' || c.is_alphabetic()) {
+            // A length of zero needs no unit.
+            if size == 0.0 {
+                return Ok(Value::px(0.0));
+            }
+            return Ok(Value::Number(size));
+        }
+        Ok(Value::Length(size, self.parse_unit()?))
+    }
+
+    fn parse_float(&mut self) -> ParseResult<f32> {
+        let sign = if self.starts_with("-") {
+            self.consume_char()?;
+            -1.0
+        } else {
+            1.0
+        };
+        let digits = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
+        match digits.parse::<f32>() {
+            Ok(size) => Ok(sign * size),
+            Err(_) => Err(self.error(format!("Invalid number {:?}", digits))),
+        }
+    }
+
+    fn parse_unit(&mut self) -> ParseResult<Unit> {
+        if self.starts_with("%") {
+            self.consume_char()?;
+            return Ok(Unit::Percent);
+        }
+        match self.parse_identifier().to_ascii_lowercase().as_str() {
+            "px" => Ok(Unit::Px),
+            "em" => Ok(Unit::Em),
+            "rem" => Ok(Unit::Rem),
+            "vw" => Ok(Unit::Vw),
+            "vh" => Ok(Unit::Vh),
+            unit => Err(self.error(format!("Unrecognized unit {:?}", unit))),
+        }
     }
 
-    /// Parse two hexadecimal digits.
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    /// Parse a hex color: `#rgb`, `#rgba`, `#rrggbb`, or `#rrggbbaa`.
+    fn parse_color(&mut self) -> ParseResult<Value> {
+        self.expect_char('#')?;
+        let digits = self.consume_while(|c| c.is_ascii_hexdigit());
+        let digits: String = match digits.len() {
+            // The short forms repeat each digit.
+            3 | 4 => digits.chars().flat_map(|c| [c, c]).collect(),
+            6 | 8 => digits,
+            _ => return Err(self.error(format!("Invalid hex digits {:?}", digits))),
+        };
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+        Ok(Value::Color(Color {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+            a: if digits.len() == 8 { channel(6) } else { 255 },
+        }))
     }
 
     /// Parse a property name or keyword.
@@ -235,6 +1456,21 @@ impl Parser {
         self.consume_while(valid_identifier_char)
     }
 
+    /// Consume and discard whitespace and comments, which may appear anywhere whitespace can. An
+    /// unterminated comment runs to the end of the input.
+    fn consume_whitespace_and_comments(&mut self) {
+        loop {
+            self.consume_whitespace();
+            if !self.starts_with("/*") {
+                break;
+            }
+            self.pos = match self.input[self.pos + 2..].find("*/") {
+                Some(i) => self.pos + 2 + i + 2,
+                None => self.input.len(),
+            };
+        }
+    }
+
     /// Consume and discard zero or more whitespace characters.
     fn consume_whitespace(&mut self) {
         self.consume_while(char::is_whitespace);
@@ -243,34 +1479,46 @@ impl Parser {
     /// Consume characters until `test` returns false.
     fn consume_while(&mut self, test: impl Fn(char) -> bool) -> String {
         let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
+        while let Some(c) = self.peek().filter(|&c| test(c)) {
+            result.push(c);
+            self.pos += c.len_utf8();
         }
         result
     }
 
     /// Return the current character, and advance self.pos to the next character.
-    fn consume_char(&mut self) -> char {
-        let c = self.next_char();
+    fn consume_char(&mut self) -> ParseResult<char> {
+        let c = self.next_char()?;
         self.pos += c.len_utf8();
-        c
+        Ok(c)
     }
 
-    /// If the exact string `s` is found at the current position, consume it.
-    /// Otherwise, panic.
-    fn expect_char(&mut self, c: char) {
-        let cur_c = self.consume_char();
-        if cur_c != c {
-            panic!(
-                "Expected {:?} at byte {} but it was not found. cur_c: {:?}",
-                c, self.pos, cur_c
-            );
+    /// If the character `c` is found at the current position, consume it. Otherwise, fail.
+    fn expect_char(&mut self, c: char) -> ParseResult<()> {
+        match self.peek() {
+            Some(cur_c) if cur_c == c => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(cur_c) => Err(self.error(format!("Expected {:?} but found {:?}", c, cur_c))),
+            None => Err(self.error(format!("Expected {:?} but the input ended", c))),
         }
     }
 
-    /// Read the current character without consuming it.
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+    /// Read the current character without consuming it, or fail at the end of the input.
+    fn next_char(&self) -> ParseResult<char> {
+        self.peek()
+            .ok_or_else(|| self.error("Unexpected end of input".to_string()))
+    }
+
+    /// Read the current character without consuming it, if there is one.
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    /// Do the next characters start with the given string?
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
     }
 
     /// Return true if all input is consumed.
@@ -328,10 +1576,683 @@ mod tests {
         assert_eq!(rule.selectors.len(), 2);
     }
 
+    #[test]
+    fn parse_descendant_selectors() {
+        let stylesheet = parse("div  .menu a, p { color: red; }".to_string());
+        let selectors = &stylesheet.rules[0].selectors;
+        // Sorted by specificity, lowest first.
+        assert_eq!(
+            selectors[0],
+            parse("p { }".to_string()).rules[0].selectors[0]
+        );
+        let Selector::Descendant(ref ancestors, ref a) = selectors[1] else {
+            panic!("Expected a descendant selector");
+        };
+        assert_eq!(a.tag_name.as_deref(), Some("a"));
+        assert_eq!(ancestors.to_string(), "div .menu");
+        assert_eq!(selectors[1].to_string(), "div .menu a");
+        assert_eq!(selectors[1].specificity(), Specificity(0, 1, 2));
+    }
+
+    #[test]
+    fn parse_child_selectors() {
+        let stylesheet = parse("ul>li a , div > p.x { color: red; }".to_string());
+        let selectors = &stylesheet.rules[0].selectors;
+        let Selector::Descendant(ref ancestors, _) = selectors[0] else {
+            panic!("Expected a descendant selector");
+        };
+        assert!(matches!(**ancestors, Selector::Child(..)));
+        assert_eq!(selectors[0].to_string(), "ul > li a");
+        let Selector::Child(ref parent, ref p) = selectors[1] else {
+            panic!("Expected a child selector");
+        };
+        assert_eq!(parent.to_string(), "div");
+        assert_eq!(p.class, ["x"]);
+        assert_eq!(selectors[1].specificity(), Specificity(0, 1, 2));
+    }
+
+    #[test]
+    fn parse_pseudo_classes() {
+        let stylesheet = parse("a:hover, a:active:focus { color: red; }".to_string());
+        let rule = &stylesheet.rules[0];
+        let hover = rule.selectors[0].subject();
+        assert_eq!(hover.tag_name.as_deref(), Some("a"));
+        assert_eq!(hover.pseudo_classes, vec![PseudoClass::Hover]);
+        let active = rule.selectors[1].subject();
+        assert_eq!(
+            active.pseudo_classes,
+            vec![PseudoClass::Active, PseudoClass::Focus]
+        );
+
+        let stylesheet = parse("a:visited, li:nth-child(2n + 1) { color: red; }".to_string());
+        let selectors = &stylesheet.rules[0].selectors;
+        assert_eq!(
+            selectors[0].subject().pseudo_classes,
+            vec![PseudoClass::Unsupported("visited".to_string())]
+        );
+        assert_eq!(selectors[1].to_string(), "li:nth-child(2n + 1)");
+    }
+
+    #[test]
+    fn parse_attribute_selectors() {
+        let stylesheet =
+            parse("input[ TYPE = 'text' ][disabled], [lang=en] { color: red; }".to_string());
+        let selectors = &stylesheet.rules[0].selectors;
+        assert_eq!(
+            selectors[0].subject().attributes,
+            vec![AttributeSelector {
+                name: "lang".to_string(),
+                value: Some("en".to_string())
+            }]
+        );
+        let input = selectors[1].subject();
+        assert_eq!(input.tag_name.as_deref(), Some("input"));
+        assert_eq!(input.attributes[0].name, "type");
+        assert_eq!(input.attributes[0].value.as_deref(), Some("text"));
+        assert_eq!(input.attributes[1].value, None);
+        // Each attribute selector counts as a class.
+        assert_eq!(selectors[1].specificity(), Specificity(0, 2, 1));
+        assert_eq!(selectors[1].to_string(), "input[type=\"text\"][disabled]");
+    }
+
+    #[test]
+    fn parse_grouped_selectors() {
+        let stylesheet =
+            parse("h1, h2 { color: red } , p , , .a[x=','], { margin: 0px; }".to_string());
+        let [first, second] = &stylesheet.rules[..] else {
+            panic!("expected two rules");
+        };
+        let tags: Vec<_> = first
+            .selectors
+            .iter()
+            .map(|s| s.subject().tag_name.as_deref())
+            .collect();
+        assert_eq!(tags, [Some("h1"), Some("h2")]);
+        assert_eq!(first.declarations.len(), 1);
+        // Empty groups and the trailing comma are skipped, and a comma in brackets doesn't split.
+        let selectors: Vec<String> = second.selectors.iter().map(|s| s.to_string()).collect();
+        assert_eq!(selectors, ["p", ".a[x=\",\"]"]);
+    }
+
+    #[test]
+    fn parse_not_pseudo_class() {
+        let stylesheet = parse("div:not(.hidden) { display: block; }".to_string());
+        let selector = stylesheet.rules[0].selectors[0].subject();
+        let inner = SimpleSelector {
+            class: vec!["hidden".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            selector.pseudo_classes,
+            vec![PseudoClass::Not(Box::new(inner))]
+        );
+        // `:not` adds only the specificity of its argument.
+        assert_eq!(
+            stylesheet.rules[0].selectors[0].specificity(),
+            Specificity(0, 1, 1)
+        );
+    }
+
+    #[test]
+    fn parse_style_attribute_declarations() {
+        let declarations = parse_declaration_list(" color: red; margin: 10px !important ");
+        assert_eq!(declarations.len(), 2);
+        assert_eq!(declarations[0].name, "color");
+        assert_eq!(declarations[1].value, Value::px(10.0));
+        assert!(declarations[1].important);
+        assert_eq!(parse_declaration_list("color: red;").len(), 1);
+        assert!(parse_declaration_list(" ; ").is_empty());
+    }
+
+    #[test]
+    fn parse_important_declarations() {
+        let source = "div { color: red !important; margin: 10px; }".to_string();
+        let stylesheet = parse(source);
+        let declarations = &stylesheet.rules[0].declarations;
+        assert!(declarations[0].important);
+        assert_eq!(declarations[0].value, Value::Keyword("red".to_string()));
+        assert!(!declarations[1].important);
+
+        let stylesheet =
+            parse("div { color: red ! important; margin: 1px!IMPORTANT; }".to_string());
+        let declarations = &stylesheet.rules[0].declarations;
+        assert!(declarations.iter().all(|declaration| declaration.important));
+        assert_eq!(declarations[0].value, Value::Keyword("red".to_string()));
+    }
+
+    #[test]
+    fn unknown_at_rules_are_skipped() {
+        let source = r#"@charset "utf-8"; @font-face { font-family: "a;}"; src: url(a) }
+            p { color: red; } @page { @top-left { content: "x" } } div { color: blue; }"#;
+        let stylesheet = parse_stylesheet_str(source);
+        assert_eq!(stylesheet.rules.len(), 2);
+        assert_eq!(
+            stylesheet.rules[0].declarations[0].value,
+            Value::Keyword("red".to_string())
+        );
+        assert_eq!(
+            stylesheet.rules[1].declarations[0].value,
+            Value::Keyword("blue".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_multibyte_values() {
+        let stylesheet =
+            parse(r#"p { font-family: "café 😀", serif; color: #00ff00; }"#.to_string());
+        let declarations = &stylesheet.rules[0].declarations;
+        assert_eq!(
+            declarations[0].value,
+            Value::CommaList(vec![
+                Value::Str("café 😀".to_string()),
+                Value::Keyword("serif".to_string()),
+            ])
+        );
+        assert_eq!(
+            declarations[1].value.to_color(),
+            Some(Color::rgb(0, 255, 0))
+        );
+    }
+
+    #[test]
+    fn hex_rgb_and_named_colors() {
+        let color = |source: &str| parse_value_str(source).to_color();
+        let red = Some(Color::rgb(255, 0, 0));
+        for source in [
+            "#ff0000",
+            "#F00",
+            "rgb(255,0,0)",
+            "rgba(255, 0, 0, 1)",
+            "RED",
+        ] {
+            assert_eq!(color(source), red, "{}", source);
+        }
+        assert_eq!(
+            parse_value_str("rgb(100%, 0%, 0%)"),
+            Value::Color(Color::rgb(255, 0, 0))
+        );
+
+        let translucent = Color {
+            a: 128,
+            ..Color::rgb(0, 255, 0)
+        };
+        assert_eq!(color("rgba(0, 255, 0, 0.5)"), Some(translucent));
+        assert_eq!(color("#00ff0080"), Some(translucent));
+        assert_eq!(
+            color("#0f08"),
+            Some(Color {
+                a: 136,
+                ..translucent
+            })
+        );
+        // Anything else is left for the property to make sense of.
+        assert_eq!(color("rgb(1, 2)"), None);
+        assert_eq!(color("nocolor"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid hex digits")]
+    fn multibyte_hex_digit_is_rejected_cleanly() {
+        parse("p { color: #aé0000; }".to_string());
+    }
+
+    #[test]
+    fn parse_numbers() {
+        let stylesheet = parse("a { z-index: 2; margin: -3px; b: -1; }".to_string());
+        let values: Vec<Value> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|declaration| declaration.value.clone())
+            .collect();
+        assert_eq!(
+            values,
+            [Value::Number(2.0), Value::px(-3.0), Value::Number(-1.0)]
+        );
+    }
+
+    #[test]
+    fn parse_url_values() {
+        let stylesheet = parse(
+            r#"a { background-image: url("x.png"); } b { background-image: URL( y.png ); }"#
+                .to_string(),
+        );
+        let value = |i: usize| stylesheet.rules[i].declarations[0].value.clone();
+        assert_eq!(value(0), Value::Url("x.png".to_string()));
+        assert_eq!(value(1), Value::Url("y.png".to_string()));
+    }
+
+    #[test]
+    fn parse_keyframes_stops() {
+        let stylesheet = parse(
+            "@keyframes fade { from { color: red; } 50%, 75% { width: 1px; } to { color: blue; } } \
+             p { color: green; }"
+                .to_string(),
+        );
+        assert_eq!(stylesheet.rules.len(), 1);
+        assert_eq!(stylesheet.keyframes.len(), 1);
+        let keyframes = &stylesheet.keyframes[0];
+        assert_eq!(keyframes.name, "fade");
+        let stops: Vec<(f32, &str, Value)> = keyframes
+            .stops
+            .iter()
+            .map(|stop| {
+                let declaration = &stop.declarations[0];
+                (
+                    stop.percent,
+                    declaration.name.as_str(),
+                    declaration.value.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                (0.0, "color", Value::Keyword("red".to_string())),
+                (50.0, "width", Value::px(1.0)),
+                (75.0, "width", Value::px(1.0)),
+                (100.0, "color", Value::Keyword("blue".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_may_appear_wherever_whitespace_can() {
+        let source =
+            "/* head */ p/* a */,/* b */div /* c */{/* d */color/* e */:/* f */red/* g */; \
+                      width: 1px /* h */ 2px;/* i */}/* j */ a { } /* unterminated";
+        let stylesheet = parse(source.to_string());
+        assert_eq!(stylesheet.rules.len(), 2);
+        let rule = &stylesheet.rules[0];
+        assert_eq!(rule.selectors.len(), 2);
+        assert_eq!(rule.declarations.len(), 2);
+        assert_eq!(rule.declarations[0].name, "color");
+        assert_eq!(
+            rule.declarations[0].value,
+            Value::Keyword("red".to_string())
+        );
+        assert_eq!(
+            rule.declarations[1].value,
+            Value::List(vec![Value::px(1.0), Value::px(2.0)])
+        );
+    }
+
+    #[test]
+    fn comments_around_a_rule_are_skipped() {
+        let stylesheet = parse("/* header */ h1 { color: red } /* done */".to_string());
+        assert_eq!(stylesheet.rules.len(), 1);
+        let declarations = &stylesheet.rules[0].declarations;
+        assert_eq!(declarations.len(), 1);
+        assert_eq!(declarations[0].name, "color");
+        assert_eq!(declarations[0].value, Value::Keyword("red".to_string()));
+    }
+
+    #[test]
+    fn stylesheet_round_trips_through_css() {
+        let source = r#"
+            * { margin: 0px; }
+            h1, div#main.note:hover, p:not(.intro)::before {
+                color: #ff8000 !important;
+                width: min(50%, 300px);
+                content: counter(section) ". ";
+            }
+            /* A comment that isn't kept. */
+            body { font-family: "Times New Roman", serif; background-image: url("a b.png"); }
+            img { aspect-ratio: 16 / 9; box-shadow: 4px -2.5px 1em black; z-index: 2; }
+            @media (max-width: 600px) { div { display: none; } p { quotes: '"' "'"; } }
+            @keyframes fade { from { opacity: 0; } 50%, to { opacity: 1; } }
+        "#;
+        let stylesheet = parse(source.to_string());
+        let css = stylesheet.to_css();
+        assert_eq!(parse(css.clone()), stylesheet);
+        assert!(css.contains("color: #ff8000 !important;"));
+        assert!(css.contains("@media (max-width: 600px) {\n  div { display: none; }\n"));
+        // Writing the stylesheet out again gives the same CSS.
+        assert_eq!(parse(css.clone()).to_css(), css);
+    }
+
+    #[test]
+    fn parse_media_rules() {
+        let source = "p { color: red; } @media (max-width: 600px) { p { color: blue; } div { color: green; } }".to_string();
+        let stylesheet = parse(source);
+        assert_eq!(stylesheet.rules.len(), 3);
+        assert_eq!(stylesheet.rules[0].media, None);
+        assert_eq!(stylesheet.rules[1].media, Some(MediaQuery::MaxWidth(600.0)));
+        assert_eq!(stylesheet.rules[2].media, Some(MediaQuery::MaxWidth(600.0)));
+        assert!(MediaQuery::MaxWidth(600.0).matches(500.0));
+        assert!(!MediaQuery::MinWidth(600.0).matches(500.0));
+    }
+
+    #[test]
+    fn media_query_ems_are_of_the_default_font_size() {
+        let stylesheet = parse("@media (min-width: 60em) { p { color: blue; } }".to_string());
+        assert_eq!(stylesheet.rules[0].media, Some(MediaQuery::MinWidth(960.0)));
+        assert!(!MediaQuery::MinWidth(960.0).matches(800.0));
+        let stylesheet = parse("@media (max-width: 40rem) { p { color: blue; } }".to_string());
+        assert_eq!(stylesheet.rules[0].media, Some(MediaQuery::MaxWidth(640.0)));
+    }
+
+    #[test]
+    fn parse_space_separated_values() {
+        let stylesheet = parse("div { box-shadow: 4px 4px black; margin: 0px; }".to_string());
+        let declarations = &stylesheet.rules[0].declarations;
+        assert_eq!(
+            declarations[0].value,
+            Value::List(vec![
+                Value::Length(4.0, Unit::Px),
+                Value::Length(4.0, Unit::Px),
+                Value::Keyword("black".to_string()),
+            ])
+        );
+        assert_eq!(declarations[1].value, Value::Length(0.0, Unit::Px));
+    }
+
+    #[test]
+    fn resolve_lengths_through_context() {
+        let ctx = ResolutionContext {
+            font_size: 20.0,
+            root_font_size: 16.0,
+            viewport: (800.0, 600.0),
+            containing_width: 400.0,
+            containing_height: Some(300.0),
+        };
+        let stylesheet =
+            parse("div { a: 10px; b: 2em; c: 1.5rem; d: 25%; e: 10vw; f: 50vh; }".to_string());
+        let resolved: Vec<f32> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| d.value.resolve(&ctx))
+            .collect();
+        assert_eq!(resolved, vec![10.0, 40.0, 24.0, 100.0, 80.0, 300.0]);
+        assert_eq!(
+            Value::Length(25.0, Unit::Percent).resolve_height(&ctx),
+            75.0
+        );
+        assert_eq!(Value::Length(2.0, Unit::Em).to_px(), 0.0);
+        let red = Value::Color(Color::rgb(255, 0, 0));
+        assert!(!red.is_length());
+        assert_eq!((red.to_px(), red.resolve(&ctx)), (0.0, 0.0));
+        assert!(Value::Length(25.0, Unit::Percent).is_length());
+    }
+
+    #[test]
+    fn to_px_is_zero_for_anything_but_px() {
+        let red = Value::Color(Color::rgb(255, 0, 0));
+        let auto = Value::Keyword("auto".to_string());
+        let half = Value::Length(50.0, Unit::Percent);
+        for value in [&red, &auto, &half, &Value::Length(1.0, Unit::Vw)] {
+            assert_eq!(value.to_px(), 0.0);
+        }
+        assert_eq!(Value::px(12.5).to_px(), 12.5);
+
+        // Percentages resolve once there's something to take them of.
+        assert_eq!(half.to_px_with(300.0), 150.0);
+        assert_eq!(Value::px(12.5).to_px_with(300.0), 12.5);
+        let min = Value::Math(MathFunction::Min, vec![half.clone(), Value::px(100.0)]);
+        assert_eq!(min.to_px_with(300.0), 100.0);
+        assert_eq!((red.to_px_with(300.0), auto.to_px_with(300.0)), (0.0, 0.0));
+        assert_eq!(Value::Length(2.0, Unit::Em).to_px_with(300.0), 0.0);
+    }
+
+    #[test]
+    fn parse_and_resolve_math_functions() {
+        let stylesheet = parse(
+            "div { width: min(50%, 300px); height: clamp(1em, max(10%, 5px), 20px); }".to_string(),
+        );
+        let width = &stylesheet.rules[0].declarations[0].value;
+        assert_eq!(
+            *width,
+            Value::Math(
+                MathFunction::Min,
+                vec![Value::Length(50.0, Unit::Percent), Value::px(300.0)]
+            )
+        );
+        let height = &stylesheet.rules[0].declarations[1].value;
+        let ctx = |containing_width| ResolutionContext {
+            font_size: 16.0,
+            containing_width,
+            ..Default::default()
+        };
+        assert_eq!(width.resolve(&ctx(400.0)), 200.0);
+        assert_eq!(width.resolve(&ctx(800.0)), 300.0);
+        assert_eq!(height.resolve(&ctx(100.0)), 16.0);
+        assert_eq!(height.resolve(&ctx(190.0)), 19.0);
+        assert_eq!(height.resolve(&ctx(1000.0)), 20.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected 3 arguments to clamp()")]
+    fn clamp_needs_three_arguments() {
+        parse("div { width: clamp(1px, 2px); }".to_string());
+    }
+
+    #[test]
+    fn specificity_ordering() {
+        assert!(Specificity(1, 0, 0) > Specificity(0, 9, 9));
+        assert!(Specificity(0, 1, 0) > Specificity(0, 0, 9));
+        let stylesheet = parse("div.a#b { } div.a#b { } p { }".to_string());
+        let specificities: Vec<Specificity> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| Specificity::from_selector(&rule.selectors[0]))
+            .collect();
+        assert_eq!(specificities[0], specificities[1]);
+        assert_eq!(specificities[0], Specificity(1, 1, 1));
+        assert_eq!(specificities[2], Specificity(0, 0, 1));
+    }
+
+    #[test]
+    fn compute_specificity_counts_ids_classes_and_tags() {
+        let specificity = |source: &str| {
+            let stylesheet = parse(format!("{} {{ }}", source));
+            compute_specificity(stylesheet.rules[0].selectors[0].subject())
+        };
+        assert_eq!(specificity("div"), Specificity(0, 0, 1));
+        assert_eq!(specificity(".foo"), Specificity(0, 1, 0));
+        assert_eq!(specificity("#bar"), Specificity(1, 0, 0));
+        assert_eq!(specificity("div.foo#bar"), Specificity(1, 1, 1));
+        assert_eq!(specificity("div.a.b"), Specificity(0, 2, 1));
+        assert_eq!(specificity("*"), Specificity(0, 0, 0));
+        assert_eq!(specificity("a:hover"), Specificity(0, 1, 1));
+        assert_eq!(specificity("a:not(#x)"), Specificity(1, 0, 1));
+        assert_eq!(specificity("p::first-line"), Specificity(0, 0, 2));
+    }
+
+    #[test]
+    fn px_length_arithmetic_and_comparison() {
+        let (a, b) = (Value::px(10.0), Value::px(4.0));
+        assert_eq!(&a + &b, Value::px(14.0));
+        assert_eq!(&a - &b, Value::px(6.0));
+        assert_eq!(a.checked_add(&b), Some(Value::px(14.0)));
+        assert_eq!(b.checked_sub(&a), Some(Value::px(-6.0)));
+        assert!(a > b);
+        assert!(a >= Value::px(10.0));
+        assert_eq!(b.partial_cmp(&a), Some(Ordering::Less));
+
+        let auto = Value::Keyword("auto".to_string());
+        let em = Value::Length(2.0, Unit::Em);
+        assert_eq!(&a + &auto, a);
+        assert_eq!(&em - &b, Value::px(-4.0));
+        assert_eq!(a.checked_add(&auto), None);
+        assert_eq!(em.checked_sub(&b), None);
+        assert_eq!(a.partial_cmp(&auto), None);
+        assert_eq!(auto.partial_cmp(&auto.clone()), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn validate_flags_unknown_properties_bad_values_and_empty_rules() {
+        let stylesheet = parse(
+            "p { colour: red; color: 10px; width: auto; } div { } a { background: #ff0000; }"
+                .to_string(),
+        );
+        assert_eq!(
+            validate(&stylesheet),
+            [
+                StyleWarning::UnknownProperty {
+                    rule: 0,
+                    name: "colour".to_string()
+                },
+                StyleWarning::InvalidValue {
+                    rule: 0,
+                    name: "color".to_string(),
+                    value: Value::px(10.0)
+                },
+                StyleWarning::EmptyRule { rule: 1 },
+            ]
+        );
+        assert_eq!(
+            validate(&stylesheet)[1].to_string(),
+            "rule 0: invalid value Length(10.0, Px) for \"color\""
+        );
+        // Box shorthands take one to four lengths, and lengths only the keywords their property
+        // does.
+        let stylesheet = parse(
+            "p { margin: 10px 20px; padding: 0 1px; border-width: 1px 2px; margin-left: auto; \
+             width: red; padding-top: auto; margin: 1px 2px 3px 4px 5px; }"
+                .to_string(),
+        );
+        let invalid: Vec<String> = validate(&stylesheet)
+            .into_iter()
+            .map(|warning| match warning {
+                StyleWarning::InvalidValue { name, .. } => name,
+                warning => panic!("unexpected warning {}", warning),
+            })
+            .collect();
+        assert_eq!(invalid, ["width", "padding-top", "margin"]);
+    }
+
+    #[test]
+    fn supports_rules_apply_only_if_supported() {
+        let stylesheet = parse(
+            "@supports (display: flex) { .flex { display: flex; } } \
+             @supports (display: grid) { .grid { display: grid; } } \
+             @supports not (display: grid) { .fallback { display: block; } } \
+             @supports (colour: red) { .typo { color: red; } } \
+             @supports (margin: 1px 2px) { .box { margin: 1px 2px; } } \
+             @supports (width: red) { .red { width: red; } }"
+                .to_string(),
+        );
+        let selectors: Vec<&str> = stylesheet
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.selectors)
+            .map(|selector| selector.subject().class[0].as_str())
+            .collect();
+        assert_eq!(selectors, ["flex", "fallback", "box"]);
+    }
+
+    #[test]
+    fn supports_conditions_combine_and_nest() {
+        let stylesheet = parse(
+            "@supports (display: flex) and (color: red) { .and { color: red; } } \
+             @supports (display: flex) and (display: grid) { .and-grid { color: red; } } \
+             @supports (display: grid) or (display: flex) { .or { color: red; } } \
+             @supports ((display: flex) and (not (display: grid))) { .nested { color: red; } } \
+             @supports (color: rgb(0, 128, 0)) { .rgb { color: red; } } \
+             @supports selector(a > b) { .selector { color: red; } } \
+             @supports (display: flex) { .after { color: red; } }"
+                .to_string(),
+        );
+        let selectors: Vec<&str> = stylesheet
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.selectors)
+            .map(|selector| selector.subject().class[0].as_str())
+            .collect();
+        assert_eq!(selectors, ["and", "or", "nested", "rgb", "after"]);
+    }
+
+    #[test]
+    fn validate_accepts_clean_stylesheet() {
+        let stylesheet = parse(
+            "p { color: red; margin: 0px; font-size: 2em; display: block; } \
+             div { width: auto; color: inherit; border-color: #000000; }"
+                .to_string(),
+        );
+        assert!(validate(&stylesheet).is_empty());
+        assert!(validate(&crate::style::user_agent_stylesheet()).is_empty());
+    }
+
+    #[test]
+    fn bare_zero_is_a_length() {
+        let stylesheet = parse(
+            "p { margin: 0; height: 0; z-index: 0; color: rgb(0, 0, 0); line-height: 1.5; }"
+                .to_string(),
+        );
+        let values: Vec<&Value> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| &d.value)
+            .collect();
+        assert_eq!(values[0], &Value::px(0.0));
+        assert!(values[1].is_length());
+        assert_eq!(values[2].to_number(), Some(0.0));
+        assert_eq!(values[3].to_color(), Some(Color::rgb(0, 0, 0)));
+        // Other unitless numbers stay numbers.
+        assert_eq!(values[4], &Value::Number(1.5));
+        assert!(validate(&stylesheet).is_empty());
+    }
+
+    #[test]
+    fn keywords_compare_case_insensitively() {
+        let value = Value::Keyword("AUTO".to_string());
+        assert!(value.keyword_eq("auto"));
+        assert!(!value.keyword_eq("none"));
+        assert!(!Value::Length(0.0, Unit::Px).keyword_eq("auto"));
+        assert_eq!(
+            Value::Keyword("Red".to_string()).to_color(),
+            Some(Color::rgb(255, 0, 0))
+        );
+    }
+
+    #[test]
+    fn parse_comma_separated_values() {
+        let stylesheet =
+            parse("p { font-family: \"Times New Roman\", Open Sans, serif; }".to_string());
+        assert_eq!(
+            stylesheet.rules[0].declarations[0].value,
+            Value::CommaList(vec![
+                Value::Str("Times New Roman".to_string()),
+                Value::List(vec![
+                    Value::Keyword("Open".to_string()),
+                    Value::Keyword("Sans".to_string()),
+                ]),
+                Value::Keyword("serif".to_string()),
+            ])
+        );
+    }
+
     #[test]
     fn parse_invalid_syntax() {
         let source = "div { color: red".to_string();
         let result = std::panic::catch_unwind(|| parse(source));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn try_parse_says_where_and_why_parsing_failed() {
+        let error = |source: &str| try_parse(source.to_string()).unwrap_err().to_string();
+        assert_eq!(
+            error("a { color"),
+            "Expected ':' but the input ended at byte 9"
+        );
+        assert_eq!(
+            error("a { color: red"),
+            "Unexpected end of input at byte 14"
+        );
+        assert_eq!(
+            error("a { width: 10qq; }"),
+            "Unrecognized unit \"qq\" at byte 15"
+        );
+        assert_eq!(
+            error("a::marker {}"),
+            "Unsupported pseudo-element ::marker at byte 9"
+        );
+        assert_eq!(
+            try_parse_declaration_list("color: #12"),
+            Err(CssParseError {
+                offset: 10,
+                message: "Invalid hex digits \"12\"".to_string()
+            })
+        );
+    }
 }
@@ -1,7 +1,9 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 
-pub type AttrMap = HashMap<String, String>;
+// Attributes are kept sorted by name, so that iterating over them is deterministic.
+pub type AttrMap = BTreeMap<String, String>;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Node {
     // data common to all nodes
     pub children: Vec<Node>,
@@ -10,6 +12,13 @@ pub struct Node {
     pub node_type: NodeType,
 }
 
+/// Options for `Node::to_html_with`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SerializeOptions {
+    /// Write attributes in the order they appeared in the source instead of by name.
+    pub source_order: bool,
+}
+
 impl Node {
     pub fn new(node_type: NodeType) -> Node {
         Node {
@@ -18,19 +27,274 @@ impl Node {
         }
     }
 
+    /// Start building an element with the given tag name.
+    pub fn element(tag_name: &str) -> ElementBuilder {
+        ElementBuilder {
+            tag_name: tag_name.to_string(),
+            attributes: AttrMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// A copy of this node without its children, like DOM `cloneNode(false)`.
+    pub fn clone_shallow(&self) -> Node {
+        Node::new(self.node_type.clone())
+    }
+
+    /// A copy of this node and all its descendants, like DOM `cloneNode(true)`.
+    pub fn clone_deep(&self) -> Node {
+        self.clone()
+    }
+
     pub fn append_child(&mut self, node: Node) {
         self.children.push(node);
     }
+
+    /// Replace the child at `index` with `node` and return the old child, or return `None` and
+    /// change nothing if `index` is out of range.
+    pub fn replace_child(&mut self, index: usize, node: Node) -> Option<Node> {
+        let child = self.children.get_mut(index)?;
+        Some(std::mem::replace(child, node))
+    }
+
+    /// The child at `index`, or `None` if out of range.
+    pub fn child(&self, index: usize) -> Option<&Node> {
+        self.children.get(index)
+    }
+
+    pub fn first_child(&self) -> Option<&Node> {
+        self.children.first()
+    }
+
+    pub fn last_child(&self) -> Option<&Node> {
+        self.children.last()
+    }
+
+    /// The first element in this subtree, including this node, whose `id` is `id`.
+    pub fn get_element_by_id(&self, id: &str) -> Option<&Node> {
+        self.find_elements(&|elem| elem.id().is_some_and(|own| own == id))
+            .into_iter()
+            .next()
+    }
+
+    /// The elements in this subtree, including this node, that have class `class`, in document
+    /// order.
+    pub fn get_elements_by_class(&self, class: &str) -> Vec<&Node> {
+        self.find_elements(&|elem| elem.classes().contains(class))
+    }
+
+    /// The elements in this subtree, including this node, with tag name `tag` (in any case), in
+    /// document order.
+    pub fn get_elements_by_tag(&self, tag: &str) -> Vec<&Node> {
+        self.find_elements(&|elem| elem.tag_name.eq_ignore_ascii_case(tag))
+    }
+
+    // The elements in this subtree that pass `test`, in document order.
+    fn find_elements(&self, test: &dyn Fn(&ElementData) -> bool) -> Vec<&Node> {
+        let mut found = Vec::new();
+        self.collect_elements(test, &mut found);
+        found
+    }
+
+    fn collect_elements<'a>(
+        &'a self,
+        test: &dyn Fn(&ElementData) -> bool,
+        found: &mut Vec<&'a Node>,
+    ) {
+        if self.as_element().is_some_and(test) {
+            found.push(self);
+        }
+        for child in &self.children {
+            child.collect_elements(test, found);
+        }
+    }
+
+    /// Serialize this node and its descendants as HTML. Attributes are written in name order.
+    pub fn to_html(&self) -> String {
+        self.to_html_with(SerializeOptions::default())
+    }
+
+    /// Serialize this node, including its own tags, and its descendants. The same as `to_html`.
+    pub fn outer_html(&self) -> String {
+        self.to_html()
+    }
+
+    /// Serialize this node's children, without its own tags.
+    pub fn inner_html(&self) -> String {
+        let mut html = String::new();
+        for child in &self.children {
+            child.write_html(&mut html, SerializeOptions::default());
+        }
+        html
+    }
+
+    /// Serialize this node and its descendants as HTML, with the given options.
+    pub fn to_html_with(&self, options: SerializeOptions) -> String {
+        let mut html = String::new();
+        self.write_html(&mut html, options);
+        html
+    }
+
+    fn write_html(&self, html: &mut String, options: SerializeOptions) {
+        match self.node_type {
+            NodeType::Text(ref text) => html.push_str(&escape(text)),
+            NodeType::Comment(ref text) => html.push_str(&format!("<!--{}-->", text)),
+            NodeType::Element(ref elem) => {
+                html.push('<');
+                html.push_str(&elem.tag_name);
+                let names: Vec<&String> = if options.source_order {
+                    elem.attributes_in_source_order()
+                } else {
+                    elem.attributes.keys().collect()
+                };
+                for name in names {
+                    let value = &elem.attributes[name];
+                    html.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+                }
+                html.push('>');
+                if is_void_element(&elem.tag_name) && self.children.is_empty() {
+                    return;
+                }
+                for child in &self.children {
+                    child.write_html(html, options);
+                }
+                html.push_str(&format!("</{}>", elem.tag_name));
+            }
+        }
+    }
+
+    /// An outline of this node and its descendants for debugging, one node per line, indented
+    /// two spaces per level starting at `indent` levels. Elements with children are closed on a
+    /// line of their own; attributes are written in name order.
+    pub fn pretty_print(&self, indent: usize) -> String {
+        let mut out = String::new();
+        self.write_outline(&mut out, indent);
+        out
+    }
+
+    fn write_outline(&self, out: &mut String, indent: usize) {
+        let prefix = "  ".repeat(indent);
+        match self.node_type {
+            NodeType::Text(ref text) => out.push_str(&format!("{}{}\n", prefix, text)),
+            NodeType::Comment(ref text) => out.push_str(&format!("{}<!--{}-->\n", prefix, text)),
+            NodeType::Element(ref elem) => {
+                out.push_str(&format!("{}<{}", prefix, elem.tag_name));
+                for (name, value) in &elem.attributes {
+                    out.push_str(&format!(" {}=\"{}\"", name, value));
+                }
+                out.push_str(">\n");
+                if self.children.is_empty() {
+                    return;
+                }
+                for child in &self.children {
+                    child.write_outline(out, indent + 1);
+                }
+                out.push_str(&format!("{}</{}>\n", prefix, elem.tag_name));
+            }
+        }
+    }
+
+    /// The element data of this node, or `None` if it's text or a comment.
+    pub fn as_element(&self) -> Option<&ElementData> {
+        match self.node_type {
+            NodeType::Element(ref elem) => Some(elem),
+            NodeType::Text(_) | NodeType::Comment(_) => None,
+        }
+    }
+
+    /// The text of this node, or `None` if it's an element or a comment.
+    pub fn as_text(&self) -> Option<&str> {
+        match self.node_type {
+            NodeType::Text(ref text) => Some(text),
+            NodeType::Element(_) | NodeType::Comment(_) => None,
+        }
+    }
+
+    /// Is this node an element?
+    pub fn is_element(&self) -> bool {
+        self.node_type.is_element()
+    }
+
+    /// Is this node text?
+    pub fn is_text(&self) -> bool {
+        self.node_type.is_text()
+    }
+}
+
+/// A builder for element nodes, from `Node::element`.
+pub struct ElementBuilder {
+    tag_name: String,
+    attributes: AttrMap,
+    children: Vec<Node>,
 }
 
+impl ElementBuilder {
+    /// Set an attribute, replacing any previous value.
+    pub fn attr(mut self, name: &str, value: &str) -> ElementBuilder {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Add a class to the `class` attribute.
+    pub fn class(mut self, class: &str) -> ElementBuilder {
+        let classes = self.attributes.entry("class".to_string()).or_default();
+        if !classes.is_empty() {
+            classes.push(' ');
+        }
+        classes.push_str(class);
+        self
+    }
+
+    /// Append a child node.
+    pub fn child(mut self, node: Node) -> ElementBuilder {
+        self.children.push(node);
+        self
+    }
+
+    /// Append a text child.
+    pub fn text(self, data: &str) -> ElementBuilder {
+        self.child(text(data.to_string()))
+    }
+
+    pub fn build(self) -> Node {
+        elem(self.tag_name, self.attributes, self.children)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum NodeType {
     Text(String),
     Element(ElementData),
+    // The text of a comment, without the `<!--` and `-->` delimiters.
+    Comment(String),
+}
+
+impl NodeType {
+    pub fn is_element(&self) -> bool {
+        matches!(self, NodeType::Element(_))
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, NodeType::Text(_))
+    }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct ElementData {
     pub tag_name: String,
     pub attributes: AttrMap,
+    // Attribute names in the order they appeared in the source, if known.
+    pub attribute_order: Vec<String>,
+    pub state: ElementState,
+}
+
+/// Interaction state of an element, for matching dynamic pseudo-classes like `:hover`. There is
+/// no user interaction, so this is empty unless set by the caller.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ElementState {
+    pub hover: bool,
+    pub active: bool,
+    pub focus: bool,
 }
 
 impl ElementData {
@@ -38,6 +302,8 @@ impl ElementData {
         ElementData {
             tag_name: tag_name.to_string(),
             attributes,
+            attribute_order: Vec::new(),
+            state: ElementState::default(),
         }
     }
 
@@ -45,6 +311,32 @@ impl ElementData {
         self.attributes.get("id")
     }
 
+    /// The `lang` attribute, lowercased.
+    pub fn lang(&self) -> Option<String> {
+        self.attributes.get("lang").map(|s| s.trim().to_lowercase())
+    }
+
+    /// The `dir` attribute, lowercased.
+    pub fn dir(&self) -> Option<String> {
+        self.attributes.get("dir").map(|s| s.trim().to_lowercase())
+    }
+
+    /// Attribute names in source order. Attributes with no recorded position (e.g. added after
+    /// parsing) follow in name order.
+    pub fn attributes_in_source_order(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self
+            .attribute_order
+            .iter()
+            .filter(|name| self.attributes.contains_key(*name))
+            .collect();
+        for name in self.attributes.keys() {
+            if !self.attribute_order.contains(name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
     pub fn classes(&self) -> HashSet<&str> {
         match self.attributes.get("class") {
             Some(classlist) => classlist.split(' ').collect(),
@@ -53,6 +345,28 @@ impl ElementData {
     }
 }
 
+/// Is `tag_name` a void element, like `br`, which has no contents and no closing tag?
+pub fn is_void_element(tag_name: &str) -> bool {
+    const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "input", "link", "meta"];
+    VOID_ELEMENTS
+        .iter()
+        .any(|void| void.eq_ignore_ascii_case(tag_name))
+}
+
+/// Serialize `node` and its descendants as HTML, with attributes in name order. The same as
+/// `node.to_html()`.
+pub fn serialize(node: &Node) -> String {
+    node.to_html()
+}
+
+// Escape the characters that can't appear literally in text or quoted attribute values.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 pub fn text(data: String) -> Node {
     Node {
         children: Vec::new(),
@@ -60,12 +374,233 @@ pub fn text(data: String) -> Node {
     }
 }
 
+pub fn comment(data: String) -> Node {
+    Node {
+        children: Vec::new(),
+        node_type: NodeType::Comment(data),
+    }
+}
+
 pub fn elem(tag_name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
     Node {
         children,
         node_type: NodeType::Element(ElementData {
             tag_name,
             attributes: attrs,
+            attribute_order: Vec::new(),
+            state: ElementState::default(),
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shallow_and_deep_clones() {
+        let template = crate::html::parse(
+            "<ul class=\"menu\"><li>one</li><li>two <b>2</b></li></ul>".to_string(),
+        );
+        let shallow = template.clone_shallow();
+        assert!(shallow.children.is_empty());
+        assert_eq!(shallow.node_type, template.node_type);
+        assert_eq!(shallow.to_html(), "<ul class=\"menu\"></ul>");
+
+        let mut deep = template.clone_deep();
+        assert_eq!(deep, template);
+        // The copy is independent of the original.
+        deep.children[0].append_child(Node::new(NodeType::Text("!".to_string())));
+        assert_ne!(deep, template);
+    }
+
+    #[test]
+    fn child_accessors() {
+        let mut root = elem("div".to_string(), AttrMap::new(), vec![]);
+        assert!(root.first_child().is_none());
+        assert!(root.last_child().is_none());
+        assert!(root.child(0).is_none());
+
+        root.append_child(text("a".to_string()));
+        root.append_child(text("b".to_string()));
+        let text_of = |node: Option<&Node>| node.and_then(Node::as_text).unwrap().to_string();
+        assert_eq!(text_of(root.first_child()), "a");
+        assert_eq!(text_of(root.last_child()), "b");
+        assert_eq!(text_of(root.child(1)), "b");
+        assert!(root.child(2).is_none());
+    }
+
+    #[test]
+    fn replace_child_swaps_in_place() {
+        let mut root = crate::html::parse("<div><a></a><b></b><i></i></div>".to_string());
+        let old = root.replace_child(1, text("new".to_string()));
+        assert_eq!(old.unwrap().outer_html(), "<b></b>");
+        assert_eq!(root.inner_html(), "<a></a>new<i></i>");
+
+        let unused = text("unused".to_string());
+        assert!(root.replace_child(3, unused).is_none());
+        assert_eq!(root.inner_html(), "<a></a>new<i></i>");
+    }
+
+    #[test]
+    fn downcast_helpers() {
+        let element = elem("p".to_string(), AttrMap::new(), vec![]);
+        assert!(element.node_type.is_element());
+        assert!(!element.node_type.is_text());
+        assert_eq!(element.as_element().map(|e| e.tag_name.as_str()), Some("p"));
+        assert!(element.as_text().is_none());
+
+        let text_node = text("hi".to_string());
+        assert!(text_node.node_type.is_text());
+        assert!(!text_node.node_type.is_element());
+        assert_eq!(text_node.as_text(), Some("hi"));
+        assert!(text_node.as_element().is_none());
+
+        let comment_node = comment("note".to_string());
+        assert!(!comment_node.is_element() && !comment_node.is_text());
+        assert_eq!(comment_node.as_text(), None);
+        assert!(comment_node.as_element().is_none());
+        assert!(element.is_element() && !element.is_text());
+        assert!(text_node.is_text() && !text_node.is_element());
+    }
+
+    #[test]
+    fn to_html_orders_attributes_by_name() {
+        let source = r#"<div title="a > b" id="main" class="x" data="1">hi</div>"#;
+        let expected = r#"<div class="x" data="1" id="main" title="a &gt; b">hi</div>"#;
+        for _ in 0..10 {
+            assert_eq!(crate::html::parse(source.to_string()).to_html(), expected);
+        }
+    }
+
+    #[test]
+    fn builder_matches_elem() {
+        let built = Node::element("div")
+            .attr("id", "main")
+            .class("a")
+            .class("b")
+            .child(Node::element("p").text("hello").build())
+            .text("world")
+            .build();
+
+        let mut attributes = AttrMap::new();
+        attributes.insert("id".to_string(), "main".to_string());
+        attributes.insert("class".to_string(), "a b".to_string());
+        let p = elem(
+            "p".to_string(),
+            AttrMap::new(),
+            vec![text("hello".to_string())],
+        );
+        let expected = elem(
+            "div".to_string(),
+            attributes,
+            vec![p, text("world".to_string())],
+        );
+        assert_eq!(built, expected);
+        assert_ne!(built, Node::element("div").build());
+    }
+
+    #[test]
+    fn outer_and_inner_html() {
+        let root = crate::html::parse("<div><b>x</b>y</div>".to_string());
+        assert_eq!(root.outer_html(), "<div><b>x</b>y</div>");
+        assert_eq!(root.inner_html(), "<b>x</b>y");
+        assert_eq!(root.children[0].inner_html(), "x");
+        assert_eq!(text("<".to_string()).outer_html(), "&lt;");
+        assert_eq!(text("<".to_string()).inner_html(), "");
+    }
+
+    #[test]
+    fn query_helpers_search_the_subtree_in_document_order() {
+        let root = crate::html::parse(
+            "<div id=\"top\" class=\"box\"><p class=\"box note\">a</p>\
+             <section><p id=\"inner\" class=\"note\">b</p></section></div>"
+                .to_string(),
+        );
+        let text_of = |node: &Node| node.children[0].as_text().unwrap().to_string();
+
+        // The node a query is called on can match it.
+        assert!(std::ptr::eq(root.get_element_by_id("top").unwrap(), &root));
+        let inner = root.get_element_by_id("inner").unwrap();
+        assert_eq!(text_of(inner), "b");
+        assert!(root.get_element_by_id("missing").is_none());
+
+        let boxes = root.get_elements_by_class("box");
+        assert_eq!(boxes.len(), 2);
+        assert!(std::ptr::eq(boxes[0], &root));
+        let notes: Vec<String> = root
+            .get_elements_by_class("note")
+            .into_iter()
+            .map(text_of)
+            .collect();
+        assert_eq!(notes, ["a", "b"]);
+
+        let paragraphs: Vec<String> = root
+            .get_elements_by_tag("P")
+            .into_iter()
+            .map(text_of)
+            .collect();
+        assert_eq!(paragraphs, ["a", "b"]);
+        // Only the inner paragraph is in the section's subtree.
+        let section = &root.get_elements_by_tag("section")[0];
+        assert_eq!(section.get_elements_by_tag("p").len(), 1);
+    }
+
+    #[test]
+    fn pretty_print_indents_children() {
+        let root = crate::html::parse(
+            "<div id=\"main\" class=\"a\"><p>Hello</p><br><!--note--></div>".to_string(),
+        );
+        let expected = "\
+<div class=\"a\" id=\"main\">
+  <p>
+    Hello
+  </p>
+  <br>
+  <!--note-->
+</div>
+";
+        assert_eq!(root.pretty_print(0), expected);
+        assert_eq!(
+            root.children[0].pretty_print(2),
+            "    <p>\n      Hello\n    </p>\n"
+        );
+    }
+
+    #[test]
+    fn serialize_round_trips_through_the_parser() {
+        let source = r#"<html><p class="x &quot;y&quot;" id="a">1 &lt; 2 &amp;&amp; 3 &gt; 2</p><br><div><img src="i.png"><span>t</span></div></html>"#;
+        let root = crate::html::parse(source.to_string());
+        let html = serialize(&root);
+        assert_eq!(html, source);
+        assert_eq!(crate::html::parse(html), root);
+        // The escaped characters decode back to themselves.
+        let p = root.get_element_by_id("a").unwrap();
+        assert_eq!(p.as_element().unwrap().attributes["class"], "x \"y\"");
+        assert_eq!(p.children[0].as_text(), Some("1 < 2 && 3 > 2"));
+    }
+
+    #[test]
+    fn to_html_can_keep_source_order() {
+        let mut root = crate::html::parse(r#"<a z="1" a="2"></a>"#.to_string());
+        let options = SerializeOptions { source_order: true };
+        assert_eq!(root.to_html_with(options), r#"<a z="1" a="2"></a>"#);
+        assert_eq!(root.to_html(), r#"<a a="2" z="1"></a>"#);
+
+        if let NodeType::Element(ref mut elem) = root.node_type {
+            elem.attributes.insert("m".to_string(), "3".to_string());
+        }
+        assert_eq!(root.to_html_with(options), r#"<a z="1" a="2" m="3"></a>"#);
+    }
+
+    #[test]
+    fn lang_and_dir_are_lowercased() {
+        let root = crate::html::parse(r#"<p dir="RTL" lang="AR"></p>"#.to_string());
+        let elem = root.as_element().unwrap();
+        assert_eq!(elem.dir().as_deref(), Some("rtl"));
+        assert_eq!(elem.lang().as_deref(), Some("ar"));
+        let plain = ElementData::new("p", AttrMap::new());
+        assert_eq!(plain.dir(), None);
+        assert_eq!(plain.lang(), None);
+    }
+}
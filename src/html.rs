@@ -1,12 +1,95 @@
 use crate::dom;
-use std::collections::HashMap;
+use std::fmt;
+
+/// An error returned by the checked parsing entry points.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The input is longer than the allowed number of bytes.
+    TooLarge { len: usize, max_bytes: usize },
+    /// The input is malformed. `offset` is the byte where the parser gave up.
+    Syntax { offset: usize, message: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::TooLarge { len, max_bytes } => write!(
+                f,
+                "input is {} bytes, which exceeds the limit of {} bytes",
+                len, max_bytes
+            ),
+            ParseError::Syntax {
+                offset,
+                ref message,
+            } => write!(f, "{} at byte {}", message, offset),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+/// A problem in a document that `parse_lenient` repaired, and how.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWarning {
+    /// The byte where the problem was found.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
 
 struct Parser {
     pos: usize,
     input: String,
+    // The tag names of the elements being parsed, outermost first.
+    open_elements: Vec<String>,
+    // If set, repair malformed markup where possible instead of failing, recording what was
+    // done in `warnings`.
+    lenient: bool,
+    warnings: Vec<ParseWarning>,
 }
 
 impl Parser {
+    fn new(input: String) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            open_elements: Vec::new(),
+            lenient: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        self.warnings.push(ParseWarning {
+            offset: self.pos,
+            message,
+        });
+    }
+
+    // An error at the current position.
+    fn error<T>(&self, message: String) -> ParseResult<T> {
+        Err(ParseError::Syntax {
+            offset: self.pos,
+            message,
+        })
+    }
+
+    // The input ended inside `what`. Leniently, it's closed there; otherwise, fail.
+    fn unexpected_eof(&mut self, what: &str) -> ParseResult<()> {
+        if !self.lenient {
+            return self.error(format!("Unexpected end of input in {}", what));
+        }
+        self.warn(format!("closed {} cut off by the end of input", what));
+        Ok(())
+    }
+
     // Read the current character without consuming it.
     fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
@@ -18,13 +101,13 @@ impl Parser {
     }
 
     // If the exact string `s` is found at the current position, consume it.
-    // Otherwise, panic.
-    fn expect(&mut self, s: &str) {
-        if self.starts_with(s) {
-            self.pos += s.len();
-        } else {
-            panic!("Expected {:?} at byte {} but it was not found", s, self.pos);
+    // Otherwise, fail.
+    fn expect(&mut self, s: &str) -> ParseResult<()> {
+        if !self.starts_with(s) {
+            return self.error(format!("Expected {:?} but it was not found", s));
         }
+        self.pos += s.len();
+        Ok(())
     }
 
     // Return true if all input is consumed.
@@ -58,101 +141,303 @@ impl Parser {
         self.consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
     }
 
+    // If the input is at an opening or closing tag, return its tag name, lowercased, without
+    // consuming it.
+    fn peek_tag_name(&self) -> Option<String> {
+        let rest = &self.input[self.pos..];
+        let rest = rest.strip_prefix("</").or_else(|| rest.strip_prefix('<'))?;
+        let name: String = rest
+            .chars()
+            .take_while(|&c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        Some(name).filter(|name| !name.is_empty())
+    }
+
     // Parse a single node.
-    fn parse_node(&mut self) -> dom::Node {
-        if self.starts_with("<") {
+    fn parse_node(&mut self) -> ParseResult<dom::Node> {
+        if self.starts_with("<!--") {
+            self.parse_comment()
+        } else if self.starts_with("<") {
             self.parse_element()
         } else {
-            self.parse_text()
+            Ok(self.parse_text())
         }
     }
 
+    // Parse a comment. Everything up to the first `-->` is comment text, so conditional comments
+    // like `<!--[if IE]><p>old</p><![endif]-->` are a single comment whose markup is not parsed.
+    // An unterminated comment runs to the end of the input.
+    fn parse_comment(&mut self) -> ParseResult<dom::Node> {
+        self.expect("<!--")?;
+        let (end, next) = match self.input[self.pos..].find("-->") {
+            Some(i) => (self.pos + i, self.pos + i + "-->".len()),
+            None => (self.input.len(), self.input.len()),
+        };
+        let text = self.input[self.pos..end].to_string();
+        self.pos = next;
+        Ok(dom::comment(text))
+    }
+
     // Parse a text node.
     fn parse_text(&mut self) -> dom::Node {
-        dom::text(self.consume_while(|c| c != '<'))
+        dom::text(decode_entities(&self.consume_while(|c| c != '<')))
     }
 
     // Parse a single element, including its open tag, contents, and closing tag.
-    fn parse_element(&mut self) -> dom::Node {
+    fn parse_element(&mut self) -> ParseResult<dom::Node> {
         // Opening tag.
-        self.expect("<");
-        let tag_name = self.parse_name();
-        let attrs = self.parse_attributes();
-        self.expect(">");
-
-        // Contents.
-        let children = self.parse_nodes();
+        // Tag names are case-insensitive, and kept in lowercase.
+        self.expect("<")?;
+        let tag_name = self.parse_name().to_ascii_lowercase();
+        let (attrs, attribute_order) = self.parse_attributes()?;
+        let self_closing = self.starts_with("/>");
+        if !self.eof() {
+            self.expect(if self_closing { "/>" } else { ">" })?;
+        }
 
-        // Closing tag.
-        self.expect("</");
-        self.expect(&tag_name);
-        self.expect(">");
+        // Contents. Void elements like `<br>`, and any element whose tag ends with `/>`, have
+        // none, and no closing tag.
+        let void = self_closing || dom::is_void_element(&tag_name);
+        let children = if void {
+            Vec::new()
+        } else {
+            self.open_elements.push(tag_name.clone());
+            let children = self.parse_nodes()?;
+            self.open_elements.pop();
+            children
+        };
+        let mut node = dom::elem(tag_name.clone(), attrs, children);
+        if let dom::NodeType::Element(ref mut elem) = node.node_type {
+            elem.attribute_order = attribute_order;
+        }
+        if void {
+            // An end tag right after a void element, as in `<img></img>`, belongs to it.
+            if self.starts_with("</") && self.peek_tag_name().as_ref() == Some(&tag_name) {
+                self.pos += 2 + tag_name.len();
+                self.consume_whitespace();
+                self.expect(">")?;
+            }
+            return Ok(node);
+        }
 
-        dom::elem(tag_name, attrs, children)
+        // Closing tag. Leniently, an element missing one is closed where its contents ended: at
+        // the end of the input, or at the closing tag of an ancestor.
+        if !self.starts_with("</") || self.peek_tag_name().as_ref() != Some(&tag_name) {
+            if self.lenient {
+                self.warn(format!("auto-closed <{}>", tag_name));
+                return Ok(node);
+            }
+            return self.error(format!("Expected a closing tag for <{}>", tag_name));
+        }
+        self.expect("</")?;
+        self.pos += tag_name.len();
+        self.expect(">")?;
+        Ok(node)
     }
 
-    // Parse a single name="value" pair.
-    fn parse_attr(&mut self) -> (String, String) {
-        let name = self.parse_name();
-        self.expect("=");
+    // Parse a single name="value" pair, or a boolean attribute like `disabled`, which has no
+    // value and is given an empty one.
+    fn parse_attr(&mut self) -> ParseResult<(String, String)> {
+        let name = self.parse_name().to_ascii_lowercase();
+        if name.is_empty() {
+            return self.error("Expected an attribute name".to_string());
+        }
+        // There may be whitespace around the `=`.
+        self.consume_whitespace();
+        if !self.starts_with("=") {
+            return Ok((name, String::new()));
+        }
+        self.expect("=")?;
+        self.consume_whitespace();
         let value = self.parse_attr_value();
-        (name, value)
+        Ok((name, value))
     }
 
-    // Parse a quoted value.
+    // Parse a quoted or unquoted value. Quoted values may contain any character but the quote,
+    // including `>`; unquoted values end at whitespace or the end of the tag.
     fn parse_attr_value(&mut self) -> String {
+        if self.eof() || !matches!(self.next_char(), '"' | '\'') {
+            return decode_entities(&self.consume_while(|c| !c.is_whitespace() && c != '>'));
+        }
         let open_quote = self.consume_char();
-        assert!(open_quote == '"' || open_quote == '\'');
         let value = self.consume_while(|c| c != open_quote);
-        let close_quote = self.consume_char();
-        assert_eq!(open_quote, close_quote);
-        value
+        // An unterminated value runs to the end of the input, which ends the tag.
+        if !self.eof() {
+            self.consume_char();
+        }
+        decode_entities(&value)
     }
 
-    // Parse a list of name="value" pairs, separated by whitespace.
-    fn parse_attributes(&mut self) -> dom::AttrMap {
-        let mut attributes = HashMap::new();
+    // Parse a list of name="value" pairs, separated by whitespace. Also return the names in the
+    // order they first appeared.
+    fn parse_attributes(&mut self) -> ParseResult<(dom::AttrMap, Vec<String>)> {
+        let mut attributes = dom::AttrMap::new();
+        let mut order = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
+            if self.eof() {
+                self.unexpected_eof("a tag")?;
                 break;
             }
-            let (name, value) = self.parse_attr();
+            if self.next_char() == '>' || self.starts_with("/>") {
+                break;
+            }
+            let (name, value) = self.parse_attr()?;
+            if !attributes.contains_key(&name) {
+                order.push(name.clone());
+            }
             attributes.insert(name, value);
         }
-        attributes
+        Ok((attributes, order))
     }
 
     // Parse a sequence of sibling nodes.
-    fn parse_nodes(&mut self) -> Vec<dom::Node> {
+    fn parse_nodes(&mut self) -> ParseResult<Vec<dom::Node>> {
         let mut nodes = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.eof() || self.starts_with("</") {
+            if self.eof() {
                 break;
             }
-            nodes.push(self.parse_node());
+            if self.starts_with("</") {
+                // Leniently, a closing tag that doesn't match any open element is dropped.
+                let name = self.peek_tag_name().unwrap_or_default();
+                if !self.lenient || self.open_elements.contains(&name) {
+                    break;
+                }
+                self.warn(format!("ignored stray closing tag </{}>", name));
+                self.consume_while(|c| c != '>');
+                if !self.eof() {
+                    self.consume_char();
+                }
+                continue;
+            }
+            // Declarations like `<!DOCTYPE html>` are dropped.
+            if self.starts_with("<!") && !self.starts_with("<!--") {
+                self.consume_while(|c| c != '>');
+                if !self.eof() {
+                    self.consume_char();
+                }
+                continue;
+            }
+            nodes.push(self.parse_node()?);
         }
-        nodes
+        Ok(nodes)
     }
 }
 
-// Parse an HTML document and return the root element.
-pub fn parse(source: String) -> dom::Node {
-    let mut nodes = Parser {
-        pos: 0,
-        input: source,
+/// Replace the character references in `s`: `&amp;`, `&lt;`, `&gt;`, `&quot;`, and `&apos;`,
+/// and numeric ones like `&#60;` and `&#x3c;`. Anything else starting with `&` is left as it is.
+pub fn decode_entities(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let c = rest[1..].find(';').and_then(|end| {
+            let c = match &rest[1..=end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                name => {
+                    let number = name.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16),
+                        None => number.parse(),
+                    };
+                    char::from_u32(code.ok()?)?
+                }
+            };
+            Some((c, end + 2))
+        });
+        match c {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
     }
-    .parse_nodes();
+    decoded.push_str(rest);
+    decoded
+}
+
+// Parse an HTML document and return the root element. Panics if the document is malformed; see
+// `try_parse`.
+pub fn parse(source: String) -> dom::Node {
+    try_parse(source).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Like `parse`, but repair malformed markup where possible rather than panicking: elements
+/// missing a closing tag are closed, stray closing tags are dropped, and a tag cut off by the
+/// end of the input is closed there. Also return a warning for each repair, in document order.
+pub fn parse_lenient(source: String) -> (dom::Node, Vec<ParseWarning>) {
+    let mut parser = Parser::new(source);
+    parser.lenient = true;
+    let nodes = parser.parse_nodes().unwrap_or_else(|err| panic!("{}", err));
+    (root_element(nodes), parser.warnings)
+}
 
-    // If the document contains a root element, just return it. Otherwise, create one.
-    if nodes.len() == 1 {
+// If the document contains a root element, just return it. Otherwise, create one.
+// (Empty or whitespace-only input yields an empty `html` element.)
+fn root_element(mut nodes: Vec<dom::Node>) -> dom::Node {
+    if nodes.len() == 1 && nodes[0].is_element() {
         nodes.remove(0)
     } else {
-        dom::elem("html".to_string(), HashMap::new(), nodes)
+        dom::elem("html".to_string(), dom::AttrMap::new(), nodes)
     }
 }
 
+/// Like `parse`, but return an error instead of panicking if the document is malformed.
+pub fn try_parse(source: String) -> Result<dom::Node, ParseError> {
+    let nodes = Parser::new(source).parse_nodes()?;
+    Ok(root_element(nodes))
+}
+
+/// Parse a fragment of HTML as the contents of a `context_tag` element, as when setting its
+/// inner HTML, and return the top-level nodes. The contents of elements whose text isn't parsed
+/// as markup, like `textarea`, are a single text node.
+pub fn parse_fragment(source: String, context_tag: &str) -> Vec<dom::Node> {
+    const RAW_TEXT_TAGS: &[&str] = &["script", "style", "textarea", "title"];
+    if RAW_TEXT_TAGS
+        .iter()
+        .any(|tag| tag.eq_ignore_ascii_case(context_tag))
+    {
+        return if source.is_empty() {
+            Vec::new()
+        } else {
+            vec![dom::text(source)]
+        };
+    }
+    let mut parser = Parser::new(source);
+    let nodes = parser.parse_nodes().unwrap_or_else(|err| panic!("{}", err));
+    if !parser.eof() {
+        panic!(
+            "Unexpected closing tag at byte {} in <{}> fragment",
+            parser.pos, context_tag
+        );
+    }
+    nodes
+}
+
+/// Like `try_parse`, but also refuse inputs longer than `max_bytes` instead of parsing them. Use
+/// this for untrusted input.
+pub fn parse_limited(source: String, max_bytes: usize) -> Result<dom::Node, ParseError> {
+    if source.len() > max_bytes {
+        return Err(ParseError::TooLarge {
+            len: source.len(),
+            max_bytes,
+        });
+    }
+    try_parse(source)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -170,4 +455,390 @@ mod tests {
             panic!("Root node should be an element");
         }
     }
+
+    #[test]
+    fn parse_empty_input() {
+        let root = parse("".to_string());
+        assert!(root.children.is_empty());
+        if let dom::NodeType::Element(ref element_data) = root.node_type {
+            assert_eq!(element_data.tag_name, "html");
+        } else {
+            panic!("Root node should be an element");
+        }
+    }
+
+    #[test]
+    fn parse_whitespace_only_input() {
+        let root = parse("   ".to_string());
+        assert!(root.children.is_empty());
+        if let dom::NodeType::Element(ref element_data) = root.node_type {
+            assert_eq!(element_data.tag_name, "html");
+        } else {
+            panic!("Root node should be an element");
+        }
+    }
+
+    fn attribute(source: &str, name: &str) -> Option<String> {
+        let root = parse(source.to_string());
+        root.as_element()?.attributes.get(name).cloned()
+    }
+
+    #[test]
+    fn quoted_attribute_value_may_contain_gt() {
+        let source = r#"<a title="a > b" href='x>y'></a>"#;
+        assert_eq!(attribute(source, "title").as_deref(), Some("a > b"));
+        assert_eq!(attribute(source, "href").as_deref(), Some("x>y"));
+    }
+
+    #[test]
+    fn unquoted_attribute_value_ends_at_gt() {
+        let source = "<a id=main class=big>text</a>";
+        assert_eq!(attribute(source, "id").as_deref(), Some("main"));
+        assert_eq!(attribute(source, "class").as_deref(), Some("big"));
+        assert_eq!(parse(source.to_string()).children.len(), 1);
+    }
+
+    #[test]
+    fn conditional_comment_is_one_comment() {
+        let source = "<div><!--[if IE]><p>old</p><![endif]--><p>new</p></div>";
+        let root = parse(source.to_string());
+        assert_eq!(root.children.len(), 2);
+        let comment = &root.children[0];
+        assert!(comment.children.is_empty());
+        match comment.node_type {
+            dom::NodeType::Comment(ref text) => assert_eq!(text, "[if IE]><p>old</p><![endif]"),
+            _ => panic!("Expected a comment node"),
+        }
+        assert!(root.children[1].node_type.is_element());
+    }
+
+    #[test]
+    fn comments_between_nodes_are_skipped_over() {
+        let root = parse("<div><!-- hi -->text</div>".to_string());
+        let texts: Vec<&str> = root.children.iter().filter_map(|c| c.as_text()).collect();
+        assert_eq!(texts, ["text"]);
+
+        let root = parse("<div><p></p><!-- between --><p></p></div>".to_string());
+        let elements = root.children.iter().filter(|c| c.node_type.is_element());
+        assert_eq!(elements.count(), 2);
+    }
+
+    #[test]
+    fn unterminated_comment_runs_to_end_of_input() {
+        let root = parse("<p>text</p><!-- no end <p>".to_string());
+        assert_eq!(root.children.len(), 2);
+        match root.children[1].node_type {
+            dom::NodeType::Comment(ref text) => assert_eq!(text, " no end <p>"),
+            _ => panic!("Expected a comment node"),
+        }
+    }
+
+    #[test]
+    fn decode_named_and_numeric_entities() {
+        assert_eq!(decode_entities("A &amp; B"), "A & B");
+        assert_eq!(
+            decode_entities("&lt;p&gt; &quot;q&quot; &apos;a&apos;"),
+            "<p> \"q\" 'a'"
+        );
+        assert_eq!(decode_entities("&#60;&#x3c;&#X3C;&#9731;"), "<<<☃");
+        // Unknown and malformed references are kept.
+        assert_eq!(
+            decode_entities("&foo; & &amp &#xzz; &#;"),
+            "&foo; & &amp &#xzz; &#;"
+        );
+        assert_eq!(decode_entities("a &; b&"), "a &; b&");
+        assert_eq!(decode_entities("&#1114112;"), "&#1114112;");
+    }
+
+    #[test]
+    fn entities_are_decoded_in_text_and_attributes() {
+        let source = r#"<p title="a &quot;b&quot;" data=x&amp;y>A &amp; B &#x3c;3</p>"#;
+        let root = parse(source.to_string());
+        assert_eq!(root.children[0].as_text(), Some("A & B <3"));
+        assert_eq!(attribute(source, "title").as_deref(), Some("a \"b\""));
+        assert_eq!(attribute(source, "data").as_deref(), Some("x&y"));
+    }
+
+    #[test]
+    fn tag_and_attribute_names_are_lowercased() {
+        let root = parse(r#"<DIV CLASS="Big"><P Id=X>Text</p></Div>"#.to_string());
+        let div = root.as_element().unwrap();
+        assert_eq!(div.tag_name, "div");
+        assert_eq!(div.attributes.get("class").map(String::as_str), Some("Big"));
+        let p = root.children[0].as_element().unwrap();
+        assert_eq!(p.tag_name, "p");
+        assert_eq!(p.id().map(String::as_str), Some("X"));
+        assert_eq!(root.children[0].children[0].as_text(), Some("Text"));
+    }
+
+    #[test]
+    fn boolean_attributes() {
+        let root = parse(r#"<input disabled type="text">"#.to_string());
+        let attributes = &root.as_element().unwrap().attributes;
+        assert_eq!(attributes.get("disabled").map(String::as_str), Some(""));
+        assert_eq!(attributes.get("type").map(String::as_str), Some("text"));
+
+        // Before the end of the tag, or of a self-closing tag.
+        assert_eq!(
+            attribute("<option selected></option>", "selected").as_deref(),
+            Some("")
+        );
+        assert_eq!(attribute("<input a b/>", "b").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn void_elements_have_no_contents() {
+        let root = parse("<div><br>after</div>".to_string());
+        assert_eq!(root.children.len(), 2);
+        assert!(root.children[0].children.is_empty());
+        assert_eq!(root.children[1].as_text(), Some("after"));
+
+        // An image followed by text is two siblings, and serializes without a closing tag.
+        let source = r#"<p><img src="x.png">text<hr></p>"#;
+        let root = parse(source.to_string());
+        assert_eq!(root.children.len(), 3);
+        assert_eq!(
+            root.children[0].as_element().unwrap().attributes["src"],
+            "x.png"
+        );
+        assert_eq!(root.to_html(), source);
+    }
+
+    #[test]
+    fn void_elements_may_have_an_end_tag() {
+        let root = parse("<p><img src=\"x\"></img>text<br></br><link></p>".to_string());
+        assert_eq!(root.to_html(), "<p><img src=\"x\">text<br><link></p>");
+    }
+
+    #[test]
+    fn self_closing_tags() {
+        let root = parse(r#"<div><img src="x.png"/><span class="a" />text</div>"#.to_string());
+        assert_eq!(root.children.len(), 3);
+        assert!(root.children[1].children.is_empty());
+        assert_eq!(
+            attribute(r#"<span class="a" />"#, "class").as_deref(),
+            Some("a")
+        );
+        assert_eq!(root.children[2].as_text(), Some("text"));
+    }
+
+    #[test]
+    fn doctype_is_skipped() {
+        let root = parse("<!DOCTYPE html><html></html>".to_string());
+        assert_eq!(root.as_element().unwrap().tag_name, "html");
+        assert!(root.children.is_empty());
+
+        let root = parse("<!doctype html>\n<!-- c --><div><p></p></div>".to_string());
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[1].as_element().unwrap().tag_name, "div");
+    }
+
+    #[test]
+    fn multibyte_text_round_trips() {
+        let source = r#"<p title="naïve ☃">café 😀<!-- ü --></p>"#;
+        let root = parse(source.to_string());
+        assert_eq!(root.children[0].as_text(), Some("café 😀"));
+        assert_eq!(attribute(source, "title").as_deref(), Some("naïve ☃"));
+        assert_eq!(root.to_html(), source);
+    }
+
+    #[test]
+    fn peek_tag_name_does_not_consume() {
+        let mut parser = Parser::new("<div></p>text<!-- c -->".to_string());
+        assert_eq!(parser.peek_tag_name().as_deref(), Some("div"));
+        assert_eq!(parser.pos, 0);
+        parser.pos = 5;
+        assert_eq!(parser.peek_tag_name().as_deref(), Some("p"));
+        assert_eq!(parser.pos, 5);
+        parser.pos = 9;
+        assert_eq!(parser.peek_tag_name(), None);
+        parser.pos = 13;
+        assert_eq!(parser.peek_tag_name(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected a closing tag for <p> at byte 8")]
+    fn mismatched_closing_tag_panics() {
+        parse("<div><p></div></p>".to_string());
+    }
+
+    #[test]
+    fn try_parse_returns_syntax_errors() {
+        assert_eq!(
+            try_parse("<div><p></div></p>".to_string()),
+            Err(ParseError::Syntax {
+                offset: 8,
+                message: "Expected a closing tag for <p>".to_string()
+            })
+        );
+        let err = try_parse("<div =x></div>".to_string()).unwrap_err();
+        assert_eq!(err.to_string(), "Expected an attribute name at byte 5");
+    }
+
+    #[test]
+    fn parse_fragment_returns_siblings() {
+        let nodes = parse_fragment("<li>a</li><li>b</li>".to_string(), "ul");
+        let html: Vec<String> = nodes.iter().map(|node| node.outer_html()).collect();
+        assert_eq!(html, ["<li>a</li>", "<li>b</li>"]);
+        assert!(parse_fragment("  ".to_string(), "ul").is_empty());
+    }
+
+    #[test]
+    fn parse_fragment_in_raw_text_context_is_text() {
+        let nodes = parse_fragment("<b>not bold</b>".to_string(), "textarea");
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].as_text(), Some("<b>not bold</b>"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected closing tag at byte 9 in <ul> fragment")]
+    fn parse_fragment_rejects_stray_closing_tag() {
+        parse_fragment("<li></li></ul>".to_string(), "ul");
+    }
+
+    #[test]
+    fn lenient_parse_closes_unclosed_elements() {
+        let (root, warnings) = parse_lenient("<div><p>one</div>".to_string());
+        assert_eq!(root.to_html(), "<div><p>one</p></div>");
+        assert_eq!(
+            warnings,
+            vec![ParseWarning {
+                offset: 11,
+                message: "auto-closed <p>".to_string()
+            }]
+        );
+        assert_eq!(warnings[0].to_string(), "auto-closed <p> at byte 11");
+
+        // At the end of the input, every open element is closed, innermost first.
+        let (root, warnings) = parse_lenient("<div><span>text".to_string());
+        assert_eq!(root.to_html(), "<div><span>text</span></div>");
+        let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "auto-closed <span> at byte 15",
+                "auto-closed <div> at byte 15"
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient_parse_recovers_from_misnested_tags() {
+        let (root, warnings) = parse_lenient("<div><span></div>".to_string());
+        assert_eq!(root.as_element().unwrap().tag_name, "div");
+        assert_eq!(root.children.len(), 1);
+        assert!(root.children[0]
+            .as_element()
+            .is_some_and(|e| e.tag_name == "span"));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].to_string(), "auto-closed <span> at byte 11");
+
+        // The `</b>` closes the `<i>` too, and the `</i>` left over is dropped.
+        let (root, warnings) = parse_lenient("<p><b><i>text</b></i></p>".to_string());
+        assert_eq!(root.to_html(), "<p><b><i>text</i></b></p>");
+        let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "auto-closed <i> at byte 13",
+                "ignored stray closing tag </i> at byte 17"
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace_around_attribute_equals() {
+        let root = parse("<div id = \"main\" hidden class=\"a\"></div>".to_string());
+        let elem = root.as_element().unwrap();
+        assert_eq!(elem.id().map(String::as_str), Some("main"));
+        assert_eq!(elem.attributes["hidden"], "");
+        assert_eq!(elem.attributes["class"], "a");
+        assert_eq!(elem.attribute_order, ["id", "hidden", "class"]);
+    }
+
+    #[test]
+    fn input_ending_inside_a_tag() {
+        let source = "<div class=\"unter";
+        let err = try_parse(source.to_string()).unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::Syntax {
+                offset: 17,
+                message: "Unexpected end of input in a tag".to_string()
+            }
+        );
+
+        let (root, warnings) = parse_lenient(source.to_string());
+        assert_eq!(root.to_html(), "<div class=\"unter\"></div>");
+        let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "closed a tag cut off by the end of input at byte 17",
+                "auto-closed <div> at byte 17"
+            ]
+        );
+        let (root, _) = parse_lenient("<p>text</p><img src=a".to_string());
+        assert_eq!(root.to_html(), "<html><p>text</p><img src=\"a\"></html>");
+    }
+
+    #[test]
+    fn lenient_parse_drops_stray_closing_tags() {
+        let (root, warnings) = parse_lenient("<div>a</b>b</div></i>".to_string());
+        assert_eq!(root.to_html(), "<div>ab</div>");
+        let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "ignored stray closing tag </b> at byte 6",
+                "ignored stray closing tag </i> at byte 17"
+            ]
+        );
+        // Well-formed documents parse the same as with `parse`, without warnings.
+        let source = "<div id=\"a\"><p>x</p><!-- c --></div>";
+        let (root, warnings) = parse_lenient(source.to_string());
+        assert_eq!(root, parse(source.to_string()));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn parse_limited_rejects_large_input() {
+        let source = "<div></div>".to_string();
+        let err = match parse_limited(source, 10) {
+            Err(err) => err,
+            Ok(_) => panic!("Input over the limit should be rejected"),
+        };
+        assert_eq!(
+            err,
+            ParseError::TooLarge {
+                len: 11,
+                max_bytes: 10
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "input is 11 bytes, which exceeds the limit of 10 bytes"
+        );
+    }
+
+    #[test]
+    fn parse_limited_accepts_input_within_limit() {
+        let root = parse_limited("<div></div>".to_string(), 11).unwrap();
+        if let dom::NodeType::Element(ref element_data) = root.node_type {
+            assert_eq!(element_data.tag_name, "div");
+        } else {
+            panic!("Root node should be an element");
+        }
+    }
+
+    #[test]
+    fn parse_limited_reports_malformed_input() {
+        assert_eq!(
+            parse_limited("<div></p>".to_string(), 100),
+            Err(ParseError::Syntax {
+                offset: 5,
+                message: "Expected a closing tag for <div>".to_string()
+            })
+        );
+    }
 }
@@ -0,0 +1,221 @@
+//! A spatial index over a laid out tree, for hit testing and finding the boxes in a region of a
+//! large page without visiting every box.
+
+use crate::layout::{LayoutBox, Rect};
+use std::collections::HashMap;
+
+// The default width and height of a grid cell, in px.
+const DEFAULT_CELL_SIZE: f32 = 64.0;
+// Boxes covering more cells than this (like the root) are kept in a list that every query checks
+// instead, so the grid stays small.
+const MAX_CELLS_PER_BOX: i64 = 256;
+
+/// The border boxes of a layout tree, bucketed into a uniform grid.
+pub struct LayoutIndex<'t, 'a> {
+    // Every box with a non-empty border box, in tree order.
+    boxes: Vec<(Rect, &'t LayoutBox<'a>)>,
+    cell_size: f32,
+    // The boxes overlapping each cell, by index into `boxes`.
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    large: Vec<usize>,
+}
+
+impl<'t, 'a> LayoutIndex<'t, 'a> {
+    pub fn build(root: &'t LayoutBox<'a>) -> LayoutIndex<'t, 'a> {
+        LayoutIndex::with_cell_size(root, DEFAULT_CELL_SIZE)
+    }
+
+    /// Build an index with grid cells `cell_size` px square.
+    pub fn with_cell_size(root: &'t LayoutBox<'a>, cell_size: f32) -> LayoutIndex<'t, 'a> {
+        assert!(cell_size > 0.0, "Grid cells must have a positive size");
+        let mut index = LayoutIndex {
+            boxes: Vec::new(),
+            cell_size,
+            cells: HashMap::new(),
+            large: Vec::new(),
+        };
+        for (_, layout_box) in root.iter_boxes() {
+            let rect = layout_box.dimensions.border_box();
+            if rect.width <= 0.0 || rect.height <= 0.0 {
+                continue;
+            }
+            let i = index.boxes.len();
+            index.boxes.push((rect, layout_box));
+            let (x0, y0, x1, y1) = index.cell_range(&rect);
+            if (x1 - x0 + 1) * (y1 - y0 + 1) > MAX_CELLS_PER_BOX {
+                index.large.push(i);
+                continue;
+            }
+            for cx in x0..=x1 {
+                for cy in y0..=y1 {
+                    index.cells.entry((cx, cy)).or_default().push(i);
+                }
+            }
+        }
+        index
+    }
+
+    /// The boxes whose border box contains the point (`x`, `y`), in tree order, so the last is
+    /// the innermost.
+    pub fn query_point(&self, x: f32, y: f32) -> Vec<&'t LayoutBox<'a>> {
+        let cell = (self.cell(x), self.cell(y));
+        let candidates = self.cells.get(&cell).into_iter().flatten();
+        self.collect(candidates, |rect| contains(rect, x, y))
+    }
+
+    /// The boxes whose border box overlaps `area`, in tree order.
+    pub fn query_rect(&self, area: &Rect) -> Vec<&'t LayoutBox<'a>> {
+        let (x0, y0, x1, y1) = self.cell_range(area);
+        let candidates = (x0..=x1)
+            .flat_map(|cx| (y0..=y1).map(move |cy| (cx, cy)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten();
+        self.collect(candidates, |rect| overlaps(rect, area))
+    }
+
+    // The boxes among `candidates` and the large boxes that pass `test`, without duplicates and
+    // in tree order.
+    fn collect<'s>(
+        &'s self,
+        candidates: impl Iterator<Item = &'s usize>,
+        test: impl Fn(&Rect) -> bool,
+    ) -> Vec<&'t LayoutBox<'a>> {
+        let mut found: Vec<usize> = candidates
+            .chain(&self.large)
+            .copied()
+            .filter(|&i| test(&self.boxes[i].0))
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found.into_iter().map(|i| self.boxes[i].1).collect()
+    }
+
+    fn cell(&self, coordinate: f32) -> i64 {
+        (coordinate / self.cell_size).floor() as i64
+    }
+
+    // The first and last columns and rows of the cells `rect` touches.
+    fn cell_range(&self, rect: &Rect) -> (i64, i64, i64, i64) {
+        (
+            self.cell(rect.x),
+            self.cell(rect.y),
+            self.cell(rect.x + rect.width),
+            self.cell(rect.y + rect.height),
+        )
+    }
+}
+
+// Does `rect` contain the point? Its right and bottom edges are outside it.
+fn contains(rect: &Rect, x: f32, y: f32) -> bool {
+    x >= rect.x && x < rect.x + rect.width && y >= rect.y && y < rect.y + rect.height
+}
+
+// Do the rects share any area?
+fn overlaps(rect: &Rect, other: &Rect) -> bool {
+    let overlap = rect.intersection(*other);
+    overlap.width > 0.0 && overlap.height > 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{layout_tree, Dimensions, DEFAULT_VIEWPORT};
+    use crate::style::style_tree;
+
+    // The boxes passing `test`, found by visiting every box.
+    fn brute_force<'t, 'a>(
+        root: &'t LayoutBox<'a>,
+        test: impl Fn(&Rect) -> bool,
+    ) -> Vec<&'t LayoutBox<'a>> {
+        root.iter_boxes()
+            .map(|(_, layout_box)| layout_box)
+            .filter(|layout_box| {
+                let rect = layout_box.dimensions.border_box();
+                rect.width > 0.0 && rect.height > 0.0 && test(&rect)
+            })
+            .collect()
+    }
+
+    fn same_boxes(a: &[&LayoutBox], b: &[&LayoutBox]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| std::ptr::eq(*x, *y))
+    }
+
+    #[test]
+    fn queries_match_brute_force_on_a_grid() {
+        // A 10x10 grid of 30px boxes, 40px apart, with a larger box overlapping them.
+        let mut html = String::from("<div>");
+        let mut css = String::from("div { display: block; position: relative; height: 400px; } ");
+        for i in 0..100 {
+            html.push_str(&format!("<p id=\"b{}\"></p>", i));
+            css.push_str(&format!(
+                "#b{} {{ display: block; position: absolute; left: {}px; top: {}px; \
+                 width: 30px; height: 30px; }} ",
+                i,
+                (i % 10) * 40,
+                (i / 10) * 40
+            ));
+        }
+        html.push_str("<p id=\"big\"></p></div>");
+        css.push_str(
+            "#big { display: block; position: absolute; left: 50px; top: 50px; \
+             width: 300px; height: 100px; }",
+        );
+        let root = crate::html::parse(html);
+        let stylesheet = crate::css::parse(css);
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+
+        for cell_size in [7.0, 64.0, 1000.0] {
+            let index = LayoutIndex::with_cell_size(&layout_root, cell_size);
+            for x in (-10..420).step_by(13) {
+                for y in (-10..420).step_by(11) {
+                    let (x, y) = (x as f32, y as f32);
+                    let expected = brute_force(&layout_root, |r| contains(r, x, y));
+                    assert!(same_boxes(&index.query_point(x, y), &expected));
+
+                    let area = Rect {
+                        x,
+                        y,
+                        width: 25.0,
+                        height: 55.0,
+                    };
+                    let expected = brute_force(&layout_root, |r| overlaps(r, &area));
+                    assert!(same_boxes(&index.query_rect(&area), &expected));
+                }
+            }
+        }
+
+        // The point inside the first box hits the container and then the box.
+        let index = LayoutIndex::build(&layout_root);
+        let hits = index.query_point(5.0, 5.0);
+        assert_eq!(hits.len(), 2);
+        assert!(std::ptr::eq(hits[1], &layout_root.children[0]));
+    }
+
+    #[test]
+    fn queries_on_many_boxes() {
+        let paragraphs: String = (0..5000).map(|_| "<p></p>").collect();
+        let root = crate::html::parse(format!("<div>{}</div>", paragraphs));
+        let stylesheet =
+            crate::css::parse("div, p { display: block; } p { height: 10px; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let index = LayoutIndex::build(&layout_root);
+
+        for i in 0..5000 {
+            let y = i as f32 * 10.0 + 5.0;
+            let hits = index.query_point(400.0, y);
+            assert_eq!(hits.len(), 2);
+            assert!(std::ptr::eq(hits[1], &layout_root.children[i]));
+        }
+        let area = Rect {
+            x: 0.0,
+            y: 1000.0,
+            width: 10.0,
+            height: 100.0,
+        };
+        let expected = brute_force(&layout_root, |r| overlaps(r, &area));
+        assert_eq!(expected.len(), 11);
+        assert!(same_boxes(&index.query_rect(&area), &expected));
+    }
+}
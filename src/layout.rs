@@ -1,7 +1,12 @@
-use crate::css::Unit::Px;
 use crate::css::Value::{Keyword, Length};
-use crate::layout::BoxType::{AnonymousBlock, BlockNode, InlineNode};
-use crate::style::{Display, StyledNode};
+use crate::css::{Color, PseudoElement, ResolutionContext, Value};
+use crate::dom::NodeType;
+use crate::layout::BoxType::{AnonymousBlock, BlockNode, InlineNode, Marker};
+use crate::style::{
+    AlignItems, Display, JustifyContent, Position, StyledNode, TextAlign, VerticalAlign,
+    WritingMode,
+};
+use crate::text::{baseline_offset, expand_tabs, text_width, LineBreaker};
 
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Dimensions {
@@ -14,7 +19,7 @@ pub struct Dimensions {
     pub margin: EdgeSizes,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -22,6 +27,14 @@ pub struct Rect {
     pub height: f32,
 }
 
+/// The viewport used when no other size is given.
+pub const DEFAULT_VIEWPORT: Rect = Rect {
+    x: 0.0,
+    y: 0.0,
+    width: 800.0,
+    height: 600.0,
+};
+
 #[derive(Clone, Copy, Default, Debug)]
 pub struct EdgeSizes {
     pub left: f32,
@@ -33,12 +46,46 @@ pub enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
     AnonymousBlock,
+    // Generated text: the marker of a list item, or the `::before` or `::after` content of an
+    // element. It's styled like the element.
+    Marker(&'a StyledNode<'a>, String),
 }
 
 pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     pub box_type: BoxType<'a>,
     pub children: Vec<LayoutBox<'a>>,
+    // The lines of text laid out for a text node, in order.
+    pub fragments: Vec<TextFragment>,
+    // The padding box that absolutely positioned children of this box are placed in: that of the
+    // nearest positioned ancestor-or-self, or the initial containing block.
+    absolute_containing_block: Rect,
+    // Where an absolutely positioned box would have started in the normal flow.
+    static_position: (f32, f32),
+    // The line a replaced inline box, like an image, was placed on.
+    line: usize,
+    // How many anonymous blocks have been generated among this box's children.
+    anonymous_children: usize,
+}
+
+/// A piece of a text node's content, laid out on a single line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextFragment {
+    pub text: String,
+    pub line: usize,
+    pub rect: Rect,
+    // The color of the text, if it's set on this line rather than the text node, by
+    // `::first-line`.
+    pub color: Option<Color>,
+}
+
+/// A laid out piece of text with its color, for consumers such as accessibility tools that want
+/// the text of a page in reading order rather than a layout tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    pub rect: Rect,
+    pub color: Color,
 }
 
 impl<'a> LayoutBox<'a> {
@@ -47,194 +94,1172 @@ impl<'a> LayoutBox<'a> {
             box_type,
             dimensions: Default::default(), // initially set all fields to 0.0
             children: Vec::new(),
+            fragments: Vec::new(),
+            absolute_containing_block: Rect::default(),
+            static_position: (0.0, 0.0),
+            line: 0,
+            anonymous_children: 0,
         }
     }
 
-    fn get_style_node(&self) -> &'a StyledNode<'a> {
+    // The size of this box if it's a replaced inline element, like an image, which is laid out
+    // as a single unbreakable box.
+    fn replaced_size(&self) -> Option<(f32, f32)> {
+        match self.box_type {
+            InlineNode(style) => style.replaced_size(),
+            _ => None,
+        }
+    }
+
+    /// Were this box's descendants skipped, because it has `content-visibility: hidden`?
+    pub fn skipped_contents(&self) -> bool {
+        match self.box_type {
+            BlockNode(style) => style.skips_contents(),
+            _ => false,
+        }
+    }
+
+    /// The styled node this box was generated for, or `None` for an anonymous box. Its `node`
+    /// is the DOM node.
+    pub fn styled_node(&self) -> Option<&'a StyledNode<'a>> {
         match self.box_type {
-            BlockNode(node) | InlineNode(node) => node,
-            AnonymousBlock => panic!("Anonymous block box has no style node"),
+            BlockNode(node) | InlineNode(node) | Marker(node, _) => Some(node),
+            AnonymousBlock => None,
+        }
+    }
+
+    fn get_style_node(&self) -> &'a StyledNode<'a> {
+        self.styled_node()
+            .expect("Anonymous block box has no style node")
+    }
+
+    /// The child boxes, in tree order.
+    pub fn children(&self) -> &[LayoutBox<'a>] {
+        &self.children
+    }
+
+    /// The child boxes in the order they paint in. Positioned children with a `z-index` are
+    /// stacked by it, above or below their siblings (which count as zero). Others, including
+    /// static boxes with a `z-index`, paint in tree order. There are no stacking contexts beyond
+    /// siblings.
+    pub fn paint_order_children(&self) -> Vec<&LayoutBox<'a>> {
+        let mut children: Vec<&LayoutBox<'a>> = self.children.iter().collect();
+        children.sort_by_key(|child| match child.box_type {
+            BlockNode(style) | InlineNode(style) => style.z_index().unwrap_or(0),
+            Marker(..) | AnonymousBlock => 0,
+        });
+        children
+    }
+
+    /// The child box at `index`, or `None` if out of range.
+    pub fn child(&self, index: usize) -> Option<&LayoutBox<'a>> {
+        self.children.get(index)
+    }
+
+    pub fn first_child(&self) -> Option<&LayoutBox<'a>> {
+        self.children.first()
+    }
+
+    pub fn last_child(&self) -> Option<&LayoutBox<'a>> {
+        self.children.last()
+    }
+
+    /// Serialize the box tree as JSON: the kind, node, and content rect of each box, one box per
+    /// line. Lengths are rounded to thousandths of a px. For comparing layouts in tests.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json, 0);
+        json.push('\n');
+        json
+    }
+
+    /// An outline of the box tree for debugging, one box per line, indented two spaces per
+    /// level: the kind and node of each box, its content rect, and the sizes of its margin,
+    /// border, and padding edges (top, right, bottom, left). Lengths are rounded like `to_json`.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        for (depth, layout_box) in self.iter_boxes() {
+            let (kind, node) = layout_box.describe();
+            let d = &layout_box.dimensions;
+            let r = d.content;
+            let edges = |e: &EdgeSizes| {
+                format!(
+                    "{} {} {} {}",
+                    round(e.top),
+                    round(e.right),
+                    round(e.bottom),
+                    round(e.left)
+                )
+            };
+            out.push_str(&format!(
+                "{}{} {} content: ({}, {}) {}x{}, margin: {}, border: {}, padding: {}\n",
+                "  ".repeat(depth),
+                kind,
+                node,
+                round(r.x),
+                round(r.y),
+                round(r.width),
+                round(r.height),
+                edges(&d.margin),
+                edges(&d.border),
+                edges(&d.padding)
+            ));
+        }
+        out
+    }
+
+    // The kind of this box and the name of its node, for `to_json` and `debug_tree`.
+    fn describe(&self) -> (&'static str, &'a str) {
+        let (kind, style) = match self.box_type {
+            BlockNode(style) => ("block", Some(style)),
+            InlineNode(style) => ("inline", Some(style)),
+            AnonymousBlock => ("anonymous", None),
+            Marker(style, _) => ("marker", Some(style)),
+        };
+        let node = match style.map(|style| &style.node.node_type) {
+            Some(NodeType::Element(elem)) => elem.tag_name.as_str(),
+            Some(NodeType::Text(_)) => "#text",
+            Some(NodeType::Comment(_)) => "#comment",
+            None => "",
+        };
+        (kind, node)
+    }
+
+    fn write_json(&self, json: &mut String, depth: usize) {
+        let (kind, node) = self.describe();
+        let rect = self.dimensions.content;
+        json.push_str(&format!(
+            "{}{{\"box\": \"{}\", \"node\": \"{}\", \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \"children\": [",
+            "  ".repeat(depth),
+            kind,
+            node,
+            round(rect.x),
+            round(rect.y),
+            round(rect.width),
+            round(rect.height)
+        ));
+        for (i, child) in self.children.iter().enumerate() {
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            child.write_json(json, depth + 1);
         }
+        if !self.children.is_empty() {
+            json.push('\n');
+            json.push_str(&"  ".repeat(depth));
+        }
+        json.push_str("]}");
+    }
+}
+// A length rounded to thousandths of a px, so that debug output doesn't show float noise.
+fn round(v: f32) -> f32 {
+    (v * 1000.0).round() / 1000.0
+}
+
+// The total width of the horizontal margins, borders, and padding of a box, ignoring any that
+// aren't in px.
+fn horizontal_edges(style: &StyledNode) -> f32 {
+    let zero = Value::px(0.0);
+    [
+        style.lookup("margin-left", "margin", &zero),
+        style.lookup("margin-right", "margin", &zero),
+        style.border_width("left"),
+        style.border_width("right"),
+        style.lookup("padding-left", "padding", &zero),
+        style.lookup("padding-right", "padding", &zero),
+    ]
+    .iter()
+    .map(|v| v.to_px())
+    .sum()
+}
+
+/// The space left between two adjoining vertical margins when they collapse into one: the larger
+/// of two positive margins, the more negative of two negative ones, or the sum of a positive and
+/// a negative margin.
+pub fn collapse_margins(prev_bottom: f32, next_top: f32) -> f32 {
+    if prev_bottom >= 0.0 && next_top >= 0.0 {
+        prev_bottom.max(next_top)
+    } else if prev_bottom <= 0.0 && next_top <= 0.0 {
+        prev_bottom.min(next_top)
+    } else {
+        prev_bottom + next_top
     }
 }
-/// Transform a style tree into a layout tree.
-pub fn layout_tree<'a>(
+
+/// Transform a style tree into a layout tree, laid out in `containing_block`, the viewport.
+///
+/// ```
+/// use robinson::layout::{layout_tree, Dimensions, Rect};
+///
+/// let root = robinson::html::parse("<div></div>".to_string());
+/// let stylesheet = robinson::css::parse("div { display: block; padding: 10px; }".to_string());
+/// let styled = robinson::style::style_tree(&root, &stylesheet);
+/// let viewport = Dimensions::from_content(Rect {
+///     width: 300.0,
+///     ..Default::default()
+/// });
+/// let layout_root = layout_tree(&styled, viewport);
+/// assert_eq!(layout_root.dimensions.content.width, 280.0);
+/// assert_eq!(layout_root.dimensions.border_box().width, 300.0);
+/// ```
+pub fn layout_tree<'a>(node: &'a StyledNode<'a>, containing_block: Dimensions) -> LayoutBox<'a> {
+    layout_tree_scrolled(node, containing_block, 0.0)
+}
+
+/// Like `layout_tree`, with the viewport scrolled down by `scroll_y`. This only affects sticky
+/// boxes.
+pub fn layout_tree_scrolled<'a>(
     node: &'a StyledNode<'a>,
-    mut containing_block: Dimensions,
+    containing_block: Dimensions,
+    scroll_y: f32,
 ) -> LayoutBox<'a> {
-    // The layout algorithm expects the container height to start at 0.
-    // TODO: Save the initial containing block height, for calculating percent heights.
-    containing_block.content.height = 0.0;
-
     let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    root_box.layout_root_scrolled(containing_block, scroll_y);
+    root_box
+}
+
+/// Like `layout_tree`, but take the vectors the boxes keep their children and text in from
+/// `arena` rather than allocating new ones.
+pub fn layout_tree_in<'a>(
+    arena: &mut LayoutArena<'a>,
+    node: &'a StyledNode<'a>,
+    containing_block: Dimensions,
+) -> LayoutBox<'a> {
+    let mut root_box = build_box(node, &mut ContentState::default(), arena);
+    root_box.layout_root(containing_block);
     root_box
 }
 
+/// A pool of the vectors that layout boxes keep their children and text fragments in. Laying out
+/// a large document allocates a pair per box; a tree given back with `recycle` leaves its vectors
+/// here for `layout_tree_in` to reuse, so relaying out a page (for example, each time the window
+/// is resized) stops allocating once the pool is warm.
+#[derive(Default)]
+pub struct LayoutArena<'a> {
+    children: Vec<Vec<LayoutBox<'a>>>,
+    fragments: Vec<Vec<TextFragment>>,
+    max_anonymous_children: Option<usize>,
+}
+
+impl<'a> LayoutArena<'a> {
+    pub fn new() -> LayoutArena<'a> {
+        LayoutArena::default()
+    }
+
+    /// Generate at most `max` anonymous block boxes for the inline content of a single block.
+    /// Inline content that would need another one is dropped, so that pathological documents
+    /// can't make the layout tree balloon. There's no limit by default.
+    pub fn with_max_anonymous_children(mut self, max: usize) -> LayoutArena<'a> {
+        self.max_anonymous_children = Some(max);
+        self
+    }
+
+    /// Take apart a layout tree, keeping its (emptied) vectors for reuse.
+    pub fn recycle(&mut self, mut layout_box: LayoutBox<'a>) {
+        for child in layout_box.children.drain(..) {
+            self.recycle(child);
+        }
+        layout_box.fragments.clear();
+        self.children.push(layout_box.children);
+        self.fragments.push(layout_box.fragments);
+    }
+
+    /// The number of boxes' worth of vectors in the pool.
+    pub fn available(&self) -> usize {
+        self.children.len().min(self.fragments.len())
+    }
+
+    // A new box, with vectors from the pool if there are any.
+    fn new_box(&mut self, box_type: BoxType<'a>) -> LayoutBox<'a> {
+        let mut layout_box = LayoutBox::new(box_type);
+        if let Some(children) = self.children.pop() {
+            layout_box.children = children;
+        }
+        if let Some(fragments) = self.fragments.pop() {
+            layout_box.fragments = fragments;
+        }
+        layout_box
+    }
+}
+
 /// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
 fn build_layout_tree<'a>(styled_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+    build_box(
+        styled_node,
+        &mut ContentState::default(),
+        &mut LayoutArena::new(),
+    )
+}
+
+// The state of generated content at some point of the document: the CSS counters in scope,
+// innermost last, and how many quotes are open.
+#[derive(Default)]
+struct ContentState {
+    counters: Vec<(String, i32)>,
+    quote_depth: usize,
+}
+
+impl ContentState {
+    // Apply the `counter-reset` and then the `counter-increment` of an element. A reset starts a
+    // new counter for the element's descendants and following siblings.
+    fn update_counters(&mut self, style: &StyledNode) {
+        self.counters.extend(style.counters("counter-reset", 0));
+        for (name, by) in style.counters("counter-increment", 1) {
+            match self.counters.iter_mut().rev().find(|(n, _)| *n == name) {
+                Some(counter) => counter.1 += by,
+                None => self.counters.push((name, by)),
+            }
+        }
+    }
+
+    // The values of all the counters called `name`, outermost first.
+    fn counter_values(&self, name: &str) -> Vec<i32> {
+        self.counters
+            .iter()
+            .filter(|(n, _)| n == name)
+            .map(|&(_, value)| value)
+            .collect()
+    }
+
+    // The text of a `content` value of an element styled by `style`: strings, `counter(name)`,
+    // `counters(name, separator)`, and quotes, or `None` for `none`, `normal`, or anything else.
+    fn content_text(&mut self, content: &Value, style: &StyledNode) -> Option<String> {
+        let parts = match content {
+            Value::List(parts) => parts.as_slice(),
+            part => std::slice::from_ref(part),
+        };
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                Value::Str(s) => text.push_str(s),
+                Value::Function(function, args) => {
+                    let name = match args.first() {
+                        Some(Keyword(name)) => name,
+                        _ => return None,
+                    };
+                    let values = self.counter_values(name);
+                    let values = if function.eq_ignore_ascii_case("counter") {
+                        // The innermost counter, which is zero if there isn't one.
+                        vec![values.last().copied().unwrap_or(0)]
+                    } else if function.eq_ignore_ascii_case("counters") {
+                        values
+                    } else {
+                        return None;
+                    };
+                    let separator = match args.get(1) {
+                        Some(Value::Str(separator)) => separator.as_str(),
+                        _ => "",
+                    };
+                    let numbers: Vec<String> = values.iter().map(|n| n.to_string()).collect();
+                    text.push_str(&numbers.join(separator));
+                }
+                Keyword(keyword) => {
+                    // Quotes nested deeper than `quotes` lists use its innermost pair.
+                    let quotes = style.quotes();
+                    let pair = |depth: usize| quotes.get(depth.min(quotes.len().saturating_sub(1)));
+                    match keyword.to_ascii_lowercase().as_str() {
+                        "open-quote" => {
+                            if let Some((open, _)) = pair(self.quote_depth) {
+                                text.push_str(open);
+                            }
+                            self.quote_depth += 1;
+                        }
+                        "close-quote" if self.quote_depth > 0 => {
+                            self.quote_depth -= 1;
+                            if let Some((_, close)) = pair(self.quote_depth) {
+                                text.push_str(close);
+                            }
+                        }
+                        "no-open-quote" => self.quote_depth += 1,
+                        "no-close-quote" => self.quote_depth = self.quote_depth.saturating_sub(1),
+                        "close-quote" => {}
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(text)
+    }
+}
+
+fn build_box<'a>(
+    styled_node: &'a StyledNode<'a>,
+    content: &mut ContentState,
+    arena: &mut LayoutArena<'a>,
+) -> LayoutBox<'a> {
+    content.update_counters(styled_node);
+    let generated = |pseudo, state: &mut ContentState, arena: &mut LayoutArena<'a>| {
+        let value = styled_node.pseudo_element_value(pseudo, "content")?;
+        let text = state.content_text(&value, styled_node)?;
+        Some(arena.new_box(Marker(styled_node, text)))
+    };
+
     // Create the root box.
-    let mut root = LayoutBox::new(match styled_node.display() {
-        Display::Block => BlockNode(styled_node),
+    let mut root = arena.new_box(match styled_node.display() {
+        Display::Block
+        | Display::ListItem
+        | Display::Table
+        | Display::TableRow
+        | Display::TableCell
+        | Display::Flex
+        | Display::InlineBlock => BlockNode(styled_node),
         Display::Inline => InlineNode(styled_node),
         Display::None => panic!("Root node has display: none."),
     });
 
-    // Create the descendant boxes.
+    // A block whose contents are skipped has no descendant boxes.
+    if styled_node.skips_contents() && matches!(root.box_type, BlockNode(_)) {
+        return root;
+    }
+
+    // Create the descendant boxes. Counters started by the children are scoped to this box.
+    if let Some(before) = generated(PseudoElement::Before, content, arena) {
+        root.push_inline(before, arena);
+    }
+    let scope = content.counters.len();
+    let mut list_items = 0;
     for child in &styled_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
-                .get_inline_container()
-                .children
-                .push(build_layout_tree(child)),
+            // Rows and cells are only laid out as such by a table. We don't generate the
+            // anonymous table boxes that would wrap them elsewhere, so there they are blocks.
+            Display::Block
+            | Display::Table
+            | Display::TableRow
+            | Display::TableCell
+            | Display::Flex => root.children.push(build_box(child, content, arena)),
+            Display::ListItem => {
+                list_items += 1;
+                let mut item = build_box(child, content, arena);
+                item.insert_marker(list_items, arena);
+                root.children.push(item);
+            }
+            // An inline-block is a block box in the inline flow.
+            Display::Inline | Display::InlineBlock => {
+                let inline = build_box(child, content, arena);
+                root.push_inline(inline, arena);
+            }
             Display::None => {} // Skip nodes with `display: none;`
         }
     }
+    content.counters.truncate(scope);
+    if let Some(after) = generated(PseudoElement::After, content, arena) {
+        root.push_inline(after, arena);
+    }
 
     root
 }
 
-impl LayoutBox<'_> {
-    /// Lay out a box and its descendants.
-    fn layout(&mut self, containing_block: Dimensions) {
+impl<'a> LayoutBox<'a> {
+    /// Lay out this box as the root of a layout tree, in `containing_block` (usually the
+    /// viewport). Any previous layout is discarded first, so this can be called again, for
+    /// example after the viewport is resized.
+    pub fn layout_root(&mut self, containing_block: Dimensions) {
+        self.layout_root_scrolled(containing_block, 0.0);
+    }
+
+    /// Like `layout_root`, with the viewport scrolled down by `scroll_y`.
+    pub fn layout_root_scrolled(&mut self, mut containing_block: Dimensions, scroll_y: f32) {
+        self.reset_dimensions();
+
+        // Viewport-relative lengths are resolved against the initial containing block.
+        let font_size = self.get_style_node().font_size();
+        let ctx = ResolutionContext {
+            font_size,
+            root_font_size: font_size,
+            viewport: (
+                containing_block.content.width,
+                containing_block.content.height,
+            ),
+            containing_width: containing_block.content.width,
+            containing_height: Some(containing_block.content.height),
+        };
+
+        // The initial containing block is also the containing block of absolutely positioned
+        // boxes without a positioned ancestor.
+        let positioned = containing_block.padding_box();
+
+        // The layout algorithm expects the container height to start at 0.
+        let viewport = containing_block.content;
+        containing_block.content.height = 0.0;
+        self.layout(containing_block, &ctx, positioned);
+
+        // Sticky boxes are laid out as relative ones, then moved into view.
+        let scrollport = Rect {
+            y: viewport.y + scroll_y,
+            ..viewport
+        };
+        self.apply_sticky(scrollport, &ctx);
+    }
+
+    // Shift sticky descendants so that, as far as their containing block allows, they stay at
+    // least `top` below the top of `scrollport`, or `bottom` above its bottom.
+    fn apply_sticky(&mut self, scrollport: Rect, ctx: &ResolutionContext) {
+        let container = self.dimensions.content;
+        for child in &mut self.children {
+            if let BlockNode(style) = child.box_type {
+                if style.position() == Position::Sticky {
+                    let offset = |name| {
+                        let value = style.value(name).filter(Value::is_length)?;
+                        Some(value.resolve_height(ctx))
+                    };
+                    let border_box = child.dimensions.border_box();
+                    let margin_box = child.dimensions.margin_box();
+                    // How far the box may move down or up without leaving its container.
+                    let max_down = (container.y + container.height
+                        - (margin_box.y + margin_box.height))
+                        .max(0.0);
+                    let max_up = (margin_box.y - container.y).max(0.0);
+                    let mut dy = 0.0;
+                    if let Some(top) = offset("top") {
+                        dy = (scrollport.y + top - border_box.y).clamp(0.0, max_down);
+                    }
+                    if let Some(bottom) = offset("bottom").filter(|_| dy == 0.0) {
+                        let limit = scrollport.y + scrollport.height - bottom;
+                        dy = (limit - (border_box.y + border_box.height)).clamp(-max_up, 0.0);
+                    }
+                    child.translate(0.0, dy);
+                }
+            }
+            child.apply_sticky(scrollport, ctx);
+        }
+    }
+
+    // Move this box and its descendants right by `dx` and down by `dy`.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        for fragment in &mut self.fragments {
+            fragment.rect.x += dx;
+            fragment.rect.y += dy;
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    /// Zero the dimensions of this box and its descendants and drop their laid out text, leaving
+    /// the tree as it was before layout.
+    pub fn reset_dimensions(&mut self) {
+        self.dimensions = Default::default();
+        self.absolute_containing_block = Rect::default();
+        self.fragments.clear();
+        for child in &mut self.children {
+            child.reset_dimensions();
+        }
+    }
+
+    /// Lay out a box and its descendants. `positioned` is the padding box of the containing block
+    /// for absolutely positioned descendants.
+    fn layout(&mut self, containing_block: Dimensions, ctx: &ResolutionContext, positioned: Rect) {
         match self.box_type {
-            BlockNode(_) => self.layout_block(containing_block),
-            InlineNode(_) => {}  // TODO
-            AnonymousBlock => {} // TODO
+            BlockNode(style) => match style.display() {
+                Display::Table => self.layout_table(containing_block, ctx, positioned),
+                Display::Flex => self.layout_flex(containing_block, ctx, positioned),
+                _ => self.layout_block(containing_block, ctx, positioned),
+            },
+            InlineNode(_) => {} // TODO
+            // Laid out by its container, with `layout_anonymous`, which needs the container's
+            // text properties.
+            AnonymousBlock => {}
+            Marker(..) => {} // Laid out inline by its container.
         }
     }
 
-    fn layout_block(&mut self, containing_block: Dimensions) {
+    fn layout_block(
+        &mut self,
+        containing_block: Dimensions,
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) {
+        // Relative lengths of this box are resolved against its own font size and its container.
+        // The container's height, if definite, was passed down in `ctx`.
+        let ctx = ResolutionContext {
+            font_size: self.get_style_node().font_size(),
+            containing_width: containing_block.content.width,
+            ..*ctx
+        };
+
         // child width can depend on parent width, so we need to calculate
         // this box's width before laying out its children.
-        self.calculate_block_width(containing_block);
+        self.calculate_block_width(containing_block, &ctx);
 
         // Determine where the box is located within its container.
-        self.calculate_block_position(containing_block);
+        self.calculate_block_position(containing_block, &ctx);
 
         // recursively lay out the children of this box.
-        self.layout_block_children();
+        let absolute = self.layout_block_children(&ctx, positioned);
 
         // Parent height can depend on child height, so `calculate_height`
         // must be called *after* the children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(&ctx);
+
+        // Absolutely positioned children may depend on this box's height, if it is positioned.
+        self.layout_absolute_children(absolute, &ctx, positioned);
+
+        self.apply_relative_offset(&ctx);
+    }
+
+    // Shift a relatively positioned box and its descendants by its `left` or `right` and `top`
+    // or `bottom` offsets. The space it took in the flow is left where it was, so its siblings
+    // don't move. If both offsets on an axis are given, `left` and `top` win.
+    fn apply_relative_offset(&mut self, ctx: &ResolutionContext) {
+        let style = self.get_style_node();
+        if style.position() != Position::Relative {
+            return;
+        }
+        let offset = |name| style.value(name).filter(Value::is_length);
+        let dx = match (offset("left"), offset("right")) {
+            (Some(left), _) => left.resolve(ctx),
+            (None, Some(right)) => -right.resolve(ctx),
+            (None, None) => 0.0,
+        };
+        let dy = match (offset("top"), offset("bottom")) {
+            (Some(top), _) => top.resolve_height(ctx),
+            (None, Some(bottom)) => -bottom.resolve_height(ctx),
+            (None, None) => 0.0,
+        };
+        self.translate(dx, dy);
+    }
+
+    /// Lay out an absolutely positioned (or fixed) box, relative to `positioned`, the padding box
+    /// of its containing block. `static_position` is where the box would have started in the
+    /// normal flow, which is used for any offset that isn't specified.
+    fn layout_absolute(
+        &mut self,
+        static_position: (f32, f32),
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) {
+        self.static_position = static_position;
+        let style = self.get_style_node();
+        // Percentages are of the containing block: its width for left and right, and its height
+        // for top and bottom.
+        let ctx = ResolutionContext {
+            font_size: style.font_size(),
+            containing_width: positioned.width,
+            containing_height: Some(positioned.height),
+            ..*ctx
+        };
+        let offset = |name| style.value(name).filter(Value::is_length);
+        let left = offset("left").map(|v| v.resolve(&ctx));
+        let right = offset("right").map(|v| v.resolve(&ctx));
+        let top = offset("top").map(|v| v.resolve_height(&ctx));
+        let bottom = offset("bottom").map(|v| v.resolve_height(&ctx));
+
+        // Auto margins are treated as zero, except for vertical ones in the case below.
+        let zero = Value::px(0.0);
+        let edge = |name, fallback| style.lookup(name, fallback, &zero).resolve(&ctx);
+        let d = &mut self.dimensions;
+        d.margin = EdgeSizes {
+            left: edge("margin-left", "margin"),
+            right: edge("margin-right", "margin"),
+            top: edge("margin-top", "margin"),
+            bottom: edge("margin-bottom", "margin"),
+        };
+        d.border = EdgeSizes {
+            left: style.border_width("left").resolve(&ctx),
+            right: style.border_width("right").resolve(&ctx),
+            top: style.border_width("top").resolve(&ctx),
+            bottom: style.border_width("bottom").resolve(&ctx),
+        };
+        d.padding = EdgeSizes {
+            left: edge("padding-left", "padding"),
+            right: edge("padding-right", "padding"),
+            top: edge("padding-top", "padding"),
+            bottom: edge("padding-bottom", "padding"),
+        };
+        let (before_x, after_x) = (
+            d.margin.left + d.border.left + d.padding.left,
+            d.margin.right + d.border.right + d.padding.right,
+        );
+        let (before_y, after_y) = (
+            d.margin.top + d.border.top + d.padding.top,
+            d.margin.bottom + d.border.bottom + d.padding.bottom,
+        );
+
+        // With an auto width, the box stretches between its left and right offsets if both are
+        // given, and otherwise shrinks to fit its content.
+        let available = positioned.width - left.unwrap_or(0.0) - right.unwrap_or(0.0);
+        let available = (available - before_x - after_x).max(0.0);
+        let width = match style.value("width") {
+            Some(width @ Length(..)) => width.resolve(&ctx),
+            _ if left.is_some() && right.is_some() => available,
+            _ => {
+                let (min, max) = self.intrinsic_widths();
+                min.max(available).min(max)
+            }
+        };
+        let d = &mut self.dimensions;
+        d.content.width = width;
+        d.content.x = match (left, right) {
+            (Some(left), _) => positioned.x + left + before_x,
+            (None, Some(right)) => positioned.x + positioned.width - right - after_x - width,
+            (None, None) => static_position.0 + before_x,
+        };
+        d.content.y = match top {
+            Some(top) => positioned.y + top + before_y,
+            None => static_position.1 + before_y,
+        };
+        d.content.height = 0.0;
+
+        let absolute = self.layout_block_children(&ctx, positioned);
+
+        let d = &mut self.dimensions;
+        match (style.value("height"), top, bottom) {
+            (Some(height @ Length(..)), _, _) => d.content.height = height.resolve_height(&ctx),
+            (_, Some(top), Some(bottom)) => {
+                d.content.height = (positioned.height - top - bottom - before_y - after_y).max(0.0)
+            }
+            _ => {}
+        }
+        if let (None, Some(bottom)) = (top, bottom) {
+            d.content.y = positioned.y + positioned.height - bottom - after_y - d.content.height;
+        }
+
+        // With the height and both vertical offsets given, auto vertical margins take up the
+        // space left over, split evenly (centering the box) if both are auto.
+        let is_auto = |name| style.lookup(name, "margin", &zero).keyword_eq("auto");
+        let (auto_top, auto_bottom) = (is_auto("margin-top"), is_auto("margin-bottom"));
+        if let (Some(Length(..)), Some(top), Some(bottom)) = (style.value("height"), top, bottom) {
+            let leftover = positioned.height - top - bottom - before_y - after_y - d.content.height;
+            let old_top = d.margin.top;
+            match (auto_top, auto_bottom) {
+                (true, true) => {
+                    d.margin.top = leftover / 2.0;
+                    d.margin.bottom = leftover / 2.0;
+                }
+                (true, false) => d.margin.top = leftover,
+                (false, true) => d.margin.bottom = leftover,
+                (false, false) => {}
+            }
+            let dy = d.margin.top - old_top;
+            self.translate(0.0, dy);
+        }
+
+        self.layout_absolute_children(absolute, &ctx, positioned);
+    }
+
+    // Lay out the absolutely positioned children found by `layout_block_children`, now that this
+    // box's size is known.
+    fn layout_absolute_children(
+        &mut self,
+        absolute: Vec<(usize, f32)>,
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) {
+        let positioned = self.positioned_block(positioned);
+        self.absolute_containing_block = positioned;
+        // Absolutely positioned boxes in static descendants were placed against this box's
+        // height so far. Now that it's known, place them again.
+        if self.is_positioned() {
+            for child in &mut self.children {
+                child.relayout_nested_absolute(ctx, positioned);
+            }
+        }
+        for (i, static_y) in absolute {
+            let child = &mut self.children[i];
+            let child_positioned = match child.get_style_node().position() {
+                Position::Fixed => Rect {
+                    x: 0.0,
+                    y: 0.0,
+                    width: ctx.viewport.0,
+                    height: ctx.viewport.1,
+                },
+                _ => positioned,
+            };
+            child.layout_absolute((self.dimensions.content.x, static_y), ctx, child_positioned);
+        }
+    }
+
+    // Place again the absolutely positioned boxes in this box and its static descendants, against
+    // `positioned`, the final padding box of their nearest positioned ancestor.
+    fn relayout_nested_absolute(&mut self, ctx: &ResolutionContext, positioned: Rect) {
+        if !self.is_block() || self.is_positioned() {
+            return;
+        }
+        self.absolute_containing_block = positioned;
+        for child in &mut self.children {
+            if !child.is_out_of_flow() {
+                child.relayout_nested_absolute(ctx, positioned);
+            } else if child.get_style_node().position() == Position::Absolute {
+                child.reset_dimensions();
+                child.layout_absolute(child.static_position, ctx, positioned);
+            }
+        }
+    }
+
+    fn is_positioned(&self) -> bool {
+        matches!(self.box_type, BlockNode(style) if style.position() != Position::Static)
+    }
+
+    // The containing block for absolutely positioned descendants: this box's padding box if it
+    // is positioned, or else `positioned`, that of its ancestors.
+    fn positioned_block(&self, positioned: Rect) -> Rect {
+        if self.is_positioned() {
+            self.dimensions.padding_box()
+        } else {
+            positioned
+        }
+    }
+
+    // Is this box taken out of the normal flow by absolute or fixed positioning?
+    fn is_out_of_flow(&self) -> bool {
+        match self.box_type {
+            BlockNode(style) => matches!(style.position(), Position::Absolute | Position::Fixed),
+            _ => false,
+        }
+    }
+
+    /// Lay out a flex container and its items, in a single row. Each item is as wide as its
+    /// `flex-basis`, its `width`, or else its content, and the free space is shared out by
+    /// `justify-content`. Items don't grow, shrink, or wrap.
+    fn layout_flex(
+        &mut self,
+        containing_block: Dimensions,
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) {
+        let style = self.get_style_node();
+        let ctx = ResolutionContext {
+            font_size: style.font_size(),
+            containing_width: containing_block.content.width,
+            ..*ctx
+        };
+        self.calculate_block_width(containing_block, &ctx);
+        self.calculate_block_position(containing_block, &ctx);
+
+        // Items are sized against the container, like blocks.
+        let item_ctx = ResolutionContext {
+            containing_width: self.dimensions.content.width,
+            containing_height: self.definite_height(&ctx),
+            ..ctx
+        };
+        let mut absolute = Vec::new();
+        let mut items = Vec::new();
+        for (i, child) in self.children.iter().enumerate() {
+            if child.is_out_of_flow() {
+                absolute.push((i, self.dimensions.content.y));
+                continue;
+            }
+            let outer_width = match child.box_type {
+                BlockNode(item) => {
+                    let basis = ["flex-basis", "width"]
+                        .iter()
+                        .find_map(|&name| item.value(name).filter(Value::is_length))
+                        .map(|value| value.resolve(&item_ctx))
+                        .unwrap_or_else(|| child.intrinsic_widths().1);
+                    basis + horizontal_edges(item)
+                }
+                _ => child.intrinsic_widths().1,
+            };
+            items.push((i, outer_width));
+        }
+
+        let content = self.dimensions.content;
+        let free = (content.width - items.iter().map(|&(_, w)| w).sum::<f32>()).max(0.0);
+        let (mut x, spacing) = match style.justify_content() {
+            JustifyContent::FlexStart => (0.0, 0.0),
+            JustifyContent::Center => (free / 2.0, 0.0),
+            JustifyContent::SpaceBetween if items.len() > 1 => {
+                (0.0, free / (items.len() - 1) as f32)
+            }
+            JustifyContent::SpaceBetween => (0.0, 0.0),
+        };
+        let mut row_height: f32 = 0.0;
+        for (n, &(i, outer_width)) in items.iter().enumerate() {
+            let mut slot = self.dimensions;
+            slot.content.x = content.x + x;
+            slot.content.width = outer_width;
+            slot.content.height = 0.0;
+            let child = &mut self.children[i];
+            match child.box_type {
+                AnonymousBlock => child.layout_anonymous(slot, style, n == 0, &ctx, positioned),
+                _ => child.layout(slot, &ctx, positioned),
+            }
+            row_height = row_height.max(child.dimensions.margin_box().height);
+            x += outer_width + spacing;
+        }
+
+        // Stretch items with an auto height to the height of the row.
+        if style.align_items() == AlignItems::Stretch {
+            for &(i, _) in &items {
+                let child = &mut self.children[i];
+                let auto_height = match child.box_type {
+                    BlockNode(item) => !item.value("height").is_some_and(|h| h.is_length()),
+                    _ => true,
+                };
+                if auto_height {
+                    let d = &mut child.dimensions;
+                    d.content.height += row_height - d.margin_box().height;
+                }
+            }
+        }
+        self.dimensions.content.height = row_height;
+        self.calculate_block_height(&ctx);
+        self.layout_absolute_children(absolute, &ctx, positioned);
+    }
+
+    /// Lay out a table: its block children are rows, and their block children are cells. Each
+    /// column is as wide as the widest content of its cells, and each row as tall as its tallest
+    /// cell.
+    fn layout_table(
+        &mut self,
+        containing_block: Dimensions,
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) {
+        let style = self.get_style_node();
+        let ctx = ResolutionContext {
+            font_size: style.font_size(),
+            containing_width: containing_block.content.width,
+            ..*ctx
+        };
+
+        // Size each column to the widest cell in it.
+        let mut columns: Vec<f32> = Vec::new();
+        for row in self.block_children() {
+            for (i, cell) in row.block_children().enumerate() {
+                let cell_style = cell.get_style_node();
+                let width = cell.intrinsic_widths().1 + horizontal_edges(cell_style);
+                match columns.get_mut(i) {
+                    Some(column) => *column = column.max(width),
+                    None => columns.push(width),
+                }
+            }
+        }
+
+        self.calculate_block_width(containing_block, &ctx);
+        self.calculate_block_position(containing_block, &ctx);
+        // An auto-width table shrinks to fit its columns.
+        let columns_width: f32 = columns.iter().sum();
+        if style.value("width").is_none() && columns_width < self.dimensions.content.width {
+            self.dimensions.margin.right += self.dimensions.content.width - columns_width;
+            self.dimensions.content.width = columns_width;
+        }
+
+        let table = self.dimensions.content;
+        let mut y = table.y;
+        for row in self.children.iter_mut().filter(|row| row.is_block()) {
+            let mut x = table.x;
+            let mut height: f32 = 0.0;
+            for (cell, &width) in row
+                .children
+                .iter_mut()
+                .filter(|cell| cell.is_block())
+                .zip(&columns)
+            {
+                let cell_block = Dimensions::from_content(Rect {
+                    x,
+                    y,
+                    width,
+                    height: 0.0,
+                });
+                cell.layout_block(cell_block, &ctx, positioned);
+                height = height.max(cell.dimensions.margin_box().height);
+                x += width;
+            }
+            row.dimensions.content = Rect {
+                x: table.x,
+                y,
+                width: table.width,
+                height,
+            };
+            y += height;
+        }
+        self.dimensions.content.height = y - table.y;
+        self.calculate_block_height(&ctx);
+    }
+
+    fn is_block(&self) -> bool {
+        matches!(self.box_type, BlockNode(_))
+    }
+
+    // The block-level children of a box: the rows of a table, or the cells of a row.
+    fn block_children(&self) -> impl Iterator<Item = &Self> {
+        self.children.iter().filter(|child| child.is_block())
     }
 
-    fn calculate_block_width(&mut self, containing_block: Dimensions) {
+    fn calculate_block_width(&mut self, containing_block: Dimensions, ctx: &ResolutionContext) {
         let style = self.get_style_node();
 
         // `width` has initial value `auto`
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        let mut width = match style.value("width") {
+            Some(ref value) if value.keyword_eq("min-content") => {
+                Value::px(self.intrinsic_widths().0)
+            }
+            Some(ref value) if value.keyword_eq("max-content") => {
+                Value::px(self.intrinsic_widths().1)
+            }
+            // A value that isn't a length, like a color set by mistake, is ignored.
+            value => value.filter(Value::is_length).unwrap_or(auto),
+        };
+        // An auto width follows a definite height through `aspect-ratio`.
+        if width.keyword_eq("auto") {
+            if let (Some(ratio), Some(height)) = (style.aspect_ratio(), self.definite_height(ctx)) {
+                width = Value::px(height * ratio);
+            }
+        }
+        let border_box = style
+            .value_ref("box-sizing")
+            .is_some_and(|value| value.keyword_eq("border-box"));
+        self.solve_block_width(containing_block, ctx, width, border_box);
+
+        // Clamp the used width to `max-width` and then `min-width`, so the minimum wins if they
+        // conflict, and solve again with the clamped width. Like `width`, they size the border
+        // box under `box-sizing: border-box`.
+        let sized_width = |d: &Dimensions| {
+            if border_box {
+                d.border_box().width
+            } else {
+                d.content.width
+            }
+        };
+        let limit = |name| style.value_ref(name).filter(|v| v.is_length());
+        if let Some(max_width) = limit("max-width").map(|v| v.resolve(ctx)) {
+            if sized_width(&self.dimensions) > max_width {
+                let width = Value::px(max_width);
+                self.solve_block_width(containing_block, ctx, width, border_box);
+            }
+        }
+        if let Some(min_width) = limit("min-width").map(|v| v.resolve(ctx)) {
+            if sized_width(&self.dimensions) < min_width {
+                let width = Value::px(min_width);
+                self.solve_block_width(containing_block, ctx, width, border_box);
+            }
+        }
+    }
+
+    // Set the horizontal dimensions of a block from its `width`, which may be `auto`, and its
+    // horizontal margins, borders, and padding. If `border_box` is true, a definite `width`
+    // includes the borders and padding.
+    fn solve_block_width(
+        &mut self,
+        containing_block: Dimensions,
+        ctx: &ResolutionContext,
+        mut width: Value,
+        border_box: bool,
+    ) {
+        let style = self.get_style_node();
 
         // margin, border, and padding have initial value 0.
-        let zero = Length(0.0, Px);
+        let zero = Value::px(0.0);
 
         let mut margin_left = style.lookup("margin-left", "margin", &zero);
         let mut margin_right = style.lookup("margin-right", "margin", &zero);
 
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
+        let border_left = style.border_width("left");
+        let border_right = style.border_width("right");
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.lookup_ref("padding-left", "padding", &zero);
+        let padding_right = style.lookup_ref("padding-right", "padding", &zero);
+
+        if border_box && !width.keyword_eq("auto") {
+            let edges = [&border_left, &border_right, padding_left, padding_right];
+            let content = width.resolve(ctx) - sum(edges.iter().map(|v| v.resolve(ctx)));
+            width = Value::px(content.max(0.0));
+        }
 
         let total = sum([
             &margin_left,
             &margin_right,
             &border_left,
             &border_right,
-            &padding_left,
-            &padding_right,
+            padding_left,
+            padding_right,
             &width,
         ]
         .iter()
-        .map(|v| v.to_px()));
+        .map(|v| v.resolve(ctx)));
 
         // if width is not auto and the total is wider than the container, treat auto margins as 0.
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = Length(0.0, Px);
+        if !width.keyword_eq("auto") && total > containing_block.content.width {
+            if margin_left.keyword_eq("auto") {
+                margin_left = Value::px(0.0);
             }
-            if margin_right == auto {
-                margin_right = Length(0.0, Px);
+            if margin_right.keyword_eq("auto") {
+                margin_right = Value::px(0.0);
             }
         }
 
         let underflow = containing_block.content.width - total;
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
+        match (
+            width.keyword_eq("auto"),
+            margin_left.keyword_eq("auto"),
+            margin_right.keyword_eq("auto"),
+        ) {
             // If the values are overconstrained, calculate margin_riaght.
             (false, false, false) => {
-                margin_right = Length(margin_right.to_px() + underflow, Px);
+                margin_right = Value::px(margin_right.resolve(ctx) + underflow);
             }
             // if exactly one size is auto, its used value follows from the equality.
             (false, false, true) => {
-                margin_right = Length(underflow, Px);
+                margin_right = Value::px(underflow);
             }
             (false, true, false) => {
-                margin_left = Length(underflow, Px);
+                margin_left = Value::px(underflow);
             }
 
             // If width is set to auto, any other auto values become 0.
             (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Length(0.0, Px);
+                if margin_left.keyword_eq("auto") {
+                    margin_left = Value::px(0.0);
                 }
-                if margin_right == auto {
-                    margin_right = Length(0.0, Px);
+                if margin_right.keyword_eq("auto") {
+                    margin_right = Value::px(0.0);
                 }
 
                 if underflow >= 0.0 {
                     // Expand width to fill the underflow.
-                    width = Length(underflow, Px);
+                    width = Value::px(underflow);
                 } else {
                     // Width can't be negative. Adjust the right margin instead.
-                    width = Length(0.0, Px);
-                    margin_right = Length(margin_right.to_px() + underflow, Px);
+                    width = Value::px(0.0);
+                    margin_right = Value::px(margin_right.resolve(ctx) + underflow);
                 }
             }
             // If margin-left and margin-right are both auto, their used values are equal.
             (false, true, true) => {
-                margin_left = Length(underflow / 2.0, Px);
-                margin_right = Length(underflow / 2.0, Px);
+                margin_left = Value::px(underflow / 2.0);
+                margin_right = Value::px(underflow / 2.0);
             }
         }
 
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
+        d.content.width = width.resolve(ctx);
 
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.padding.left = padding_left.resolve(ctx);
+        d.padding.right = padding_right.resolve(ctx);
 
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+        d.border.left = border_left.resolve(ctx);
+        d.border.right = border_right.resolve(ctx);
 
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
+        d.margin.left = margin_left.resolve(ctx);
+        d.margin.right = margin_right.resolve(ctx);
     }
 
-    fn calculate_block_position(&mut self, containing_block: Dimensions) {
+    fn calculate_block_position(&mut self, containing_block: Dimensions, ctx: &ResolutionContext) {
         let style = self.get_style_node();
         let d = &mut self.dimensions;
 
         // margin, border, and padding have initial value 0.
-        let zero = Length(0.0, Px);
+        let zero = Value::px(0.0);
 
         // If margin-top or margin-bottom is `auto`, the used value is zero.
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = style.lookup_ref("margin-top", "margin", &zero).resolve(ctx);
+        d.margin.bottom = style
+            .lookup_ref("margin-bottom", "margin", &zero)
+            .resolve(ctx);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.border_width("top").resolve(ctx);
+        d.border.bottom = style.border_width("bottom").resolve(ctx);
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = style
+            .lookup_ref("padding-top", "padding", &zero)
+            .resolve(ctx);
+        d.padding.bottom = style
+            .lookup_ref("padding-bottom", "padding", &zero)
+            .resolve(ctx);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -246,44 +1271,636 @@ impl LayoutBox<'_> {
             + d.padding.top;
     }
 
-    fn layout_block_children(&mut self) {
-        for child in &mut self.children {
-            child.layout(self.dimensions);
-            // Increment the height so each child is laid out below the previous one.
-            self.dimensions.content.height += child.dimensions.margin_box().height;
+    /// Lay out the children of a block in the normal flow. Absolutely positioned children are
+    /// skipped, and returned with the y coordinate where each would have been placed.
+    fn layout_block_children(
+        &mut self,
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) -> Vec<(usize, f32)> {
+        let style = self.get_style_node();
+        let vertical = style.writing_mode() == WritingMode::VerticalRl;
+        // In vertical-rl, the width taken by the children so far, from the right.
+        let mut used_width = 0.0;
+        // Only the first child can hold the block's first line.
+        let mut first = true;
+        // The space between each child in the flow and the next, besides their margins.
+        let gap = style.row_gap().resolve(ctx);
+        // The bottom margin of the previous child in the flow, which collapses with the top
+        // margin of the next unless a gap separates them.
+        let mut prev_margin_bottom = None;
+        // Percentage heights of the children are of this box's height, if it's known yet.
+        let child_ctx = ResolutionContext {
+            containing_height: self.definite_height(ctx),
+            ..*ctx
+        };
+        let mut absolute = Vec::new();
+        for (i, child) in self.children.iter_mut().enumerate() {
+            if child.is_out_of_flow() {
+                absolute.push((
+                    i,
+                    self.dimensions.content.y + self.dimensions.content.height,
+                ));
+                continue;
+            }
+            // The height of a positioned box isn't known yet, so its descendants are positioned
+            // against its height so far.
+            let child_positioned = match style.position() {
+                Position::Static => positioned,
+                _ => self.dimensions.padding_box(),
+            };
+            if !first {
+                if vertical {
+                    used_width += gap;
+                } else {
+                    self.dimensions.content.height += gap;
+                }
+            }
+            // Side by side, every child starts at the top.
+            let mut container = self.dimensions;
+            if vertical {
+                container.content.height = 0.0;
+            }
+            match child.box_type {
+                AnonymousBlock => {
+                    child.layout_anonymous(container, style, first, &child_ctx, child_positioned)
+                }
+                _ => child.layout(container, &child_ctx, child_positioned),
+            }
+            first = false;
+            if vertical {
+                // Margins aren't stretched to fill the width of a container that children sit
+                // side by side in.
+                if let BlockNode(child_style) = child.box_type {
+                    child.dimensions.margin.right = child_style
+                        .lookup("margin-right", "margin", &Value::px(0.0))
+                        .resolve(ctx);
+                }
+            }
+            let margin_box = child.dimensions.margin_box();
+            if vertical {
+                // Move the child left of the previous one, and make this box as tall as the
+                // tallest child.
+                let content = self.dimensions.content;
+                let dx = content.x + content.width - used_width - margin_box.width - margin_box.x;
+                child.translate(dx, 0.0);
+                used_width += margin_box.width;
+                self.dimensions.content.height = content.height.max(margin_box.height);
+            } else {
+                // Move the child up by however much its top margin overlaps the previous
+                // child's bottom margin.
+                let margin = child.dimensions.margin;
+                let overlap = match prev_margin_bottom {
+                    Some(prev) if gap == 0.0 => {
+                        prev + margin.top - collapse_margins(prev, margin.top)
+                    }
+                    _ => 0.0,
+                };
+                child.translate(0.0, -overlap);
+                prev_margin_bottom = Some(margin.bottom);
+                // Increment the height so each child is laid out below the previous one.
+                self.dimensions.content.height += margin_box.height - overlap;
+            }
         }
+        absolute
     }
 
-    fn calculate_block_height(&mut self) {
+    fn calculate_block_height(&mut self, ctx: &ResolutionContext) {
         // If the height is set to an explicit length, use that exact length.
-        // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        // Otherwise (`height: auto`), just keep the value set by `layout_block_children`,
+        // which includes the line boxes of any inline content.
+        // An auto height with an `aspect-ratio` follows the width instead, and one whose contents
+        // are skipped comes from `contain-intrinsic-size`.
+        // A percentage of a container whose height depends on its contents counts as `auto`.
+        let style = self.get_style_node();
+        if let Some(height) = self.definite_height(ctx) {
+            self.dimensions.content.height = height;
+        } else if let Some(ratio) = style.aspect_ratio() {
+            self.dimensions.content.height = self.dimensions.content.width / ratio;
+        } else if let (true, (_, Some(height))) =
+            (self.skipped_contents(), style.contain_intrinsic_size())
+        {
+            self.dimensions.content.height = height.resolve(ctx);
         }
     }
 
-    /// Where a new inline child should go.
-    fn get_inline_container(&mut self) -> &mut Self {
-        match self.box_type {
-            InlineNode(_) | AnonymousBlock => self,
-            BlockNode(_) => {
-                // If we've just generated an anonymous block box, keep using it.
-                // Otherwise, create a new one.
-                match self.children.last() {
-                    Some(&LayoutBox {
-                        box_type: AnonymousBlock,
-                        ..
-                    }) => {}
-                    _ => self.children.push(LayoutBox::new(AnonymousBlock)),
-                }
-                self.children.last_mut().unwrap()
-            }
+    // The content height set by this box's `height`, which is known before its children are laid
+    // out, or `None` if it's `auto` or a percentage of a container whose height isn't known.
+    fn definite_height(&self, ctx: &ResolutionContext) -> Option<f32> {
+        let height = self
+            .get_style_node()
+            .value_ref("height")
+            .filter(|v| v.is_length())?;
+        if height.has_percentage() && ctx.containing_height.is_none() {
+            return None;
         }
+        Some(height.resolve_height(ctx))
     }
-}
+
+    /// Lay out the inline children of an anonymous block as lines of text, using the text
+    /// properties of `block`, the block container it belongs to. If `first` is true, this box
+    /// holds the block's first line, which is styled by its `::first-line`. `ctx` and
+    /// `positioned` are passed on to the layout of any inline-blocks.
+    fn layout_anonymous(
+        &mut self,
+        containing_block: Dimensions,
+        block: &StyledNode,
+        first: bool,
+        ctx: &ResolutionContext,
+        positioned: Rect,
+    ) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+
+        let first_line_color = block
+            .pseudo_element_value(PseudoElement::FirstLine, "color")
+            .and_then(|value| value.to_color())
+            .filter(|_| first);
+        let mut lines = LineLayout {
+            first_line_color,
+            breaker: LineBreaker::new(d.content.width),
+            origin: d.content,
+            block,
+            metrics: Vec::new(),
+            ctx: ResolutionContext {
+                containing_width: d.content.width,
+                ..*ctx
+            },
+            positioned,
+        };
+        for child in &mut self.children {
+            child.layout_inline(&mut lines, VerticalAlign::Baseline);
+        }
+
+        // Stack the line boxes. Each is at least as tall as a line of the block's own text, and
+        // is moved across the block by `text-align` into the space its content doesn't fill.
+        let strut = baseline_offset(block.font_size(), block.line_height());
+        let text_align = block.text_align();
+        let mut line_boxes = Vec::new();
+        let mut top = 0.0;
+        for i in 0..lines.breaker.line_count() {
+            let mut metrics = lines.metrics.get(i).copied().unwrap_or_default();
+            metrics.add(VerticalAlign::Baseline, strut, block.line_height());
+            let height = metrics.height();
+            let free = (lines.origin.width - lines.breaker.line_width(i)).max(0.0);
+            line_boxes.push(LineBox {
+                top: lines.origin.y + top,
+                height,
+                baseline: metrics.ascent,
+                offset: match text_align {
+                    TextAlign::Left => 0.0,
+                    TextAlign::Right => free,
+                    TextAlign::Center => free / 2.0,
+                },
+            });
+            top += height;
+        }
+        for child in &mut self.children {
+            child.align_inline(&line_boxes, VerticalAlign::Baseline);
+        }
+        self.dimensions.content.height = top;
+    }
+
+    /// Place the text of an inline box and its descendants on the lines of `lines`. Fragments
+    /// are positioned vertically later, by `align_inline`, once the heights of the lines are known.
+    fn layout_inline(&mut self, lines: &mut LineLayout, align: VerticalAlign) {
+        let align = self.vertical_align(align);
+        if let BlockNode(_) = self.box_type {
+            self.layout_inline_block(lines, align);
+            return;
+        }
+        let (start_edge, end_edge) = self.set_inline_edges(&lines.ctx);
+        lines.breaker.open_box(start_edge);
+        if let Some((width, height)) = self.replaced_size() {
+            let (font_size, white_space) = (lines.block.font_size(), lines.block.white_space());
+            let (line, x) = lines.breaker.push_atomic(width, font_size, white_space);
+            // It sits on the baseline by its bottom edge.
+            lines.line_metrics(line).add(align, height, height);
+            self.line = line;
+            self.dimensions.content = Rect {
+                x: lines.origin.x + x,
+                y: 0.0,
+                width,
+                height,
+            };
+        } else if let Some(text) = self.text() {
+            // Case changes apply to the laid out text only, not to the document.
+            let mut text = lines.block.text_transform().apply(text);
+            if !lines.block.white_space().collapses_spaces() {
+                text = expand_tabs(&text, lines.block.tab_size());
+            }
+            let style = self.get_style_node();
+            let (font_size, line_height) = (style.font_size(), style.line_height());
+            let baseline = baseline_offset(font_size, line_height);
+            let (mode, white_space) = (lines.block.wrap_mode(), lines.block.white_space());
+            for piece in lines.breaker.push(&text, font_size, mode, white_space) {
+                lines
+                    .line_metrics(piece.line)
+                    .add(align, baseline, line_height);
+                self.fragments.push(TextFragment {
+                    rect: Rect {
+                        x: lines.origin.x + piece.x,
+                        y: 0.0,
+                        width: piece.width,
+                        height: line_height,
+                    },
+                    line: piece.line,
+                    text: piece.text,
+                    color: lines.first_line_color.filter(|_| piece.line == 0),
+                });
+            }
+        }
+        if self.replaced_size().is_none() {
+            for child in &mut self.children {
+                child.layout_inline(lines, align);
+            }
+        }
+        lines.breaker.close_box(end_edge);
+    }
+
+    // Lay out an inline-block as a block, then place it on a line as a single unbreakable box.
+    // An auto width shrinks to fit the content, as for an absolutely positioned box.
+    fn layout_inline_block(&mut self, lines: &mut LineLayout, align: VerticalAlign) {
+        let style = self.get_style_node();
+        let available = lines.origin.width;
+        let mut container = Dimensions::default();
+        container.content.width = available;
+        if !style.value("width").is_some_and(|width| width.is_length()) {
+            let (min, max) = self.intrinsic_widths();
+            let edges = horizontal_edges(style);
+            container.content.width = min.max(available - edges).min(max) + edges;
+        }
+        self.layout(container, &lines.ctx, lines.positioned);
+
+        // Its horizontal margins are as specified, with auto ones zero, rather than whatever
+        // fills the container.
+        let ctx = ResolutionContext {
+            font_size: style.font_size(),
+            containing_width: available,
+            ..lines.ctx
+        };
+        let zero = Value::px(0.0);
+        self.dimensions.margin.left = style.lookup("margin-left", "margin", &zero).resolve(&ctx);
+        self.dimensions.margin.right = style.lookup("margin-right", "margin", &zero).resolve(&ctx);
+
+        let margin_box = self.dimensions.margin_box();
+        let (font_size, white_space) = (lines.block.font_size(), lines.block.white_space());
+        let (line, x) = lines
+            .breaker
+            .push_atomic(margin_box.width, font_size, white_space);
+        // Like a replaced box, it sits on the baseline by its bottom margin edge.
+        let height = margin_box.height;
+        lines.line_metrics(line).add(align, height, height);
+        self.line = line;
+        self.translate(lines.origin.x + x - margin_box.x, -margin_box.y);
+    }
+
+    // Set the horizontal margins, borders, and padding of an inline element, resolved in the
+    // context `ctx` of its line, and return their total widths at its start and end. Inline boxes
+    // have no vertical edges here.
+    fn set_inline_edges(&mut self, ctx: &ResolutionContext) -> (f32, f32) {
+        let style = match self.box_type {
+            InlineNode(style) if style.node.as_text().is_none() => style,
+            _ => return (0.0, 0.0),
+        };
+        let ctx = ResolutionContext {
+            font_size: style.font_size(),
+            ..*ctx
+        };
+        let zero = Value::px(0.0);
+        let d = &mut self.dimensions;
+        d.margin.left = style.lookup("margin-left", "margin", &zero).resolve(&ctx);
+        d.margin.right = style.lookup("margin-right", "margin", &zero).resolve(&ctx);
+        d.border.left = style.border_width("left").resolve(&ctx);
+        d.border.right = style.border_width("right").resolve(&ctx);
+        d.padding.left = style.lookup("padding-left", "padding", &zero).resolve(&ctx);
+        d.padding.right = style
+            .lookup("padding-right", "padding", &zero)
+            .resolve(&ctx);
+        (
+            d.margin.left + d.border.left + d.padding.left,
+            d.margin.right + d.border.right + d.padding.right,
+        )
+    }
+
+    /// Position the fragments of an inline box and its descendants within `line_boxes`.
+    fn align_inline(&mut self, line_boxes: &[LineBox], align: VerticalAlign) {
+        let align = self.vertical_align(align);
+        if let BlockNode(_) = self.box_type {
+            // An inline-block, which was laid out at the top of the lines.
+            let line_box = line_boxes[self.line];
+            let height = self.dimensions.margin_box().height;
+            let top = line_box.top_of(align, height, height);
+            self.translate(line_box.offset, top - self.dimensions.margin_box().y);
+            return;
+        }
+        if self.replaced_size().is_some() {
+            let line_box = line_boxes[self.line];
+            let height = self.dimensions.content.height;
+            self.dimensions.content.x += line_box.offset;
+            self.dimensions.content.y = line_box.top_of(align, height, height);
+            return;
+        }
+        if !self.fragments.is_empty() {
+            let font_size = self.get_style_node().font_size();
+            for fragment in &mut self.fragments {
+                let line_box = line_boxes[fragment.line];
+                let height = fragment.rect.height;
+                let baseline = baseline_offset(font_size, height);
+                fragment.rect.x += line_box.offset;
+                fragment.rect.y = line_box.top_of(align, baseline, height);
+            }
+        }
+        for child in &mut self.children {
+            child.align_inline(line_boxes, align);
+        }
+
+        // An inline box covers the bounding box of its (non-empty) content, including the edges
+        // of its inline children.
+        let rects = self.fragments.iter().map(|f| f.rect);
+        let rects = rects.chain(self.children.iter().map(|c| c.dimensions.margin_box()));
+        self.dimensions.content = rects
+            .filter(|rect| rect.height > 0.0)
+            .reduce(Rect::union)
+            .unwrap_or_default();
+    }
+
+    // The vertical alignment of an inline box: its own, if set, or else that of the nearest
+    // inline ancestor, `parent`.
+    fn vertical_align(&self, parent: VerticalAlign) -> VerticalAlign {
+        match self.box_type {
+            InlineNode(style) | BlockNode(style) if style.value("vertical-align").is_some() => {
+                style.vertical_align()
+            }
+            _ => parent,
+        }
+    }
+
+    /// The (min-content, max-content) widths of this box's content: the width of its widest
+    /// unbreakable piece, and its width if no lines were broken. Text is measured in the font
+    /// size of each text node, and blocks add their horizontal margins, borders, and padding.
+    pub fn intrinsic_widths(&self) -> (f32, f32) {
+        match self.box_type {
+            BlockNode(style) if self.skipped_contents() => {
+                let width = style.contain_intrinsic_size().0.map_or(0.0, |w| w.to_px());
+                (width, width)
+            }
+            BlockNode(_) => self.children.iter().fold((0.0, 0.0), |(min, max), child| {
+                let (child_min, child_max) = child.intrinsic_widths();
+                let edges = match child.box_type {
+                    BlockNode(child_style) => horizontal_edges(child_style),
+                    _ => 0.0,
+                };
+                (min.max(child_min + edges), max.max(child_max + edges))
+            }),
+            AnonymousBlock | InlineNode(_) | Marker(..) => {
+                let mut glyphs = Vec::new();
+                self.collect_glyphs(&mut glyphs);
+                // Each word, with the width of the collapsed space before it.
+                let mut words: Vec<(f32, f32)> = Vec::new();
+                let mut space = None;
+                let mut in_word = false;
+                for (c, font_size) in glyphs {
+                    let width = text_width(c.encode_utf8(&mut [0; 4]), font_size);
+                    if c.is_whitespace() {
+                        in_word = false;
+                        space.get_or_insert(width);
+                    } else {
+                        if !in_word {
+                            words.push((space.take().unwrap_or(0.0), 0.0));
+                            in_word = true;
+                        }
+                        words.last_mut().unwrap().1 += width;
+                    }
+                }
+                let min = words.iter().map(|&(_, width)| width).fold(0.0, f32::max);
+                let max = words
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &(space, width))| if i == 0 { width } else { space + width })
+                    .sum();
+                (min, max)
+            }
+        }
+    }
+
+    // Append the characters of the text of this box and its inline descendants to `glyphs`, each
+    // with its font size.
+    fn collect_glyphs(&self, glyphs: &mut Vec<(char, f32)>) {
+        if let Some(text) = self.text() {
+            let font_size = self.get_style_node().font_size();
+            glyphs.extend(text.chars().map(|c| (c, font_size)));
+        }
+        for child in &self.children {
+            child.collect_glyphs(glyphs);
+        }
+    }
+
+    // The text content of a text node or list marker box.
+    fn text(&self) -> Option<&str> {
+        match self.box_type {
+            InlineNode(style) => style.node.as_text(),
+            Marker(_, ref text) => Some(text),
+            BlockNode(_) | AnonymousBlock => None,
+        }
+    }
+
+    /// Insert the marker of the `number`th item of a list before the item's content.
+    fn insert_marker(&mut self, number: usize, arena: &mut LayoutArena<'a>) {
+        let style = self.get_style_node();
+        let text = match style.value("list-style-type") {
+            Some(Keyword(s)) => marker_text(&s, number),
+            _ => marker_text("disc", number),
+        };
+        let marker = match text {
+            Some(text) => arena.new_box(Marker(style, text + " ")),
+            None => return,
+        };
+        match self.children.first_mut() {
+            Some(
+                first @ &mut LayoutBox {
+                    box_type: AnonymousBlock,
+                    ..
+                },
+            ) => first.children.insert(0, marker),
+            _ => {
+                let mut container = arena.new_box(AnonymousBlock);
+                container.children.push(marker);
+                self.children.insert(0, container);
+                self.anonymous_children += 1;
+            }
+        }
+    }
+
+    /// Add an inline child, in an anonymous block if this is a block. It's dropped if this block
+    /// already has as many anonymous blocks as `arena` allows and doesn't end with one.
+    fn push_inline(&mut self, child: LayoutBox<'a>, arena: &mut LayoutArena<'a>) {
+        if let Some(container) = self.get_inline_container(arena) {
+            container.children.push(child);
+        }
+    }
+
+    /// Where a new inline child should go, or `None` if it has nowhere to go.
+    fn get_inline_container(&mut self, arena: &mut LayoutArena<'a>) -> Option<&mut Self> {
+        match self.box_type {
+            InlineNode(_) | AnonymousBlock | Marker(..) => Some(self),
+            BlockNode(_) => {
+                // If we've just generated an anonymous block box, keep using it.
+                // Otherwise, create a new one, unless this block already has as many as it may.
+                let trailing_anonymous = self
+                    .children
+                    .last()
+                    .is_some_and(|child| matches!(child.box_type, AnonymousBlock));
+                if !trailing_anonymous {
+                    if arena
+                        .max_anonymous_children
+                        .is_some_and(|max| self.anonymous_children >= max)
+                    {
+                        return None;
+                    }
+                    self.children.push(arena.new_box(AnonymousBlock));
+                    self.anonymous_children += 1;
+                }
+                self.children.last_mut()
+            }
+        }
+    }
+
+    /// The number of anonymous boxes in this tree.
+    pub fn anonymous_box_count(&self) -> usize {
+        let own = matches!(self.box_type, AnonymousBlock) as usize;
+        own + self
+            .children
+            .iter()
+            .map(LayoutBox::anonymous_box_count)
+            .sum::<usize>()
+    }
+
+    /// The area a scroll container's content can be scrolled over: its padding box, extended to
+    /// include everything its descendants draw. How far this extends beyond the padding box is
+    /// how far the content can scroll. Boxes that aren't scroll containers don't scroll, so for
+    /// them this is just the padding box.
+    ///
+    /// Only axes that scroll extend past the padding box: content overflowing along an axis
+    /// with `overflow: hidden` is clipped rather than scrollable.
+    pub fn scroll_extent(&self) -> Rect {
+        let padding_box = self.dimensions.padding_box();
+        match self.box_type {
+            BlockNode(style) if style.is_scroll_container() => {
+                let extent = self.children.iter().fold(padding_box, |extent, child| {
+                    extent.union(child.overflow_rect())
+                });
+                let (x, y) = style.overflow();
+                let (left, width) = if x.scrolls() {
+                    (extent.x, extent.width)
+                } else {
+                    (padding_box.x, padding_box.width)
+                };
+                let (top, height) = if y.scrolls() {
+                    (extent.y, extent.height)
+                } else {
+                    (padding_box.y, padding_box.height)
+                };
+                Rect {
+                    x: left,
+                    y: top,
+                    width,
+                    height,
+                }
+            }
+            _ => padding_box,
+        }
+    }
+
+    /// The area this box clips the drawing of its descendants to, or `None` if it doesn't clip:
+    /// its padding box, if its `overflow` isn't visible. (If either axis isn't, neither is.)
+    pub fn clip_rect(&self) -> Option<Rect> {
+        match self.box_type {
+            BlockNode(style) if style.overflow().0.clips() => Some(self.dimensions.padding_box()),
+            _ => None,
+        }
+    }
+
+    // The area drawn by this box and its descendants, as seen by an ancestor scroll container.
+    // What a descendant draws outside this box's clip rect doesn't count.
+    fn overflow_rect(&self) -> Rect {
+        let own = self.dimensions.margin_box();
+        let own = self.fragments.iter().fold(own, |r, f| r.union(f.rect));
+        let descendants = self
+            .children
+            .iter()
+            .fold(own, |r, child| r.union(child.overflow_rect()));
+        match self.clip_rect() {
+            Some(clip) => own.union(descendants.intersection(clip)),
+            None => descendants,
+        }
+    }
+
+    /// The laid out text of this tree, one run per line of each text node, in document order.
+    /// Text is black unless a color is specified.
+    pub fn text_runs(&self) -> Vec<TextRun> {
+        let mut runs = Vec::new();
+        for (_, layout_box) in self.iter_boxes() {
+            if layout_box.fragments.is_empty() {
+                continue;
+            }
+            let color = layout_box
+                .get_style_node()
+                .computed_color("color")
+                .unwrap_or(Color::rgb(0, 0, 0));
+            runs.extend(layout_box.fragments.iter().map(|fragment| TextRun {
+                text: fragment.text.clone(),
+                rect: fragment.rect,
+                color: fragment.color.unwrap_or(color),
+            }));
+        }
+        runs
+    }
+
+    /// Every box in this tree, in paint order (parents before their children), with its depth
+    /// below this box and its content rect.
+    pub fn iter_flat(&self) -> impl Iterator<Item = (usize, &BoxType<'_>, Rect)> {
+        self.iter_boxes()
+            .map(|(depth, layout_box)| (depth, &layout_box.box_type, layout_box.dimensions.content))
+    }
+
+    /// The containing block for absolutely positioned children of this box, as laid out: the
+    /// padding box of the nearest positioned ancestor (or this box, if it is positioned), or else
+    /// the initial containing block.
+    pub fn containing_block_for_absolute(&self) -> Rect {
+        self.absolute_containing_block
+    }
+
+    /// Every box in this tree in paint order, with its depth below this box.
+    pub fn iter_boxes(&self) -> impl Iterator<Item = (usize, &Self)> {
+        let mut stack = vec![(0, self)];
+        std::iter::from_fn(move || {
+            let (depth, layout_box) = stack.pop()?;
+            // Push in reverse so the first child is visited next.
+            stack.extend(
+                layout_box
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|child| (depth + 1, child)),
+            );
+            Some((depth, layout_box))
+        })
+    }
+}
 impl Dimensions {
+    /// Dimensions with the given content area and no padding, borders, or margins.
+    pub fn from_content(content: Rect) -> Dimensions {
+        Dimensions {
+            content,
+            ..Default::default()
+        }
+    }
+
     // The area covered by the content area plus its padding.
-    fn padding_box(self) -> Rect {
+    pub fn padding_box(self) -> Rect {
         self.content.expanded_by(self.padding)
     }
 
@@ -292,12 +1909,122 @@ impl Dimensions {
         self.padding_box().expanded_by(self.border)
     }
     // The area covered by the content area plus padding, borders, and margin.
-    fn margin_box(self) -> Rect {
+    pub fn margin_box(self) -> Rect {
         self.border_box().expanded_by(self.margin)
     }
 }
 
+// The state of laying out lines of inline content within a block.
+struct LineLayout<'a> {
+    breaker: LineBreaker,
+    origin: Rect,
+    block: &'a StyledNode<'a>,
+    // The `::first-line` color of the block, if this is its first line box.
+    first_line_color: Option<Color>,
+    // The heights of the content on each line so far.
+    metrics: Vec<LineMetrics>,
+    // The resolution context of the lines, with the block as the containing block, for the
+    // edges of inline boxes and the layout of inline-blocks. And the padding box of the
+    // containing block for absolutely positioned boxes.
+    ctx: ResolutionContext,
+    positioned: Rect,
+}
+
+impl LineLayout<'_> {
+    // The metrics of line `line`, which may not have had any content yet.
+    fn line_metrics(&mut self, line: usize) -> &mut LineMetrics {
+        if self.metrics.len() <= line {
+            self.metrics.resize(line + 1, LineMetrics::default());
+        }
+        &mut self.metrics[line]
+    }
+}
+
+// The extent of the content on a line: above and below the baseline for baseline-aligned
+// content, and the tallest of the rest.
+#[derive(Clone, Copy, Default)]
+struct LineMetrics {
+    ascent: f32,
+    descent: f32,
+    aligned_height: f32,
+}
+
+impl LineMetrics {
+    // Account for content `height` tall with its baseline `baseline` from the top.
+    fn add(&mut self, align: VerticalAlign, baseline: f32, height: f32) {
+        match align {
+            VerticalAlign::Baseline => {
+                self.ascent = self.ascent.max(baseline);
+                self.descent = self.descent.max(height - baseline);
+            }
+            _ => self.aligned_height = self.aligned_height.max(height),
+        }
+    }
+
+    fn height(&self) -> f32 {
+        (self.ascent + self.descent).max(self.aligned_height)
+    }
+}
+
+// A laid out line: the y coordinate of its top, its height, the distance from its top to its
+// baseline, and how far its content is moved right by `text-align`.
+#[derive(Clone, Copy)]
+struct LineBox {
+    top: f32,
+    height: f32,
+    baseline: f32,
+    offset: f32,
+}
+
+impl LineBox {
+    // The y coordinate of the top of content `height` tall, with its baseline `baseline` from its
+    // top, aligned on this line by `align`.
+    fn top_of(&self, align: VerticalAlign, baseline: f32, height: f32) -> f32 {
+        match align {
+            VerticalAlign::Baseline => self.top + self.baseline - baseline,
+            VerticalAlign::Top => self.top,
+            VerticalAlign::Bottom => self.top + self.height - height,
+            VerticalAlign::Middle => self.top + (self.height - height) / 2.0,
+        }
+    }
+}
+
 impl Rect {
+    /// This rectangle with its position and size multiplied by `factor`.
+    pub fn scale(self, factor: f32) -> Rect {
+        Rect {
+            x: self.x * factor,
+            y: self.y * factor,
+            width: self.width * factor,
+            height: self.height * factor,
+        }
+    }
+
+    // The smallest rectangle containing both `self` and `other`.
+    fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    /// The overlap of `self` and `other`, which is empty (with zero width or height) if they
+    /// don't overlap.
+    pub fn intersection(self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x).max(0.0),
+            height: ((self.y + self.height).min(other.y + other.height) - y).max(0.0),
+        }
+    }
+
     fn expanded_by(self, edge: EdgeSizes) -> Rect {
         Rect {
             x: self.x - edge.left,
@@ -308,6 +2035,17 @@ impl Rect {
     }
 }
 
+// The text of a list item marker for the given `list-style-type`, if any.
+fn marker_text(list_style_type: &str, number: usize) -> Option<String> {
+    match list_style_type {
+        "disc" => Some("\u{2022}".to_string()),
+        "circle" => Some("\u{25e6}".to_string()),
+        "square" => Some("\u{25aa}".to_string()),
+        "decimal" => Some(format!("{}.", number)),
+        _ => None,
+    }
+}
+
 fn sum<I>(iter: I) -> f32
 where
     I: Iterator<Item = f32>,
@@ -319,35 +2057,43 @@ where
 mod tests {
     use super::*;
     use crate::css::{Declaration, Rule, Selector, SimpleSelector, Stylesheet, Unit, Value};
-    use crate::dom::{ElementData, Node, NodeType};
+    use crate::dom::{AttrMap, ElementData, Node, NodeType};
     use crate::style::style_tree;
-    use std::collections::HashMap;
 
     #[test]
     fn style_tree_with_empty_stylesheet() {
-        let root = Node::new(NodeType::Element(ElementData::new("div", HashMap::new())));
-        let stylesheet = Stylesheet { rules: vec![] };
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let stylesheet = Stylesheet {
+            rules: vec![],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert!(styled_node.specified_values.is_empty());
     }
 
     #[test]
     fn style_tree_with_single_rule() {
-        let mut attributes = HashMap::new();
+        let mut attributes = AttrMap::new();
         attributes.insert("id".to_string(), "main".to_string());
         let root = Node::new(NodeType::Element(ElementData::new("div", attributes)));
         let rule = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("div".to_string()),
                 id: Some("main".to_string()),
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "color".to_string(),
                 value: Value::Keyword("red".to_string()),
+                important: false,
             }],
         };
-        let stylesheet = Stylesheet { rules: vec![rule] };
+        let stylesheet = Stylesheet {
+            rules: vec![rule],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert_eq!(
             styled_node.specified_values.get("color"),
@@ -357,21 +2103,27 @@ mod tests {
 
     #[test]
     fn style_tree_with_nested_elements() {
-        let mut root = Node::new(NodeType::Element(ElementData::new("div", HashMap::new())));
-        let child = Node::new(NodeType::Element(ElementData::new("p", HashMap::new())));
+        let mut root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let child = Node::new(NodeType::Element(ElementData::new("p", AttrMap::new())));
         root.append_child(child);
         let rule = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("p".to_string()),
                 id: None,
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "margin".to_string(),
                 value: Value::Length(10.0, Unit::Px),
+                important: false,
             }],
         };
-        let stylesheet = Stylesheet { rules: vec![rule] };
+        let stylesheet = Stylesheet {
+            rules: vec![rule],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert_eq!(
             styled_node.children[0].specified_values.get("margin"),
@@ -382,38 +2134,48 @@ mod tests {
     #[test]
     fn style_tree_with_text_node() {
         let root = Node::new(NodeType::Text("Hello".to_string()));
-        let stylesheet = Stylesheet { rules: vec![] };
+        let stylesheet = Stylesheet {
+            rules: vec![],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert!(styled_node.specified_values.is_empty());
     }
 
     #[test]
     fn style_tree_with_conflicting_rules() {
-        let root = Node::new(NodeType::Element(ElementData::new("div", HashMap::new())));
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
         let rule1 = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("div".to_string()),
                 id: None,
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "color".to_string(),
                 value: Value::Keyword("red".to_string()),
+                important: false,
             }],
         };
         let rule2 = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("div".to_string()),
                 id: None,
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "color".to_string(),
                 value: Value::Keyword("blue".to_string()),
+                important: false,
             }],
         };
         let stylesheet = Stylesheet {
             rules: vec![rule1, rule2],
+            ..Default::default()
         };
         let styled_node = style_tree(&root, &stylesheet);
         assert_eq!(
@@ -421,4 +2183,1466 @@ mod tests {
             Some(&Value::Keyword("blue".to_string()))
         );
     }
+
+    fn layout_text(text: &str, css: &str) -> Vec<String> {
+        let root = crate::html::parse(format!("<div>{}</div>", text));
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let text_box = &layout_root.children[0].children[0];
+        text_box.fragments.iter().map(|f| f.text.clone()).collect()
+    }
+
+    #[test]
+    fn long_word_overflows_line_by_default() {
+        // At the default 16px font size each glyph is 8px wide, so 80px holds ten characters.
+        let word = "abcdefghij".repeat(3);
+        let lines = layout_text(&word, "div { display: block; width: 80px; }");
+        assert_eq!(lines, vec![word]);
+    }
+
+    #[test]
+    fn word_break_all_splits_long_word() {
+        let word = "abcdefghij".repeat(3);
+        let css = "div { display: block; width: 80px; word-break: break-all; }";
+        assert_eq!(layout_text(&word, css), vec!["abcdefghij"; 3]);
+        let css = "div { display: block; width: 80px; overflow-wrap: break-word; }";
+        assert_eq!(layout_text(&word, css), vec!["abcdefghij"; 3]);
+    }
+
+    #[test]
+    fn white_space_modes_segment_text_differently() {
+        // 40px holds five glyphs.
+        let lay_out = |white_space: &str| {
+            let root = crate::html::parse("<div>a  b\nc    d</div>".to_string());
+            let css = format!(
+                "div {{ display: block; width: 40px; white-space: {}; }}",
+                white_space
+            );
+            let stylesheet = crate::css::parse(css);
+            let styled_node = style_tree(&root, &stylesheet);
+            let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+            let anonymous = &layout_root.children[0];
+            let pieces: Vec<(String, f32)> = anonymous.children[0]
+                .fragments
+                .iter()
+                .map(|f| (f.text.clone(), f.rect.width))
+                .collect();
+            let line_count = (anonymous.dimensions.content.height / 19.2).round() as usize;
+            (pieces, line_count)
+        };
+        let piece = |text: &str, width| (text.to_string(), width);
+
+        assert_eq!(
+            lay_out("normal"),
+            (vec![piece("a b c", 40.0), piece("d", 8.0)], 2)
+        );
+        assert_eq!(lay_out("nowrap"), (vec![piece("a b c d", 56.0)], 1));
+        assert_eq!(
+            lay_out("pre"),
+            (vec![piece("a  b", 32.0), piece("c    d", 48.0)], 2)
+        );
+        assert_eq!(
+            lay_out("pre-wrap"),
+            (
+                vec![piece("a  b", 32.0), piece("c    ", 40.0), piece("d", 8.0)],
+                3
+            )
+        );
+        assert_eq!(
+            lay_out("pre-line"),
+            (vec![piece("a b", 24.0), piece("c d", 24.0)], 2)
+        );
+    }
+
+    #[test]
+    fn text_transform_changes_laid_out_text_only() {
+        let root = crate::html::parse("<div>shout it</div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: block; width: 64px; text-transform: uppercase; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let fragment = &layout_root.children[0].children[0].fragments[0];
+        assert_eq!(fragment.text, "SHOUT IT");
+        assert_eq!(fragment.rect.width, 64.0);
+        assert_eq!(root.children[0].as_text(), Some("shout it"));
+    }
+
+    #[test]
+    fn layout_against_viewport_from_content() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: block; padding: 10px; } p { display: block; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 800.0,
+            height: 600.0,
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        assert_eq!(layout_root.dimensions.content.width, 780.0);
+        assert_eq!(layout_root.children[0].dimensions.content.width, 780.0);
+    }
+
+    #[test]
+    fn unitless_zero_height_lays_out_at_zero() {
+        let root = crate::html::parse("<div><p>text</p></div>".to_string());
+        let stylesheet =
+            crate::css::parse("div, p { display: block; } p { height: 0; margin: 0; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        assert_eq!(layout_root.children[0].dimensions.content.height, 0.0);
+        assert_eq!(layout_root.dimensions.content.height, 0.0);
+    }
+
+    #[test]
+    fn debug_tree_shows_the_geometry_of_each_box() {
+        let root = crate::html::parse("<div><p></p><p id=\"b\"></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } div { padding: 5px; } \
+             p { height: 10px; margin: 2px; } #b { border-width: 1px; width: 50.5px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 200.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled, viewport);
+        let expected = "\
+block div content: (5, 5) 190x28, margin: 0 0 0 0, border: 0 0 0 0, padding: 5 5 5 5
+  block p content: (7, 7) 186x10, margin: 2 2 2 2, border: 0 0 0 0, padding: 0 0 0 0
+  block p content: (8, 20) 50.5x10, margin: 2 135.5 2 2, border: 1 1 1 1, padding: 0 0 0 0
+";
+        assert_eq!(layout_root.debug_tree(), expected);
+    }
+
+    #[test]
+    fn relative_offsets_move_the_box_but_not_its_siblings() {
+        let root = crate::html::parse(
+            "<div><p id=\"moved\"><p></p></p><p></p><p id=\"up\"></p></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } div { height: 200px; } p { height: 20px; } \
+             #moved { position: relative; top: 10px; left: 10%; } \
+             #up { position: relative; bottom: 5px; right: 4px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 600.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled, viewport);
+        let [moved, next, up] = &layout_root.children[..] else {
+            panic!("expected three paragraphs");
+        };
+        let position = |b: &LayoutBox| (b.dimensions.content.x, b.dimensions.content.y);
+        assert_eq!(position(moved), (60.0, 10.0));
+        // Its descendants move with it.
+        assert_eq!(position(&moved.children[0]), (60.0, 10.0));
+        // The next sibling stays where it would have been.
+        assert_eq!(position(next), (0.0, 20.0));
+        // Without top or left, bottom and right move the box up and to the left.
+        assert_eq!(position(up), (-4.0, 35.0));
+    }
+
+    #[test]
+    fn percentage_heights_need_a_definite_container_height() {
+        let root = crate::html::parse(
+            "<div><section id=\"fixed\"><p id=\"half\"><p></p></p></section>\
+             <section><p id=\"auto\"><p></p></p></section></div>"
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, section, p { display: block; } #fixed { height: 100px; } \
+             #half { height: 50%; } #half p { height: 40%; } \
+             #auto { height: 50%; } #auto p { height: 15px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled, viewport);
+        let height = |b: &LayoutBox| b.dimensions.content.height;
+        let half = &layout_root.children[0].children[0];
+        assert_eq!(height(half), 50.0);
+        // A percentage of a percentage height is definite too.
+        assert_eq!(height(&half.children[0]), 20.0);
+        // In a container sized by its contents, the percentage falls back to `auto`.
+        let auto = &layout_root.children[1].children[0];
+        assert_eq!(height(auto), 15.0);
+    }
+
+    #[test]
+    fn relative_lengths_resolve_against_container_and_viewport() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: block; width: 50vw; padding-left: 1em; } p { display: block; width: 50%; height: 10vh; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        assert_eq!(layout_root.dimensions.content.width, 400.0);
+        assert_eq!(layout_root.dimensions.padding.left, 16.0);
+        let p = &layout_root.children[0].dimensions;
+        assert_eq!(p.content.width, 200.0);
+        assert_eq!(p.content.height, 60.0);
+    }
+
+    #[test]
+    fn min_and_max_content_widths() {
+        let layout_width = |width: &str| {
+            let root = crate::html::parse("<div><p>aaaa bb <b>cccccc</b></p></div>".to_string());
+            let css = format!(
+                "div {{ display: block; }} p {{ display: block; padding: 2px; width: {}; }}",
+                width
+            );
+            let stylesheet = crate::css::parse(css);
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 800.0,
+                ..Default::default()
+            });
+            let layout_root = layout_tree(&styled_node, viewport);
+            let p = &layout_root.children[0];
+            (p.dimensions.content.width, p.dimensions.content.height)
+        };
+        // The longest word is six 8px glyphs wide; all three words take fourteen.
+        let line_height = 16.0 * 1.2;
+        assert_eq!(layout_width("min-content"), (48.0, 3.0 * line_height));
+        assert_eq!(layout_width("max-content"), (112.0, line_height));
+    }
+
+    #[test]
+    fn table_cells_form_a_grid() {
+        let root = crate::html::parse(
+            "<table><tr><td>aa</td><td>b</td></tr><tr><td>a</td><td>bbb</td></tr></table>"
+                .to_string(),
+        );
+        let stylesheet = crate::style::user_agent_stylesheet();
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        // Columns are 2 and 3 glyphs wide, and each row is one line tall.
+        let line_height = 16.0 * 1.2;
+        let position = |row: usize, col: usize| {
+            let cell = &layout_root.children[row].children[col].dimensions.content;
+            (cell.x, cell.y, cell.width)
+        };
+        assert_eq!(position(0, 0), (0.0, 0.0, 16.0));
+        assert_eq!(position(0, 1), (16.0, 0.0, 24.0));
+        assert_eq!(position(1, 0), (0.0, line_height, 16.0));
+        assert_eq!(position(1, 1), (16.0, line_height, 24.0));
+        assert_eq!(layout_root.dimensions.content.width, 40.0);
+        assert_eq!(layout_root.dimensions.content.height, 2.0 * line_height);
+    }
+
+    #[test]
+    fn vertical_align_within_line_box() {
+        let layout_line = |align: &str| {
+            let root = crate::html::parse(
+                "<div><span id=\"big\">x</span><span id=\"small\">y</span></div>".to_string(),
+            );
+            let css = format!(
+                "div {{ display: block; }} #big {{ font-size: 32px; vertical-align: top; }} #small {{ vertical-align: {}; }}",
+                align
+            );
+            let stylesheet = crate::css::parse(css);
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 800.0,
+                ..Default::default()
+            });
+            let layout_root = layout_tree(&styled_node, viewport);
+            let line = &layout_root.children[0];
+            let (big, small) = (&line.children[0], &line.children[1]);
+            (
+                line.dimensions.content.height,
+                big.dimensions.content.y,
+                small.dimensions.content.y,
+            )
+        };
+        // The 32px box is 38.4px tall and sets the height of the line; the 16px box is 19.2px.
+        let (height, big, small) = layout_line("top");
+        assert_eq!((height, big, small), (32.0 * 1.2, 0.0, 0.0));
+        let (height, big, small) = layout_line("bottom");
+        assert_eq!((height, big), (32.0 * 1.2, 0.0));
+        assert!((small - 16.0 * 1.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn reset_and_relayout() {
+        let root = crate::html::parse("<div><p>some text</p><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: block; padding: 5px; } p { display: block; margin: 3px; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        });
+        let mut layout_root = layout_tree(&styled_node, viewport);
+        fn collect(layout_box: &LayoutBox, rects: &mut Vec<(Rect, usize)>) {
+            rects.push((layout_box.dimensions.content, layout_box.fragments.len()));
+            for child in &layout_box.children {
+                collect(child, rects);
+            }
+        }
+        let mut original = Vec::new();
+        collect(&layout_root, &mut original);
+
+        layout_root.reset_dimensions();
+        let mut reset = Vec::new();
+        collect(&layout_root, &mut reset);
+        assert!(reset.iter().all(|&r| r == (Rect::default(), 0)));
+
+        layout_root.layout_root(viewport);
+        let mut relaid = Vec::new();
+        collect(&layout_root, &mut relaid);
+        assert_eq!(relaid, original);
+    }
+
+    #[test]
+    fn sticky_box_sticks_to_viewport_top() {
+        let root = crate::html::parse("<main><div></div><nav></nav><div></div></main>".to_string());
+        let stylesheet = crate::css::parse(
+            "main, div, nav { display: block; } div { height: 100px; } \
+             nav { position: sticky; top: 0px; height: 20px; } main { height: 500px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 200.0,
+            ..Default::default()
+        });
+        let nav_y = |scroll_y| {
+            let layout_root = layout_tree_scrolled(&styled_node, viewport, scroll_y);
+            layout_root.children[1].dimensions.content.y
+        };
+        // Before the scroll offset reaches it, the box stays in its static position.
+        assert_eq!(nav_y(0.0), 100.0);
+        assert_eq!(nav_y(50.0), 100.0);
+        // Then it stays at the top of the viewport...
+        assert_eq!(nav_y(150.0), 150.0);
+        assert_eq!(nav_y(300.0), 300.0);
+        // ...until it reaches the bottom of its containing block.
+        assert_eq!(nav_y(1000.0), 480.0);
+        // The following box isn't affected.
+        let layout_root = layout_tree_scrolled(&styled_node, viewport, 300.0);
+        assert_eq!(layout_root.children[2].dimensions.content.y, 120.0);
+    }
+
+    #[test]
+    fn absolute_percent_offsets_resolve_against_positioned_parent() {
+        let root = crate::html::parse(
+            "<div><div id=\"parent\"><div id=\"child\"></div><p></p></div></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div { display: block; } p { display: block; height: 5px; } #parent { position: relative; width: 400px; height: 100px; margin-top: 30px; } #child { position: absolute; left: 50%; top: 10%; width: 20px; height: 20px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let parent = &layout_root.children[0];
+        let child = &parent.children[0].dimensions.content;
+        assert_eq!((child.x, child.y), (200.0, 40.0));
+        assert_eq!((child.width, child.height), (20.0, 20.0));
+        // The absolute box takes no space in the flow.
+        assert_eq!(parent.children[1].dimensions.content.y, 30.0);
+    }
+
+    #[test]
+    fn em_and_rem_widths_follow_font_sizes() {
+        let root = crate::html::parse("<html><div><p></p></div></html>".to_string());
+        let stylesheet = crate::css::parse(
+            "html { display: block; font-size: 10px; } \
+             div { display: block; font-size: 20px; width: 2em; } \
+             p { display: block; font-size: 2em; width: 3rem; height: 1em; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let div = &layout_root.children[0];
+        assert_eq!(div.dimensions.content.width, 40.0);
+        // `em` is relative to the element's own font size, 40px here, and `rem` to the root's.
+        let p = &div.children[0].dimensions;
+        assert_eq!(p.content.width, 30.0);
+        assert_eq!(p.content.height, 40.0);
+    }
+
+    #[test]
+    fn percentage_widths_compound_and_center_with_auto_margins() {
+        let root = crate::html::parse("<div><div><p></p></div></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; width: 50%; } p { margin-left: auto; margin-right: auto; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 600.0,
+            height: 400.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled, viewport);
+        assert_eq!(layout_root.dimensions.content.width, 300.0);
+        let inner = &layout_root.children[0];
+        assert_eq!(inner.dimensions.content.width, 150.0);
+        // The auto margins split what's left once the percentage is resolved.
+        let p = &inner.children[0].dimensions;
+        assert_eq!(p.content.width, 75.0);
+        assert_eq!((p.margin.left, p.margin.right), (37.5, 37.5));
+        assert_eq!(p.content.x, 37.5);
+    }
+
+    #[test]
+    fn min_and_max_width_clamp_the_used_width() {
+        let layout_in_600px = |css: &str| {
+            let root = crate::html::parse("<div></div>".to_string());
+            let stylesheet = crate::css::parse(format!("div {{ display: block; {} }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 600.0,
+                ..Default::default()
+            });
+            layout_tree(&styled_node, viewport).dimensions
+        };
+        assert_eq!(layout_in_600px("max-width: 100px;").content.width, 100.0);
+        assert_eq!(layout_in_600px("max-width: 50%;").content.width, 300.0);
+        assert_eq!(
+            layout_in_600px("width: 50px; min-width: 80px;")
+                .content
+                .width,
+            80.0
+        );
+        // The minimum wins over a smaller maximum.
+        let d = layout_in_600px("min-width: 200px; max-width: 100px;");
+        assert_eq!(d.content.width, 200.0);
+        // Auto margins are solved again for the clamped width.
+        let d = layout_in_600px("max-width: 100px; margin-left: auto; margin-right: auto;");
+        assert_eq!(
+            (d.margin.left, d.content.width, d.margin.right),
+            (250.0, 100.0, 250.0)
+        );
+    }
+
+    #[test]
+    fn border_box_sizing_includes_padding_and_border_in_the_width() {
+        let layout_in_600px = |css: &str| {
+            let root = crate::html::parse("<div></div>".to_string());
+            let stylesheet = crate::css::parse(format!("div {{ display: block; {} }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 600.0,
+                ..Default::default()
+            });
+            layout_tree(&styled_node, viewport).dimensions
+        };
+        let sized = "width: 100px; padding: 10px;";
+        assert_eq!(layout_in_600px(sized).content.width, 100.0);
+        let d = layout_in_600px(&format!("{} box-sizing: border-box;", sized));
+        assert_eq!((d.content.width, d.border_box().width), (80.0, 100.0));
+        assert_eq!(d.margin.right, 500.0);
+        // The content can't be narrower than nothing.
+        let d = layout_in_600px("width: 10px; padding: 10px; box-sizing: border-box;");
+        assert_eq!(d.content.width, 0.0);
+        // An auto width is the same either way.
+        let d = layout_in_600px("padding: 10px; box-sizing: border-box;");
+        assert_eq!(d.content.width, 580.0);
+        // So is `max-width`, which also sizes the border box.
+        let d = layout_in_600px("padding: 10px; box-sizing: border-box; max-width: 100px;");
+        assert_eq!(d.content.width, 80.0);
+    }
+
+    #[test]
+    fn math_function_widths_resolve_against_the_container() {
+        let width_in = |css: &str, container: f32| {
+            let root = crate::html::parse("<div></div>".to_string());
+            let stylesheet =
+                crate::css::parse(format!("div {{ display: block; width: {}; }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: container,
+                ..Default::default()
+            });
+            layout_tree(&styled_node, viewport).dimensions.content.width
+        };
+        assert_eq!(width_in("min(50%, 300px)", 800.0), 300.0);
+        assert_eq!(width_in("min(50%, 300px)", 400.0), 200.0);
+        let clamp = "clamp(100px, 50%, 300px)";
+        assert_eq!(width_in(clamp, 100.0), 100.0);
+        assert_eq!(width_in(clamp, 400.0), 200.0);
+        assert_eq!(width_in(clamp, 800.0), 300.0);
+    }
+
+    #[test]
+    fn aspect_ratio_sizes_the_auto_dimension() {
+        let content_size = |css: &str| {
+            let root = crate::html::parse("<div><p>text</p></div>".to_string());
+            let stylesheet = crate::css::parse(format!("div, p {{ display: block; }} {}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 800.0,
+                ..Default::default()
+            });
+            let content = layout_tree(&styled_node, viewport).dimensions.content;
+            (content.width, content.height)
+        };
+        assert_eq!(
+            content_size("div { width: 200px; aspect-ratio: 2 / 1; }"),
+            (200.0, 100.0)
+        );
+        assert_eq!(
+            content_size("div { height: 50px; aspect-ratio: 3; }"),
+            (150.0, 50.0)
+        );
+        assert_eq!(
+            content_size("div { width: AUTO; height: 2em; aspect-ratio: 2; }"),
+            (64.0, 32.0)
+        );
+        assert_eq!(
+            content_size("div { width: 200px; height: 20px; aspect-ratio: 2 / 1; }"),
+            (200.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn absolute_box_uses_nearest_positioned_ancestor() {
+        let root = crate::html::parse(
+            "<div id=\"outer\"><div id=\"inner\"><div><div id=\"abs\"></div></div></div></div>"
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div { display: block; } #outer { position: relative; padding: 10px; } \
+             #inner { position: relative; margin-left: 20px; height: 50px; } \
+             #abs { position: absolute; left: 0px; top: 0px; width: 5px; height: 5px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        });
+        let outer = layout_tree(&styled_node, viewport);
+        let inner = &outer.children[0];
+        let static_parent = &inner.children[0];
+        let inner_box = inner.dimensions.padding_box();
+        assert_eq!((inner_box.x, inner_box.y), (30.0, 10.0));
+        assert_eq!(
+            outer.containing_block_for_absolute(),
+            outer.dimensions.padding_box()
+        );
+        assert_eq!(inner.containing_block_for_absolute(), inner_box);
+        assert_eq!(static_parent.containing_block_for_absolute(), inner_box);
+        let abs = &static_parent.children[0].dimensions.content;
+        assert_eq!((abs.x, abs.y), (30.0, 10.0));
+    }
+
+    #[test]
+    fn auto_margins_center_absolute_box_vertically() {
+        let root = crate::html::parse("<div id=\"outer\"><div id=\"abs\"></div></div>".to_string());
+        let margins = |css: &str| {
+            let stylesheet = crate::css::parse(format!(
+                "div {{ display: block; }} #outer {{ position: relative; height: 200px; }} \
+                 #abs {{ position: absolute; top: 10px; bottom: 30px; height: 60px; {} }}",
+                css
+            ));
+            let styled_node = style_tree(&root, &stylesheet);
+            let outer = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+            let d = outer.children[0].dimensions;
+            (d.margin.top, d.content.y, d.margin.bottom)
+        };
+        // 200 - 10 - 30 - 60 = 100px of space to share out.
+        assert_eq!(margins("margin: auto;"), (50.0, 60.0, 50.0));
+        assert_eq!(margins("margin-top: auto;"), (100.0, 110.0, 0.0));
+        assert_eq!(margins("margin-bottom: auto;"), (0.0, 10.0, 100.0));
+        assert_eq!(margins(""), (0.0, 10.0, 0.0));
+    }
+
+    #[test]
+    fn nested_absolute_box_is_placed_against_final_height() {
+        let root = crate::html::parse(
+            "<div id=\"outer\"><div><div id=\"abs\"></div></div><p></p></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } #outer { position: relative; } p { height: 100px; } \
+             #abs { position: absolute; bottom: 0px; height: 10px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        });
+        let outer = layout_tree(&styled_node, viewport);
+        let abs = &outer.children[0].children[0].dimensions.content;
+        assert_eq!((abs.y, abs.height), (90.0, 10.0));
+    }
+
+    #[test]
+    fn row_gap_separates_block_children() {
+        let root = crate::html::parse("<div><p></p><p></p><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } div { row-gap: 10px; } \
+             p { height: 20px; margin-bottom: 5px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let tops: Vec<f32> = layout_root
+            .children
+            .iter()
+            .map(|p| p.dimensions.content.y)
+            .collect();
+        assert_eq!(tops, [0.0, 35.0, 70.0]);
+        // No gap after the last child.
+        assert_eq!(layout_root.dimensions.content.height, 95.0);
+    }
+
+    // Lay out a flex container of three items with `css`, and return the item margin boxes.
+    fn flex_items(css: &str) -> Vec<Rect> {
+        let root = crate::html::parse(
+            "<div><p id=\"a\"></p><p id=\"b\"></p><p id=\"c\">four</p></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(format!(
+            "div {{ display: flex; width: 500px; }} p {{ display: block; }} {}",
+            css
+        ));
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        layout_root
+            .children
+            .iter()
+            .map(|item| item.dimensions.margin_box())
+            .collect()
+    }
+
+    #[test]
+    fn flex_space_between_spreads_items_across_the_row() {
+        let items =
+            flex_items("div { justify-content: space-between; } p { width: 100px; height: 10px; }");
+        let xs: Vec<(f32, f32)> = items.iter().map(|r| (r.x, r.width)).collect();
+        assert_eq!(xs, [(0.0, 100.0), (200.0, 100.0), (400.0, 100.0)]);
+    }
+
+    #[test]
+    fn flex_items_sized_by_basis_or_content_and_stretched() {
+        // "four" is 32px wide at the default font size.
+        let items = flex_items(
+            "div { justify-content: center; } #a { flex-basis: 50px; height: 40px; } \
+             #b { width: 60px; margin: 5px; } #c { height: 10px; }",
+        );
+        let rect = |x, width, height| Rect {
+            x,
+            y: 0.0,
+            width,
+            height,
+        };
+        assert_eq!(
+            items,
+            [
+                rect(174.0, 50.0, 40.0),
+                rect(224.0, 70.0, 40.0),
+                rect(294.0, 32.0, 10.0)
+            ]
+        );
+        let items = flex_items("div { align-items: flex-start; } #a { height: 40px; }");
+        assert_eq!(items[1].height, 0.0);
+    }
+
+    #[test]
+    fn color_used_as_width_is_ignored() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } p { width: red; height: #00ff00; margin: blue; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let p = layout_root.children[0].dimensions;
+        assert_eq!((p.content.width, p.content.height), (800.0, 0.0));
+        assert_eq!((p.margin.left, p.margin.right), (0.0, 0.0));
+    }
+
+    #[test]
+    fn sibling_margins_collapse() {
+        // The tops of the two paragraphs, and the height of their container.
+        let layout = |css: &str| {
+            let root = crate::html::parse("<div><p></p><p id=\"b\"></p></div>".to_string());
+            let stylesheet = crate::css::parse(format!(
+                "div, p {{ display: block; }} p {{ height: 10px; margin: 20px; }} {}",
+                css
+            ));
+            let styled_node = style_tree(&root, &stylesheet);
+            let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+            let y = |i: usize| layout_root.children[i].dimensions.content.y;
+            (y(0), y(1), layout_root.dimensions.content.height)
+        };
+        // The 20px margins between them collapse into one; the outer ones are kept.
+        assert_eq!(layout(""), (20.0, 50.0, 80.0));
+        // The larger of two margins wins.
+        assert_eq!(layout("#b { margin-top: 30px; }").1, 60.0);
+        // Margins separated by a gap don't adjoin.
+        assert_eq!(layout("div { row-gap: 5px; }").1, 75.0);
+    }
+
+    #[test]
+    fn collapse_adjoining_margins() {
+        assert_eq!(collapse_margins(10.0, 20.0), 20.0);
+        assert_eq!(collapse_margins(-10.0, -20.0), -20.0);
+        assert_eq!(collapse_margins(30.0, -10.0), 20.0);
+        assert_eq!(collapse_margins(-30.0, 10.0), -20.0);
+        assert_eq!(collapse_margins(0.0, 0.0), 0.0);
+        assert_eq!(collapse_margins(0.0, 15.0), 15.0);
+        assert_eq!(collapse_margins(-15.0, 0.0), -15.0);
+    }
+
+    #[test]
+    fn nested_counters_number_generated_content() {
+        let root = crate::html::parse(
+            "<div><section><div><section></section></div></section><section></section></div>"
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            r#"div, section { display: block; } div { counter-reset: sec; }
+               section { counter-increment: sec; }
+               section::before { content: counters(sec, ".") ". "; }
+               section::after { content: "(" counter(sec) ")"; }"#
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let layout_root = build_layout_tree(&styled_node);
+        let generated: Vec<&str> = layout_root
+            .iter_boxes()
+            .filter_map(|(_, layout_box)| match layout_box.box_type {
+                Marker(_, ref text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(generated, ["1. ", "1.1. ", "(1)", "(1)", "2. ", "(2)"]);
+    }
+
+    #[test]
+    fn paint_order_follows_z_index() {
+        let root = crate::html::parse("<div><p id=\"a\"></p><p id=\"b\"></p></div>".to_string());
+        let order = |css: &str| {
+            let stylesheet = crate::css::parse(format!("div, p {{ display: block; }} {}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let layout_root = build_layout_tree(&styled_node);
+            let ids = |boxes: Vec<&LayoutBox>| -> Vec<String> {
+                boxes
+                    .iter()
+                    .map(|b| {
+                        b.get_style_node()
+                            .node
+                            .as_element()
+                            .unwrap()
+                            .id()
+                            .unwrap()
+                            .clone()
+                    })
+                    .collect()
+            };
+            (
+                ids(layout_root.children().iter().collect()),
+                ids(layout_root.paint_order_children()),
+            )
+        };
+        let (tree, paint) = order("");
+        assert_eq!(tree, paint);
+        let (tree, paint) = order("#a { position: relative; z-index: 1; }");
+        assert_eq!(tree, ["a", "b"]);
+        assert_eq!(paint, ["b", "a"]);
+    }
+
+    #[test]
+    fn nested_quotes_alternate() {
+        let root = crate::html::parse(
+            "<p><q>a <q>b <q>c</q></q></q><q>d</q><q id=\"x\">e</q></p>".to_string(),
+        );
+        let user_agent = crate::style::user_agent_stylesheet();
+        let author = crate::css::parse(
+            r#"#x { quotes: "<<" ">>"; } #x::after { content: no-close-quote; }"#.to_string(),
+        );
+        let styled_node = crate::style::style_tree_with_origins(
+            &root,
+            &[
+                (crate::style::CascadeOrigin::UserAgent, &user_agent),
+                (crate::style::CascadeOrigin::Author, &author),
+            ],
+        );
+        let layout_root = build_layout_tree(&styled_node);
+        let generated: Vec<&str> = layout_root
+            .iter_boxes()
+            .filter_map(|(_, layout_box)| match layout_box.box_type {
+                Marker(_, ref text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            generated,
+            [
+                "\u{201c}", "\u{2018}", "\u{2018}", "\u{2019}", "\u{2019}", "\u{201d}", "\u{201c}",
+                "\u{201d}", "<<", ""
+            ]
+        );
+    }
+
+    #[test]
+    fn intrinsic_widths_of_text_and_nested_blocks() {
+        let root = crate::html::parse(
+            "<div><p>one three</p><div><p>x nineteen<b>ab</b></p></div></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } div { padding: 5px; } b { font-size: 20px; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let layout_root = build_layout_tree(&styled_node);
+        // Glyphs are 8px wide at the default font size.
+        assert_eq!(layout_root.children[0].intrinsic_widths(), (40.0, 72.0));
+        // "nineteen" runs into "ab", whose glyphs are 10px wide.
+        let nested = &layout_root.children[1];
+        assert_eq!(nested.intrinsic_widths(), (84.0, 100.0));
+        // The outer block adds the padding of the nested one.
+        assert_eq!(layout_root.intrinsic_widths(), (94.0, 110.0));
+    }
+
+    #[test]
+    fn orphan_table_cell_lays_out_as_block() {
+        let root = crate::html::parse("<div><div id=\"cell\">text</div></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: block; padding: 2px; } #cell { display: table-cell; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let cell = &layout_root.children[0].dimensions;
+        assert_eq!(cell.content.width, 792.0);
+        assert_eq!(cell.content.height, 16.0 * 1.2);
+    }
+
+    #[test]
+    fn uppercase_auto_width_is_auto() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: Block; width: AUTO; margin-left: 100px; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        assert_eq!(layout_root.dimensions.content.width, 700.0);
+    }
+
+    #[test]
+    fn border_shorthand_sets_border_widths() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let stylesheet =
+            crate::css::parse("div { display: block; border: 3px solid black; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let d = layout_tree(&styled_node, viewport).dimensions;
+        assert_eq!((d.border.left, d.border.right), (3.0, 3.0));
+        assert_eq!((d.border.top, d.border.bottom), (3.0, 3.0));
+        assert_eq!(d.content.width, 794.0);
+    }
+
+    #[test]
+    fn border_width_keywords_and_em_lengths() {
+        let borders = |css: &str| {
+            let root = crate::html::parse("<div></div>".to_string());
+            let stylesheet = crate::css::parse(format!("div {{ display: block; {} }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let d =
+                layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT)).dimensions;
+            (
+                [d.border.left, d.border.right, d.border.top, d.border.bottom],
+                d.content.width,
+            )
+        };
+        assert_eq!(borders("border-width: thick;"), ([5.0; 4], 790.0));
+        assert_eq!(borders("border: thin solid black;"), ([1.0; 4], 798.0));
+        assert_eq!(
+            borders("border-width: medium; border-top-width: 0.5em; font-size: 20px;"),
+            ([3.0, 3.0, 10.0, 3.0], 794.0)
+        );
+    }
+
+    #[test]
+    fn iter_flat_visits_boxes_in_paint_order() {
+        let root = crate::html::parse("<div><p><span></span></p><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p, span { display: block; height: 10px; } div { height: auto; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let flat: Vec<_> = layout_root.iter_flat().collect();
+        let depths: Vec<usize> = flat.iter().map(|&(depth, _, _)| depth).collect();
+        assert_eq!(depths, [0, 1, 2, 1]);
+        assert!(flat
+            .iter()
+            .all(|(_, box_type, _)| matches!(box_type, BlockNode(_))));
+        let ys: Vec<(f32, f32)> = flat.iter().map(|(_, _, r)| (r.y, r.height)).collect();
+        assert_eq!(ys, [(0.0, 20.0), (0.0, 10.0), (0.0, 10.0), (10.0, 10.0)]);
+    }
+
+    #[test]
+    fn scroll_extent_includes_overflowing_children() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } div { height: 50px; padding: 5px; overflow: auto; } \
+             p { height: 200px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let padding_box = layout_root.dimensions.padding_box();
+        let extent = layout_root.scroll_extent();
+        assert_eq!(padding_box.height, 60.0);
+        assert_eq!(extent.height - padding_box.height, 145.0);
+        assert_eq!(extent.width, padding_box.width);
+        // The overflowing child itself isn't a scroll container.
+        let child = &layout_root.children[0];
+        assert_eq!(child.scroll_extent(), child.dimensions.padding_box());
+    }
+
+    #[test]
+    fn content_visibility_hidden_skips_descendants() {
+        let root = crate::html::parse(
+            "<div><div class=\"a\"><p>Skipped</p></div><div class=\"b\"><p>Skipped</p></div>\
+             <div class=\"c\"><p>Shown</p></div></div>"
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } p { height: 30px; } \
+             .a, .b { content-visibility: hidden; } \
+             .b { contain-intrinsic-size: 100px 50px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let [a, b, c] = &layout_root.children[..] else {
+            panic!("expected three blocks");
+        };
+
+        assert!(a.skipped_contents() && a.children.is_empty());
+        assert_eq!(a.dimensions.content.height, 0.0);
+        assert!(b.skipped_contents() && b.children.is_empty());
+        assert_eq!(b.dimensions.content.height, 50.0);
+        assert_eq!(b.intrinsic_widths(), (100.0, 100.0));
+        assert!(!c.skipped_contents());
+        assert_eq!(c.children.len(), 1);
+        assert_eq!(c.dimensions.content.height, 30.0);
+    }
+
+    #[test]
+    fn inline_image_sits_on_the_line_with_text() {
+        let root = crate::html::parse(
+            "<p>Hello <img width=20 height=20></img>world <img class=tall width=30 height=5></img></p>".to_string(),
+        );
+        let stylesheet =
+            crate::css::parse("p { display: block; } .tall { height: 40px; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let line = &layout_root.children[0];
+        let [hello, image, world, tall_image] = &line.children[..] else {
+            panic!("expected text, an image, text, and an image on one line");
+        };
+
+        // "Hello" and a space are 48px wide, and the image is 20px square after them.
+        let rect = image.dimensions.content;
+        assert_eq!((rect.x, rect.width, rect.height), (48.0, 20.0, 20.0));
+        // The text after it continues 20px further along the same line.
+        assert_eq!(world.fragments[0].rect.x, 68.0);
+        assert_eq!(world.fragments[0].line, hello.fragments[0].line);
+        // The image sits on the baseline, so its bottom is the text's baseline.
+        let text = hello.fragments[0].rect;
+        let baseline = text.y + baseline_offset(16.0, text.height);
+        assert!((rect.y + rect.height - baseline).abs() < 0.01);
+        // A `height` property wins over the attribute.
+        let rect = tall_image.dimensions.content;
+        assert_eq!((rect.width, rect.height), (30.0, 40.0));
+    }
+
+    #[test]
+    fn inline_padding_takes_space_on_the_line() {
+        let root = crate::html::parse(
+            "<div><span>aaaa</span><span>bbbb</span><span>cccc</span></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div { display: block; width: 100px; } \
+             span { padding-left: 4px; padding-right: 4px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let spans = &layout_root.children[0].children;
+        let border_box = |i: usize| spans[i].dimensions.border_box();
+
+        // Each span is 32px of text with 4px of padding on either side, so two fit in 100px.
+        assert_eq!((border_box(0).x, border_box(0).width), (0.0, 40.0));
+        assert_eq!((border_box(1).x, border_box(1).width), (40.0, 40.0));
+        assert_eq!(spans[1].children[0].fragments[0].rect.x, 44.0);
+        // The third wraps, taking its left padding to the next line.
+        assert_eq!(border_box(2).x, 0.0);
+        let text = spans[2].children[0].fragments[0].rect;
+        assert_eq!((text.x, text.y), (4.0, border_box(0).y + 19.2));
+    }
+
+    #[test]
+    fn inline_edges_resolve_relative_lengths() {
+        let root = crate::html::parse("<div><span>ab</span>c</div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { display: block; width: 100px; } \
+             span { font-size: 10px; padding-left: 1em; margin-right: 10%; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let span = &layout_root.children[0].children[0];
+        // 1em of the span's own font size, and 10% of the div's width.
+        assert_eq!(span.dimensions.padding.left, 10.0);
+        assert_eq!(span.dimensions.margin.right, 10.0);
+        assert_eq!(span.children[0].fragments[0].rect.x, 10.0);
+    }
+
+    #[test]
+    fn text_align_moves_each_line_into_its_free_space() {
+        let root = crate::html::parse(
+            "<div><p id=\"center\"><span>ab</span></p><p id=\"right\">aaaa bbbbb</p></div>"
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } #center { width: 200px; text-align: center; } \
+             #right { width: 60px; text-align: right; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        // The 16px wide span is moved by half of the 184px left over.
+        let span = &layout_root.children[0].children[0].children[0];
+        assert_eq!(span.dimensions.content.x, 92.0);
+        assert_eq!(span.children[0].fragments[0].rect.x, 92.0);
+        // Each line is moved by the space left on it.
+        let text = &layout_root.children[1].children[0].children[0];
+        let xs: Vec<f32> = text.fragments.iter().map(|f| f.rect.x).collect();
+        assert_eq!(xs, [28.0, 20.0]);
+    }
+
+    #[test]
+    fn inline_block_sits_on_the_line_at_its_own_size() {
+        let root = crate::html::parse("<p>aa <b>x</b>bb <i>hello world</i></p>".to_string());
+        let stylesheet = crate::css::parse(
+            "p { display: block; } b { display: inline-block; width: 50px; height: 10px; } \
+             i { display: inline-block; padding: 2px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let line = &layout_root.children[0];
+        let [aa, b, bb, i] = &line.children[..] else {
+            panic!("expected text, an inline-block, text, and an inline-block");
+        };
+        assert!(matches!(b.box_type, BlockNode(_)));
+
+        // "aa" and a space are 24px wide, then the 50px box, then the text after it.
+        let rect = b.dimensions.content;
+        assert_eq!((rect.x, rect.width, rect.height), (24.0, 50.0, 10.0));
+        assert_eq!(bb.fragments[0].rect.x, 74.0);
+        assert_eq!(bb.fragments[0].line, aa.fragments[0].line);
+        // It sits on the baseline by its bottom edge.
+        let text = aa.fragments[0].rect;
+        let baseline = text.y + baseline_offset(16.0, text.height);
+        assert!((rect.y + rect.height - baseline).abs() < 0.01);
+
+        // An auto width shrinks to fit the text inside, which is laid out in the box.
+        let d = i.dimensions;
+        assert_eq!((d.content.width, d.padding_box().x), (88.0, 98.0));
+        let inner_text = &i.children[0].children[0].fragments[0];
+        assert_eq!(inner_text.text, "hello world");
+        assert_eq!(inner_text.rect.x, d.content.x);
+    }
+
+    #[test]
+    fn styled_node_leads_back_to_dom_node() {
+        let root = crate::html::parse("<div><p>text</p></div>".to_string());
+        let stylesheet = crate::css::parse("div, p { display: block; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let p = &layout_root.children[0];
+        let tag_name = p
+            .styled_node()
+            .and_then(|styled| styled.node.as_element())
+            .map(|elem| elem.tag_name.as_str());
+        assert_eq!(tag_name, Some("p"));
+        assert!(p.children[0].styled_node().is_none());
+        let text = p.children[0].children[0].styled_node().unwrap();
+        assert_eq!(text.node.as_text(), Some("text"));
+    }
+
+    #[test]
+    fn overflow_axes_clip_and_scroll_separately() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } \
+             div { width: 100px; height: 50px; overflow-x: hidden; overflow-y: visible; } \
+             p { width: 300px; height: 200px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let padding_box = layout_root.dimensions.padding_box();
+
+        // The visible axis is treated as auto, so both are clipped, but only the vertical one
+        // can be scrolled.
+        assert_eq!(layout_root.clip_rect(), Some(padding_box));
+        let extent = layout_root.scroll_extent();
+        assert_eq!(extent.width, padding_box.width);
+        assert_eq!(extent.height, 200.0);
+    }
+
+    #[test]
+    fn tabs_expand_to_tab_stops_in_preformatted_text() {
+        let lay_out = |css: &str| {
+            let root = crate::html::parse("<div>a\tbc\td</div>".to_string());
+            let stylesheet = crate::css::parse(format!("div {{ display: block; {} }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+            layout_root.children[0].children[0].fragments[0]
+                .text
+                .clone()
+        };
+        assert_eq!(lay_out("white-space: pre;"), "a       bc      d");
+        assert_eq!(lay_out("white-space: pre; tab-size: 4;"), "a   bc  d");
+        // Collapsed, a tab is just white space.
+        assert_eq!(lay_out("tab-size: 4;"), "a bc d");
+    }
+
+    #[test]
+    fn vertical_rl_stacks_blocks_right_to_left() {
+        let root = crate::html::parse("<div><p></p><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div, p { display: block; } div { writing-mode: vertical-rl; } \
+             p { width: 100px; height: 50px; margin: 5px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let first = layout_root.children[0].dimensions.content;
+        let second = layout_root.children[1].dimensions.content;
+        assert_eq!((first.x, first.y), (695.0, 5.0));
+        assert_eq!((second.x, second.y), (585.0, 5.0));
+        // The container is as tall as its tallest child.
+        assert_eq!(layout_root.dimensions.content.height, 60.0);
+    }
+
+    #[test]
+    fn text_runs_follow_wrapped_lines() {
+        let root = crate::html::parse("<p>aaaa bbbb cccc</p>".to_string());
+        let stylesheet = crate::css::parse(
+            "p { display: block; width: 50px; font-size: 10px; line-height: 12px; color: #ff0000; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let runs = layout_tree(&styled_node, viewport).text_runs();
+        let red = Color::rgb(255, 0, 0);
+        let run = |text: &str, y, width| TextRun {
+            text: text.to_string(),
+            rect: Rect {
+                x: 0.0,
+                y,
+                width,
+                height: 12.0,
+            },
+            color: red,
+        };
+        assert_eq!(runs, [run("aaaa bbbb", 0.0, 45.0), run("cccc", 12.0, 20.0)]);
+    }
+
+    #[test]
+    fn first_line_colors_only_the_first_line() {
+        let root = crate::html::parse("<p>aaaa <b>bbbb cccc</b> dddd</p>".to_string());
+        let stylesheet = crate::css::parse(
+            "p { display: block; width: 50px; font-size: 10px; color: #0000ff; } \
+             p::first-line { color: #ff0000; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let runs = layout_tree(&styled_node, viewport).text_runs();
+        let (red, blue) = (Color::rgb(255, 0, 0), Color::rgb(0, 0, 255));
+        let runs: Vec<(&str, Color)> = runs.iter().map(|r| (r.text.as_str(), r.color)).collect();
+        assert_eq!(
+            runs,
+            [("aaaa", red), ("bbbb", red), ("cccc", blue), ("dddd", blue)]
+        );
+    }
+
+    #[test]
+    fn inline_runs_share_anonymous_boxes() {
+        let count = |html: String| {
+            let root = crate::html::parse(html);
+            let stylesheet = crate::css::parse("div, p { display: block; }".to_string());
+            let styled_node = style_tree(&root, &stylesheet);
+            build_layout_tree(&styled_node).anonymous_box_count()
+        };
+        // One anonymous box per run of inline content between blocks.
+        assert_eq!(
+            count("<div>a<b>b</b><p></p>c<p></p><p></p>d</div>".to_string()),
+            3
+        );
+        assert_eq!(count("<div><p></p><p></p></div>".to_string()), 0);
+        // There's no cap by default.
+        let runs = "a<p></p>".repeat(20);
+        let root = crate::html::parse(format!("<div>{}</div>", runs));
+        let stylesheet = crate::css::parse("div, p { display: block; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        assert_eq!(build_layout_tree(&styled_node).anonymous_box_count(), 20);
+        // Past a cap, runs that would need a new anonymous box are dropped, rather than moved
+        // into an earlier one ahead of the blocks that follow it.
+        let mut arena = LayoutArena::new().with_max_anonymous_children(10);
+        let layout_root = build_box(&styled_node, &mut ContentState::default(), &mut arena);
+        assert_eq!(layout_root.anonymous_box_count(), 10);
+        assert_eq!(layout_root.children.len(), 2 * 10 + 10);
+        assert!(layout_root
+            .children
+            .iter()
+            .filter(|child| matches!(child.box_type, AnonymousBlock))
+            .all(|child| child.children.len() == 1));
+    }
+
+    #[test]
+    fn anonymous_blocks_add_their_lines_to_the_height() {
+        let root = crate::html::parse("<div>text<p>x</p>more</div>".to_string());
+        let stylesheet = crate::css::parse("div, p { display: block; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+        let [before, p, after] = &layout_root.children[..] else {
+            panic!("expected an anonymous block, a paragraph, and an anonymous block");
+        };
+
+        // Each anonymous block spans the container and holds one line, stacked around the `p`.
+        assert!(matches!(before.box_type, AnonymousBlock));
+        assert_eq!(before.dimensions.content.width, DEFAULT_VIEWPORT.width);
+        assert_eq!(before.dimensions.content.height, 19.2);
+        assert_eq!(p.dimensions.content.y, 19.2);
+        assert_eq!(after.dimensions.content.y, 38.4);
+        assert_eq!(after.dimensions.content.height, 19.2);
+        assert!((layout_root.dimensions.content.height - 57.6).abs() < 0.01);
+    }
+
+    #[test]
+    fn paragraph_text_is_at_least_a_line_tall() {
+        let height = |css: &str| {
+            let root = crate::html::parse("<p>some text</p>".to_string());
+            let stylesheet = crate::css::parse(format!("p {{ display: block; {} }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            let line_height = styled_node.line_height();
+            let layout_root = layout_tree(&styled_node, Dimensions::from_content(DEFAULT_VIEWPORT));
+            (layout_root.dimensions.content.height, line_height)
+        };
+        // The default line height is 1.2 times the default 16px font size.
+        let (actual, line_height) = height("");
+        assert!((line_height - 19.2).abs() < 0.01);
+        assert!(actual >= line_height);
+        let (actual, line_height) = height("font-size: 20px; line-height: 30px;");
+        assert_eq!(line_height, 30.0);
+        assert!(actual >= line_height);
+    }
+
+    #[test]
+    fn auto_height_block_sizes_to_wrapped_lines() {
+        // Twelve 4-character words; 200px holds five of them per line at 8px per glyph.
+        let root = crate::html::parse(format!("<div>{}</div>", "abcd ".repeat(12)));
+        let stylesheet =
+            crate::css::parse("div { display: block; width: 200px; height: auto; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let line_height = styled_node.line_height();
+        assert_eq!(layout_root.dimensions.content.width, 200.0);
+        assert_eq!(layout_root.dimensions.content.height, 3.0 * line_height);
+    }
+
+    #[test]
+    fn decimal_list_item_markers() {
+        let root = crate::html::parse("<ol><li>a</li><li>b</li><li>c</li></ol>".to_string());
+        let user_agent = crate::style::user_agent_stylesheet();
+        let author = crate::css::parse("li { list-style-type: decimal; }".to_string());
+        let styled_node = crate::style::style_tree_with_origins(
+            &root,
+            &[
+                (crate::style::CascadeOrigin::UserAgent, &user_agent),
+                (crate::style::CascadeOrigin::Author, &author),
+            ],
+        );
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let markers: Vec<&str> = layout_root
+            .children
+            .iter()
+            .map(|item| item.children[0].children[0].fragments[0].text.as_str())
+            .collect();
+        assert_eq!(markers, vec!["1.", "2.", "3."]);
+        let text = &layout_root.children[1].children[0].children[1].fragments[0];
+        assert_eq!(text.text, "b");
+    }
+
+    #[test]
+    fn layout_box_child_accessors() {
+        let mut parent = LayoutBox::new(AnonymousBlock);
+        assert!(parent.first_child().is_none());
+        assert!(parent.last_child().is_none());
+        assert!(parent.child(0).is_none());
+
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let stylesheet = Stylesheet {
+            rules: vec![],
+            ..Default::default()
+        };
+        let styled_node = style_tree(&root, &stylesheet);
+        parent.children.push(LayoutBox::new(AnonymousBlock));
+        parent
+            .children
+            .push(LayoutBox::new(InlineNode(&styled_node)));
+        assert!(matches!(
+            parent.first_child().unwrap().box_type,
+            AnonymousBlock
+        ));
+        assert!(matches!(
+            parent.last_child().unwrap().box_type,
+            InlineNode(_)
+        ));
+        assert!(parent.child(2).is_none());
+    }
+
+    // The border box and text of every box in a layout tree, in tree order.
+    fn layout_summary(root: &LayoutBox) -> Vec<(Rect, Vec<TextFragment>)> {
+        root.iter_boxes()
+            .map(|(_, b)| (b.dimensions.border_box(), b.fragments.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn arena_layout_matches_default_layout() {
+        let root = crate::html::parse(
+            "<div><p>some <b>bold</b> text</p><ul><li>one</li><li>two</li></ul></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div, p, ul { display: block; } ul { padding-left: 20px; } \
+             li { display: list-item; } p::before { content: '> '; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(DEFAULT_VIEWPORT);
+        let expected = layout_summary(&layout_tree(&styled, viewport));
+
+        let mut arena = LayoutArena::new();
+        let first = layout_tree_in(&mut arena, &styled, viewport);
+        assert_eq!(layout_summary(&first), expected);
+
+        // The second layout takes all its vectors from the first.
+        arena.recycle(first);
+        assert_eq!(arena.available(), expected.len());
+        let second = layout_tree_in(&mut arena, &styled, viewport);
+        assert_eq!(layout_summary(&second), expected);
+        assert_eq!(arena.available(), 0);
+    }
+
+    #[test]
+    fn arena_layout_of_thousands_of_boxes() {
+        let paragraphs: String = (0..3000).map(|i| format!("<p>para {}</p>", i)).collect();
+        let root = crate::html::parse(format!("<div>{}</div>", paragraphs));
+        let stylesheet = crate::css::parse("div, p { display: block; margin: 0px; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(DEFAULT_VIEWPORT);
+
+        let mut arena = LayoutArena::new();
+        for _ in 0..3 {
+            let layout_root = layout_tree_in(&mut arena, &styled, viewport);
+            assert_eq!(layout_root.children.len(), 3000);
+            let height = layout_root.dimensions.content.height;
+            // Allow for rounding error summing thousands of line heights.
+            assert!((height - 3000.0 * 19.2).abs() < 5.0);
+            arena.recycle(layout_root);
+            // The root, and each paragraph with its anonymous block and text.
+            assert_eq!(arena.available(), 1 + 3000 * 3);
+        }
+    }
 }
@@ -0,0 +1,128 @@
+//! A toy web browser engine: parses HTML and CSS, and computes styles and layout for rendering.
+
+pub mod css;
+pub mod dom;
+pub mod html;
+pub mod index;
+pub mod layout;
+pub mod painting;
+pub mod pdf;
+pub mod properties;
+pub mod style;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod text;
+
+use layout::{Dimensions, LayoutBox};
+use std::fmt;
+
+/// An error from `render_tree`.
+#[derive(Debug, PartialEq)]
+pub enum RenderError {
+    /// The HTML couldn't be parsed.
+    Html(html::ParseError),
+    /// The CSS couldn't be parsed.
+    Css(css::CssParseError),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Html(err) => write!(f, "invalid HTML: {}", err),
+            RenderError::Css(err) => write!(f, "invalid CSS: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Html(err) => Some(err),
+            RenderError::Css(err) => Some(err),
+        }
+    }
+}
+
+impl From<html::ParseError> for RenderError {
+    fn from(err: html::ParseError) -> RenderError {
+        RenderError::Html(err)
+    }
+}
+
+impl From<css::CssParseError> for RenderError {
+    fn from(err: css::CssParseError) -> RenderError {
+        RenderError::Css(err)
+    }
+}
+
+/// Parse `html` and `css`, style the document with the user agent stylesheet and `css`, and lay it
+/// out in `viewport`. The layout tree borrows from the document and stylesheets, which only live
+/// for this call, so it's passed to `f` and `f`'s result is returned.
+pub fn render_tree<R>(
+    html: &str,
+    css: &str,
+    viewport: Dimensions,
+    f: impl FnOnce(&LayoutBox) -> R,
+) -> Result<R, RenderError> {
+    let root = html::try_parse(html.to_string())?;
+    let author = css::try_parse(css.to_string())?;
+    let user_agent = style::user_agent_stylesheet();
+    let style_root = style::style_tree_for_viewport(
+        &root,
+        &[
+            (style::CascadeOrigin::UserAgent, &user_agent),
+            (style::CascadeOrigin::Author, &author),
+        ],
+        viewport.content.width,
+    );
+    Ok(f(&layout::layout_tree(&style_root, viewport)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layout::Rect;
+
+    fn viewport() -> Dimensions {
+        Dimensions::from_content(Rect {
+            width: 800.0,
+            height: 600.0,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn render_tree_lays_out_page() {
+        let html = "<html><div></div><p></p></html>";
+        let css = "div { height: 30px; } p { height: 20px; margin: 5px; }";
+        let height = render_tree(html, css, viewport(), |root| root.dimensions.content.height);
+        assert_eq!(height, Ok(60.0));
+    }
+
+    #[test]
+    fn render_tree_reports_parse_errors() {
+        let result = render_tree("<div>", "", viewport(), |_| ());
+        assert!(matches!(result, Err(RenderError::Html(_))));
+        let result = render_tree("<div></div>", "div {", viewport(), |_| ());
+        assert!(matches!(result, Err(RenderError::Css(_))));
+    }
+
+    #[test]
+    fn render_errors_say_where_parsing_failed() {
+        let html_offset = match render_tree("<p><b></p>", "", viewport(), |_| ()) {
+            Err(RenderError::Html(html::ParseError::Syntax { offset, .. })) => offset,
+            _ => panic!("Expected an HTML syntax error"),
+        };
+        assert_eq!(html_offset, 6);
+
+        let css_error = match render_tree("<p></p>", "p { color red; }", viewport(), |_| ()) {
+            Err(RenderError::Css(err)) => err,
+            _ => panic!("Expected a CSS error"),
+        };
+        assert_eq!(css_error.offset, 10);
+        assert_eq!(
+            RenderError::Css(css_error).to_string(),
+            "invalid CSS: Expected ':' but found 'r' at byte 10"
+        );
+    }
+}
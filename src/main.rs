@@ -1,13 +1,52 @@
+use robinson::{css, html, layout, painting, pdf, style};
+use std::fmt::Display;
 use std::fs::File;
-use std::io::{BufWriter, Read};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::process;
 
-mod css;
-mod dom;
-mod html;
-mod layout;
-mod painting;
-mod pdf;
-mod style;
+// The supported output formats.
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Png,
+    Ppm,
+    Pdf,
+}
+
+const SUPPORTED_FORMATS: &str = "png, ppm, pdf";
+
+impl Format {
+    fn from_name(name: &str) -> Result<Format, String> {
+        match &name.to_ascii_lowercase()[..] {
+            "png" => Ok(Format::Png),
+            "ppm" => Ok(Format::Ppm),
+            "pdf" => Ok(Format::Pdf),
+            x => Err(format!(
+                "Unknown format: {} (supported formats: {})",
+                x, SUPPORTED_FORMATS
+            )),
+        }
+    }
+
+    // Pick the format from the extension of the output file.
+    fn from_filename(filename: &str) -> Result<Format, String> {
+        match Path::new(filename).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => Format::from_name(ext),
+            None => Err(format!(
+                "Can't tell the output format of {} (supported formats: {})",
+                filename, SUPPORTED_FORMATS
+            )),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Ppm => "ppm",
+            Format::Pdf => "pdf",
+        }
+    }
+}
 
 fn main() {
     // Parse command-line options:
@@ -15,63 +54,114 @@ fn main() {
     opts.optopt("h", "html", "HTML document", "FILENAME");
     opts.optopt("c", "css", "CSS stylesheet", "FILENAME");
     opts.optopt("o", "output", "Output file", "FILENAME");
-    opts.optopt("f", "format", "Output format", "png|pdf");
+    opts.optopt(
+        "f",
+        "format",
+        "Output format (defaults to the output file's extension)",
+        "png|ppm|pdf",
+    );
+    opts.optopt("", "width", "Viewport width (defaults to 800)", "PX");
+    opts.optopt("", "height", "Viewport height (defaults to 600)", "PX");
 
-    let matches = opts.parse(std::env::args().skip(1)).unwrap();
+    let matches = opts
+        .parse(std::env::args().skip(1))
+        .unwrap_or_else(|err| fail(err));
     let str_arg = |flag: &str, default: &str| -> String {
         matches.opt_str(flag).unwrap_or(default.to_string())
     };
 
-    // Choose a format:
-    let png = match &str_arg("f", "png")[..] {
-        "png" => true,
-        "pdf" => false,
-        x => panic!("Unknown format: {}", x),
+    // Choose a format, from `--format` if given, or else from the output file name:
+    let format = match matches.opt_str("f") {
+        Some(name) => Format::from_name(&name),
+        None => Format::from_filename(&str_arg("o", "output.png")),
     };
+    let format = format.unwrap_or_else(|err| fail(err));
 
     // Read input files:
-    let html = read_source(str_arg("h", "examples/index.html"));
-    let css = read_source(str_arg("c", "examples/style.css"));
+    let html_file = str_arg("h", "examples/index.html");
+    let css_file = str_arg("c", "examples/style.css");
+    let html = read_source(&html_file);
+    let css = read_source(&css_file);
 
-    // Since we don't have an actual window, hard-code the "viewport" size.
-    let mut viewport: layout::Dimensions = Default::default();
-    viewport.content.width = 800.0;
-    viewport.content.height = 600.0;
+    // Since we don't have an actual window, the "viewport" size comes from the options.
+    let size_arg = |flag: &str, default: f32| -> f32 {
+        match matches.opt_str(flag) {
+            Some(size) => size
+                .parse()
+                .unwrap_or_else(|_| fail(format!("Invalid --{}: {}", flag, size))),
+            None => default,
+        }
+    };
+    let viewport = layout::Dimensions::from_content(layout::Rect {
+        width: size_arg("width", layout::DEFAULT_VIEWPORT.width),
+        height: size_arg("height", layout::DEFAULT_VIEWPORT.height),
+        ..layout::DEFAULT_VIEWPORT
+    });
 
     // Parsing and rendering:
-    let root_node = html::parse(html);
-    let stylesheet = css::parse(css);
-    let style_root = style::style_tree(&root_node, &stylesheet);
+    let root_node = html::try_parse(html)
+        .unwrap_or_else(|err| fail(format!("Error parsing {}: {}", html_file, err)));
+    let stylesheet = css::try_parse(css)
+        .unwrap_or_else(|err| fail(format!("Error parsing {}: {}", css_file, err)));
+    let user_agent = style::user_agent_stylesheet();
+    let style_root = style::style_tree_for_viewport(
+        &root_node,
+        &[
+            (style::CascadeOrigin::UserAgent, &user_agent),
+            (style::CascadeOrigin::Author, &stylesheet),
+        ],
+        viewport.content.width,
+    );
     let layout_root = layout::layout_tree(&style_root, viewport);
 
     // Create the output file:
-    let filename = str_arg("o", if png { "output.png" } else { "output.pdf" });
-    let mut file = BufWriter::new(File::create(&filename).unwrap());
+    let filename = str_arg("o", &format!("output.{}", format.extension()));
+    let file = File::create(&filename)
+        .unwrap_or_else(|err| fail(format!("Can't create {}: {}", filename, err)));
+    let mut file = BufWriter::new(file);
 
     // Write to the file:
-    let ok = if png {
-        let canvas = painting::paint(&layout_root, viewport.content);
-        let (w, h) = (canvas.width as u32, canvas.height as u32);
-        let img = image::ImageBuffer::from_fn(w, h, move |x, y| {
-            let color = canvas.pixels[(y * w + x) as usize];
-            image::Pixel::from_channels(color.r, color.g, color.b, color.a)
-        });
-        image::ImageRgba8(img).save(&mut file, image::PNG).is_ok()
-    } else {
-        pdf::render(&layout_root, viewport.content, &mut file).is_ok()
+    let ok = match format {
+        Format::Png => write_png(painting::paint(&layout_root, viewport.content), &mut file),
+        Format::Ppm => painting::paint(&layout_root, viewport.content)
+            .write_ppm(&mut file)
+            .and_then(|_| file.flush())
+            .is_ok(),
+        Format::Pdf => pdf::render(&layout_root, viewport.content, &mut file).is_ok(),
     };
     if ok {
         println!("Saved output as {}", filename)
     } else {
-        println!("Error saving output as {}", filename)
+        fail(format!("Error saving output as {}", filename))
     }
 }
 
-fn read_source(filename: String) -> String {
+// Print `message` and exit with an error status.
+fn fail(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
+
+#[cfg(feature = "png")]
+fn write_png(canvas: painting::Canvas, file: &mut BufWriter<File>) -> bool {
+    let (w, h) = (canvas.width as u32, canvas.height as u32);
+    let img = image::ImageBuffer::from_fn(w, h, move |x, y| {
+        let color = canvas.pixels[(y * w + x) as usize];
+        image::Pixel::from_channels(color.r, color.g, color.b, color.a)
+    });
+    image::ImageRgba8(img).save(file, image::PNG).is_ok()
+}
+
+#[cfg(not(feature = "png"))]
+fn write_png(_canvas: painting::Canvas, _file: &mut BufWriter<File>) -> bool {
+    eprintln!("PNG output requires the `png` feature");
+    false
+}
+
+fn read_source(filename: &str) -> String {
     let mut str = String::new();
     File::open(filename)
-        .unwrap()
-        .read_to_string(&mut str)
-        .unwrap();
+        .and_then(|mut file| file.read_to_string(&mut str))
+        .unwrap_or_else(|err| fail(format!("Can't read {}: {}", filename, err)));
     str
 }
@@ -1,110 +1,436 @@
 use crate::css::{Color, Value};
-use crate::layout::BoxType::{AnonymousBlock, BlockNode, InlineNode};
-use crate::layout::{LayoutBox, Rect};
+use crate::layout::BoxType::{AnonymousBlock, BlockNode, InlineNode, Marker};
+use crate::layout::{EdgeSizes, LayoutBox, Rect};
+use crate::text::{baseline_offset, LINE_THROUGH_OFFSET, UNDERLINE_OFFSET};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 /// Paint a tree of LayoutBoxes to an array of pixels.
 pub fn paint(layout_box: &LayoutBox, bounds: Rect) -> Canvas {
+    paint_scaled(layout_box, bounds, 1.0)
+}
+
+/// Paint a tree of LayoutBoxes to an array of pixels, with `scale_factor` device pixels per px
+/// (for HiDPI displays). The canvas is `bounds` scaled by the same factor.
+pub fn paint_scaled(layout_box: &LayoutBox, bounds: Rect, scale_factor: f32) -> Canvas {
     let display_list = build_display_list(layout_box);
+    let bounds = bounds.scale(scale_factor);
     let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
+    canvas.scale_factor = scale_factor;
     for item in display_list {
         canvas.paint_item(&item);
     }
     canvas
 }
 
+/// Options for building a display list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaintOptions {
+    /// Images aren't decoded, so a `background-image` is drawn as a solid rect of this color.
+    pub image_placeholder: Color,
+}
+
+impl Default for PaintOptions {
+    fn default() -> PaintOptions {
+        PaintOptions {
+            image_placeholder: Color::rgb(204, 204, 204),
+        }
+    }
+}
+
 type DisplayList = Vec<DisplayCommand>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
+    // A run of text, drawn within the given rect.
+    Text(Color, Rect, String),
 }
 
 pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+    build_display_list_with(layout_root, &PaintOptions::default())
+}
+
+pub fn build_display_list_with(layout_root: &LayoutBox, options: &PaintOptions) -> DisplayList {
+    let mut list = Vec::new();
+    render_layout_box(&mut list, layout_root, options);
+    list
+}
+
+/// Call `f` with each command of the display list of a layout tree, in order, without building
+/// the whole list.
+pub fn for_each_command<F: FnMut(&DisplayCommand)>(layout_root: &LayoutBox, f: &mut F) {
+    render_layout_box(&mut Callback(f), layout_root, &PaintOptions::default());
+}
+
+/// Draw the box model of every box in a layout tree over `canvas`, like a browser's developer
+/// tools: a 1px outline of each margin, border, padding, and content box, in a color for each.
+pub fn debug_overlay(layout_root: &LayoutBox, canvas: &mut Canvas) {
+    for item in debug_overlay_list(layout_root) {
+        canvas.paint_item(&item);
+    }
+}
+
+/// The display list drawn by `debug_overlay`.
+pub fn debug_overlay_list(layout_root: &LayoutBox) -> DisplayList {
+    const MARGIN: Color = Color::rgb(246, 178, 107);
+    const BORDER: Color = Color::rgb(255, 229, 153);
+    const PADDING: Color = Color::rgb(147, 196, 125);
+    const CONTENT: Color = Color::rgb(111, 168, 220);
+    let one_px = EdgeSizes {
+        left: 1.0,
+        right: 1.0,
+        top: 1.0,
+        bottom: 1.0,
+    };
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    for (_, layout_box) in layout_root.iter_boxes() {
+        let d = layout_box.dimensions;
+        for (color, rect) in [
+            (MARGIN, d.margin_box()),
+            (BORDER, d.border_box()),
+            (PADDING, d.padding_box()),
+            (CONTENT, d.content),
+        ] {
+            render_frame(&mut list, color, rect, one_px);
+        }
+    }
     list
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-    render_background(list, layout_box);
-    render_borders(list, layout_box);
-    // TODO: render text
+// Somewhere display commands are sent to as they're generated: a list, or a callback.
+trait CommandSink {
+    fn push(&mut self, command: DisplayCommand);
+}
+
+impl CommandSink for DisplayList {
+    fn push(&mut self, command: DisplayCommand) {
+        Vec::push(self, command);
+    }
+}
+
+struct Callback<'f, F>(&'f mut F);
+
+impl<F: FnMut(&DisplayCommand)> CommandSink for Callback<'_, F> {
+    fn push(&mut self, command: DisplayCommand) {
+        (self.0)(&command);
+    }
+}
+
+// Passes commands on to another sink, clipped to `rect`. A run of text is drawn whole, so it's
+// only dropped if it's entirely outside.
+struct Clip<'s> {
+    sink: &'s mut dyn CommandSink,
+    rect: Rect,
+}
+
+impl CommandSink for Clip<'_> {
+    fn push(&mut self, command: DisplayCommand) {
+        let visible = |rect: Rect| rect.width > 0.0 && rect.height > 0.0;
+        match command {
+            DisplayCommand::SolidColor(color, rect) => {
+                let rect = rect.intersection(self.rect);
+                if visible(rect) {
+                    self.sink.push(DisplayCommand::SolidColor(color, rect));
+                }
+            }
+            DisplayCommand::Text(_, rect, _) if !visible(rect.intersection(self.rect)) => {}
+            command => self.sink.push(command),
+        }
+    }
+}
+
+/// The addresses of the images a layout tree refers to (from `background-image`), in paint order
+/// and without duplicates, so that a host application can fetch them.
+pub fn referenced_urls(layout_root: &LayoutBox) -> Vec<String> {
+    let mut urls = Vec::new();
+    collect_urls(layout_root, &mut urls);
+    urls
+}
 
+fn collect_urls(layout_box: &LayoutBox, urls: &mut Vec<String>) {
+    if let Some(url) = background_image(layout_box) {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        collect_urls(child, urls);
     }
 }
 
-fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    if let Some(color) = get_color(layout_box, "background") {
+// The address of the `background-image` of a box, if it has one.
+fn background_image(layout_box: &LayoutBox) -> Option<String> {
+    match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) => match style.value("background-image") {
+            Some(Value::Url(url)) => Some(url),
+            _ => None,
+        },
+        Marker(..) | AnonymousBlock => None,
+    }
+}
+
+/// The solid rectangles of a layout tree, with their colors, in paint order. This is for callers
+/// that draw with their own graphics library rather than rasterizing a `Canvas`. Text isn't
+/// included.
+pub fn to_rects(layout_root: &LayoutBox) -> Vec<(Rect, Color)> {
+    build_display_list(layout_root)
+        .into_iter()
+        .filter_map(|item| match item {
+            DisplayCommand::SolidColor(color, rect) => Some((rect, color)),
+            DisplayCommand::Text(..) => None,
+        })
+        .collect()
+}
+
+fn render_layout_box(list: &mut dyn CommandSink, layout_box: &LayoutBox, options: &PaintOptions) {
+    // Hidden boxes draw nothing themselves, but their children may be visible.
+    let visible = match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) | Marker(style, _) => style.is_visible(),
+        AnonymousBlock => true,
+    };
+    if visible {
+        // List markers only draw their text.
+        if !matches!(layout_box.box_type, Marker(..)) {
+            render_box_shadow(list, layout_box);
+            render_background(list, layout_box, options);
+            render_borders(list, layout_box);
+        }
+        render_text(list, layout_box);
+        render_outline(list, layout_box);
+    }
+
+    // Descendants are clipped by a box with `overflow` other than visible.
+    let mut clipped;
+    let list = match layout_box.clip_rect() {
+        Some(rect) => {
+            clipped = Clip { sink: list, rect };
+            &mut clipped as &mut dyn CommandSink
+        }
+        None => list,
+    };
+    for child in layout_box.paint_order_children() {
+        render_layout_box(list, child, options);
+    }
+}
+
+// Draw the background color, from `background-color` or else the `background` shorthand, then
+// the placeholder for any background image over it.
+fn render_background(list: &mut dyn CommandSink, layout_box: &LayoutBox, options: &PaintOptions) {
+    let color =
+        get_color(layout_box, "background-color").or_else(|| get_color(layout_box, "background"));
+    if let Some(color) = color.filter(is_visible) {
         list.push(DisplayCommand::SolidColor(
             color,
             layout_box.dimensions.border_box(),
         ));
     }
+    if background_image(layout_box).is_some() && is_visible(&options.image_placeholder) {
+        list.push(DisplayCommand::SolidColor(
+            options.image_placeholder,
+            layout_box.dimensions.border_box(),
+        ));
+    }
+}
+
+// Draw a `box-shadow: <x> <y> [<blur>] <color>` as a solid copy of the border box, offset by
+// (x, y). Blur is ignored for now.
+fn render_box_shadow(list: &mut dyn CommandSink, layout_box: &LayoutBox) {
+    let style = match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) => style,
+        Marker(..) | AnonymousBlock => return,
+    };
+    let values = match style.value("box-shadow") {
+        Some(Value::List(values)) => values,
+        _ => return,
+    };
+    let (x, y, color) = match values.as_slice() {
+        [x, y, color] | [x, y, _, color] => (x.to_px(), y.to_px(), color.to_color()),
+        _ => return,
+    };
+    if let Some(color) = color.filter(is_visible) {
+        let border_box = layout_box.dimensions.border_box();
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x + x,
+                y: border_box.y + y,
+                ..border_box
+            },
+        ));
+    }
+}
+
+fn render_text(list: &mut dyn CommandSink, layout_box: &LayoutBox) {
+    // Text is black unless a color is specified.
+    let color = get_color(layout_box, "color").unwrap_or(Color::rgb(0, 0, 0));
+    let style = match layout_box.box_type {
+        InlineNode(style) | Marker(style, _) if !layout_box.fragments.is_empty() => style,
+        _ => return,
+    };
+    let decoration = style.text_decoration();
+    let font_size = style.font_size();
+    // Decoration lines are a twelfth of the font size thick, but at least 1px.
+    let thickness = (font_size / 12.0).max(1.0);
+    for fragment in &layout_box.fragments {
+        let color = fragment.color.unwrap_or(color);
+        if !is_visible(&color) {
+            continue;
+        }
+        list.push(DisplayCommand::Text(
+            color,
+            fragment.rect,
+            fragment.text.clone(),
+        ));
+        let baseline = fragment.rect.y + baseline_offset(font_size, fragment.rect.height);
+        let line_at = |y: f32| Rect {
+            y: y - thickness / 2.0,
+            height: thickness,
+            ..fragment.rect
+        };
+        if decoration.underline {
+            let rect = line_at(baseline + font_size * UNDERLINE_OFFSET);
+            list.push(DisplayCommand::SolidColor(color, rect));
+        }
+        if decoration.line_through {
+            let rect = line_at(baseline - font_size * LINE_THROUGH_OFFSET);
+            list.push(DisplayCommand::SolidColor(color, rect));
+        }
+    }
 }
 
 // Return the specified color for CSS property `name`, or None if no color was specified.
 fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
     match layout_box.box_type {
-        BlockNode(style) | InlineNode(style) => match style.value(name) {
-            Some(Value::ColorValue(color)) => Some(color),
-            _ => None,
-        },
+        BlockNode(style) | InlineNode(style) | Marker(style, _) => style.computed_color(name),
         AnonymousBlock => None,
     }
 }
 
-fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
+// Fully transparent colors don't need to be drawn at all.
+fn is_visible(color: &Color) -> bool {
+    color.a > 0
+}
+
+/// Placeholder shapes for the glyphs of a run of text drawn in `rect`. Without real fonts, each
+/// visible character is drawn as a block in the middle of its cell.
+pub fn glyph_rects(rect: Rect, text: &str) -> Vec<Rect> {
+    let count = text.chars().count();
+    if count == 0 {
+        return Vec::new();
+    }
+    let advance = rect.width / count as f32;
+    text.chars()
+        .enumerate()
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(i, _)| Rect {
+            x: rect.x + (i as f32 + 0.1) * advance,
+            y: rect.y + rect.height * 0.25,
+            width: advance * 0.8,
+            height: rect.height * 0.5,
+        })
+        .collect()
+}
+
+fn render_borders(list: &mut dyn CommandSink, layout_box: &LayoutBox) {
+    let color = match get_color(layout_box, "border-color").filter(is_visible) {
         Some(color) => color,
         _ => return,
     };
-
     let d = &layout_box.dimensions;
-    let border_box = d.border_box();
+    render_frame(list, color, d.border_box(), d.border);
+}
+
+// Draw an `outline` (or `outline-width` and `outline-color`) just outside the border box. Unlike
+// a border, it takes up no space in the layout. The color defaults to the text color.
+fn render_outline(list: &mut dyn CommandSink, layout_box: &LayoutBox) {
+    let style = match layout_box.box_type {
+        BlockNode(style) | InlineNode(style) => style,
+        Marker(..) | AnonymousBlock => return,
+    };
+    let shorthand = match style.value("outline") {
+        Some(Value::List(values)) => values,
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    let width = style
+        .value("outline-width")
+        .or_else(|| {
+            shorthand
+                .iter()
+                .find(|value| matches!(value, Value::Length(..)))
+                .cloned()
+        })
+        .map_or(0.0, |value| value.to_px());
+    let color = style
+        .value("outline-color")
+        .and_then(|value| value.to_color())
+        .or_else(|| shorthand.iter().find_map(Value::to_color))
+        .or_else(|| style.computed_color("color"))
+        .unwrap_or(Color::rgb(0, 0, 0));
+    if width <= 0.0 || !is_visible(&color) {
+        return;
+    }
+    let border_box = layout_box.dimensions.border_box();
+    let outline_box = Rect {
+        x: border_box.x - width,
+        y: border_box.y - width,
+        width: border_box.width + 2.0 * width,
+        height: border_box.height + 2.0 * width,
+    };
+    let edges = EdgeSizes {
+        left: width,
+        right: width,
+        top: width,
+        bottom: width,
+    };
+    render_frame(list, color, outline_box, edges);
+}
 
-    // Left border
+// Draw the edges of `rect`, each as wide as given by `widths`, inside it.
+fn render_frame(list: &mut dyn CommandSink, color: Color, rect: Rect, widths: EdgeSizes) {
+    // Left edge
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
+            x: rect.x,
+            y: rect.y,
+            width: widths.left,
+            height: rect.height,
         },
     ));
 
-    // Right border
+    // Right edge
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
+            x: rect.x + rect.width - widths.right,
+            y: rect.y,
+            width: widths.right,
+            height: rect.height,
         },
     ));
 
-    // Top border
+    // Top edge
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: widths.top,
         },
     ));
 
-    // Bottom border
+    // Bottom edge
     list.push(DisplayCommand::SolidColor(
         color,
         Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
+            x: rect.x,
+            y: rect.y + rect.height - widths.bottom,
+            width: rect.width,
+            height: widths.bottom,
         },
     ));
 }
@@ -114,6 +440,8 @@ pub struct Canvas {
     pub pixels: Vec<Color>,
     pub width: usize,
     pub height: usize,
+    // Device pixels per px. Display items are in px, and scaled as they are painted.
+    scale_factor: f32,
 }
 
 impl Canvas {
@@ -129,25 +457,567 @@ impl Canvas {
             pixels: vec![white; width * height],
             width,
             height,
+            scale_factor: 1.0,
         }
     }
 
+    /// Write the canvas as a binary PPM (P6) image. Alpha is dropped.
+    pub fn write_ppm<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        write!(output, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let bytes: Vec<u8> = self.pixels.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+        output.write_all(&bytes)
+    }
+
+    /// Write the canvas to the file at `path` as a binary PPM image, replacing the file if it
+    /// exists.
+    pub fn save_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_ppm(&mut file)?;
+        file.flush()
+    }
+
     fn paint_item(&mut self, item: &DisplayCommand) {
         match *item {
+            DisplayCommand::SolidColor(color, rect) => self.fill_rect(color, rect),
+            DisplayCommand::Text(color, rect, ref text) => {
+                for glyph in glyph_rects(rect, text) {
+                    self.fill_rect(color, glyph);
+                }
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, color: Color, rect: Rect) {
+        let rect = rect.scale(self.scale_factor);
+
+        // Clip the rectangle to the canvas boundaries.
+        let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
+        let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
+        let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
+        let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // TODO: alpha compositing with existing pixel
+                self.pixels[x + y * self.width] = color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{layout_tree, Dimensions};
+    use crate::style::{style_tree, StyledNode};
+
+    fn text_commands(css: &str) -> Vec<Color> {
+        let root = crate::html::parse("<div>hello</div>".to_string());
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        build_display_list(&layout_root)
+            .into_iter()
+            .filter_map(|item| match item {
+                DisplayCommand::Text(color, ..) => Some(color),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn background_images_are_placeholders_with_referenced_urls() {
+        let root = crate::html::parse("<div><p></p><p></p></div>".to_string());
+        let css = r#"div, p { display: block; height: 10px; } div { height: auto; }
+            p { background: #ff0000; background-image: url("a.png"); }
+            div { background-image: url(b.png); }"#;
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        assert_eq!(referenced_urls(&layout_root), ["b.png", "a.png"]);
+
+        let placeholder = Color::rgb(1, 2, 3);
+        let options = PaintOptions {
+            image_placeholder: placeholder,
+        };
+        let colors: Vec<Color> = build_display_list_with(&layout_root, &options)
+            .into_iter()
+            .filter_map(|item| match item {
+                DisplayCommand::SolidColor(color, _) => Some(color),
+                _ => None,
+            })
+            .collect();
+        let red = Color::rgb(255, 0, 0);
+        assert_eq!(colors, [placeholder, red, placeholder, red, placeholder]);
+    }
+
+    #[test]
+    fn outline_is_painted_outside_the_border_box_without_layout() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let layout = |css: &str| {
+            let stylesheet = crate::css::parse(css.to_string());
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 800.0,
+                ..Default::default()
+            });
+            let layout_root = layout_tree(&styled_node, viewport);
+            (layout_root.dimensions, to_rects(&layout_root))
+        };
+        let base = "div { display: block; width: 100px; height: 50px; margin: 10px; }";
+        let (plain, plain_rects) = layout(base);
+        let (outlined, rects) = layout(&format!("{} div {{ outline: 2px red; }}", base));
+        assert!(plain_rects.is_empty());
+        assert_eq!(outlined.content, plain.content);
+        assert_eq!(outlined.border_box(), plain.border_box());
+
+        let red = Color::rgb(255, 0, 0);
+        assert!(rects.iter().all(|&(_, color)| color == red));
+        let rects: Vec<Rect> = rects.into_iter().map(|(rect, _)| rect).collect();
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            rects,
+            [
+                rect(8.0, 8.0, 2.0, 54.0),
+                rect(110.0, 8.0, 2.0, 54.0),
+                rect(8.0, 8.0, 104.0, 2.0),
+                rect(8.0, 60.0, 104.0, 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn z_index_only_lifts_positioned_boxes() {
+        let root =
+            crate::html::parse("<div><p class=\"a\"></p><p class=\"b\"></p></div>".to_string());
+        let colors = |css: &str| {
+            let css = format!(
+                "div, p {{ display: block; height: 10px; }} .a {{ background: #ff0000; }} \
+                 .b {{ background: #0000ff; }} {}",
+                css
+            );
+            let stylesheet = crate::css::parse(css);
+            let styled_node = style_tree(&root, &stylesheet);
+            let viewport = Dimensions::from_content(Rect {
+                width: 800.0,
+                ..Default::default()
+            });
+            let layout_root = layout_tree(&styled_node, viewport);
+            to_rects(&layout_root)
+                .into_iter()
+                .map(|(_, color)| color)
+                .collect::<Vec<_>>()
+        };
+        let (red, blue) = (Color::rgb(255, 0, 0), Color::rgb(0, 0, 255));
+        assert_eq!(colors(""), [red, blue]);
+        assert_eq!(colors(".a { z-index: 5; }"), [red, blue]);
+        assert_eq!(colors(".a { z-index: 5; position: static; }"), [red, blue]);
+        assert_eq!(
+            colors(".a { z-index: 5; position: relative; }"),
+            [blue, red]
+        );
+        assert_eq!(
+            colors(".b { z-index: -1; position: relative; }"),
+            [blue, red]
+        );
+    }
+
+    #[test]
+    fn overflow_clips_descendants_to_padding_box() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let css = "div, p { display: block; } \
+                   div { width: 100px; height: 50px; padding: 10px; overflow: hidden; } \
+                   p { width: 300px; height: 200px; background: #ff0000; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let rects: Vec<Rect> = to_rects(&layout_root).into_iter().map(|(r, _)| r).collect();
+        assert_eq!(
+            rects,
+            // The child starts inside the padding, and is cut off at the padding box's far edges.
+            [Rect {
+                x: 10.0,
+                y: 10.0,
+                width: 110.0,
+                height: 60.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn for_each_command_matches_display_list() {
+        let root = crate::html::parse("<div><p>hello world</p><p></p></div>".to_string());
+        let css = "div, p { display: block; margin: 2px; background: #ff0000; } \
+                   p { border-color: #0000ff; border-width: 1px; box-shadow: 1px 1px black; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let mut streamed = Vec::new();
+        for_each_command(&layout_root, &mut |command: &DisplayCommand| {
+            streamed.push(command.clone())
+        });
+        let listed = build_display_list(&layout_root);
+        assert!(!listed.is_empty());
+        assert_eq!(streamed, listed);
+    }
+
+    #[test]
+    fn debug_overlay_outlines_each_box_model_area() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let css = "div { display: block; width: 100px; height: 50px; margin: 10px; \
+                   border-width: 2px; padding: 5px; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let list = debug_overlay_list(&layout_root);
+        assert_eq!(list.len(), 16);
+        // The left edge of each area, from the margin box in.
+        let left_edges: Vec<Rect> = list
+            .iter()
+            .step_by(4)
+            .map(|item| match *item {
+                DisplayCommand::SolidColor(_, rect) => rect,
+                _ => panic!("Expected a solid color"),
+            })
+            .collect();
+        let rect = |x, y, height| Rect {
+            x,
+            y,
+            width: 1.0,
+            height,
+        };
+        assert_eq!(
+            left_edges,
+            [
+                rect(0.0, 0.0, 84.0),
+                rect(10.0, 10.0, 64.0),
+                rect(12.0, 12.0, 60.0),
+                rect(17.0, 17.0, 50.0),
+            ]
+        );
+
+        let mut canvas = paint(
+            &layout_root,
+            Rect {
+                height: 100.0,
+                ..viewport.content
+            },
+        );
+        debug_overlay(&layout_root, &mut canvas);
+        assert_eq!(
+            canvas.pixels[17 * canvas.width + 17],
+            Color::rgb(111, 168, 220)
+        );
+    }
+
+    #[test]
+    fn box_shadow_is_painted_behind_the_box() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let css = "div { display: block; width: 100px; height: 50px; background: #ffffff; box-shadow: 4px 4px black; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let list = build_display_list(&layout_root);
+        match list[0] {
             DisplayCommand::SolidColor(color, rect) => {
-                // Clip the rectangle to the canvas boundaries.
-                let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
-                let y0 = rect.y.clamp(0.0, self.width as f32) as usize;
-                let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
-                let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
-
-                for y in y0..y1 {
-                    for x in x0..x1 {
-                        // TODO: alpha compositing with existing pixel
-                        self.pixels[x + y * self.width] = color;
+                assert_eq!(color, Color::rgb(0, 0, 0));
+                assert_eq!(
+                    rect,
+                    Rect {
+                        x: 4.0,
+                        y: 4.0,
+                        width: 100.0,
+                        height: 50.0
                     }
-                }
+                );
             }
+            _ => panic!("Expected the shadow to be painted first"),
         }
+        assert!(
+            matches!(list[1], DisplayCommand::SolidColor(color, _) if color == Color::rgb(255, 255, 255))
+        );
+    }
+
+    #[test]
+    fn single_block_background_color() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let css = "div { display: block; width: 50px; height: 20px; background-color: #00ff00; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let layout_root = layout_tree(&styled_node, Dimensions::default());
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 50.0,
+            height: 20.0,
+        };
+        assert_eq!(
+            build_display_list(&layout_root),
+            vec![DisplayCommand::SolidColor(Color::rgb(0, 255, 0), rect)]
+        );
+    }
+
+    #[test]
+    fn paint_fills_the_pixels_of_a_rect() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let css = "div { display: block; width: 50px; height: 50px; background: #ff0000; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let bounds = Rect {
+            width: 100.0,
+            height: 100.0,
+            ..Default::default()
+        };
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(bounds));
+        let canvas = paint(&layout_root, bounds);
+        assert_eq!(canvas.pixels.len(), 100 * 100);
+        assert_eq!(canvas.pixels[25 + 25 * 100], Color::rgb(255, 0, 0));
+        assert_eq!(canvas.pixels[75 + 25 * 100], Color::rgb(255, 255, 255));
+        assert_eq!(canvas.pixels[25 + 75 * 100], Color::rgb(255, 255, 255));
+
+        let path = std::env::temp_dir().join(format!("paint-{}.ppm", std::process::id()));
+        canvas.save_ppm(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(bytes.starts_with(b"P6\n100 100\n255\n"));
+        assert_eq!(bytes.len(), "P6\n100 100\n255\n".len() + 100 * 100 * 3);
+    }
+
+    #[test]
+    fn nested_overflow_clips_paint_only_inside_every_clip() {
+        let root = crate::html::parse("<div><section><p></p></section></div>".to_string());
+        let css = "div, section, p { display: block; overflow: hidden; } \
+                   div { width: 40px; height: 60px; } \
+                   section { width: 80px; height: 30px; } \
+                   p { width: 90px; height: 90px; background: #ff0000; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let bounds = Rect {
+            width: 100.0,
+            height: 100.0,
+            ..Default::default()
+        };
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(bounds));
+        let canvas = paint(&layout_root, bounds);
+        let pixel = |x: usize, y: usize| canvas.pixels[x + y * 100];
+        let (red, white) = (Color::rgb(255, 0, 0), Color::rgb(255, 255, 255));
+        // The child paints where both ancestors' padding boxes overlap: 40px by 30px.
+        assert_eq!(pixel(0, 0), red);
+        assert_eq!(pixel(39, 29), red);
+        // Just past the div's right edge, and the section's bottom edge.
+        assert_eq!(pixel(40, 10), white);
+        assert_eq!(pixel(10, 30), white);
+    }
+
+    #[test]
+    fn child_background_paints_over_parent() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let css = "div { display: block; padding: 10px; background-color: #0000ff; } \
+                   p { display: block; height: 20px; background-color: #ff0000; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let bounds = Rect {
+            width: 100.0,
+            height: 100.0,
+            ..Default::default()
+        };
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(bounds));
+        let canvas = paint(&layout_root, bounds);
+        let pixel = |x: usize, y: usize| canvas.pixels[x + y * canvas.width];
+        assert_eq!(pixel(50, 20), Color::rgb(255, 0, 0));
+        assert_eq!(pixel(5, 5), Color::rgb(0, 0, 255));
+        // Below the parent, nothing is painted over the white canvas.
+        assert_eq!(pixel(50, 60), Color::rgb(255, 255, 255));
+    }
+
+    #[test]
+    fn rects_are_clipped_to_the_canvas() {
+        // A tall, narrow canvas, so rows below its width are still inside it.
+        let mut canvas = Canvas::new(10, 40);
+        let red = Color::rgb(255, 0, 0);
+        let blue = Color::rgb(0, 0, 255);
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        canvas.paint_item(&DisplayCommand::SolidColor(
+            red,
+            rect(-5.0, 20.0, 10.0, 100.0),
+        ));
+        canvas.paint_item(&DisplayCommand::SolidColor(
+            blue,
+            rect(8.0, -5.0, 10.0, 10.0),
+        ));
+        assert_eq!(canvas.pixels[4 + 20 * 10], red);
+        assert_eq!(canvas.pixels[4 + 39 * 10], red);
+        assert_eq!(canvas.pixels[5 + 20 * 10], Color::rgb(255, 255, 255));
+        assert_eq!(canvas.pixels[4 + 19 * 10], Color::rgb(255, 255, 255));
+        assert_eq!(canvas.pixels[9 + 4 * 10], blue);
+        assert_eq!(canvas.pixels[9 + 5 * 10], Color::rgb(255, 255, 255));
+        // Later commands paint over earlier ones.
+        canvas.paint_item(&DisplayCommand::SolidColor(
+            blue,
+            rect(0.0, 30.0, 10.0, 1.0),
+        ));
+        assert_eq!(canvas.pixels[2 + 30 * 10], blue);
+    }
+
+    #[test]
+    fn to_rects_in_paint_order() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let css = "div { display: block; padding: 10px; background: #ff0000; } p { display: block; height: 20px; background: #0000ff; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 100.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            to_rects(&layout_root),
+            vec![
+                (rect(0.0, 0.0, 100.0, 40.0), Color::rgb(255, 0, 0)),
+                (rect(10.0, 10.0, 80.0, 20.0), Color::rgb(0, 0, 255)),
+            ]
+        );
+    }
+
+    #[test]
+    fn paint_at_double_scale() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let css =
+            "div { display: block; width: 10px; height: 10px; margin: 5px; background: #ff0000; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let bounds = Rect {
+            width: 40.0,
+            height: 30.0,
+            ..Default::default()
+        };
+        let layout_root = layout_tree(&styled_node, Dimensions::from_content(bounds));
+        let canvas = paint_scaled(&layout_root, bounds, 2.0);
+        assert_eq!((canvas.width, canvas.height), (80, 60));
+        let red = Color::rgb(255, 0, 0);
+        let painted: Vec<(usize, usize)> = (0..canvas.height)
+            .flat_map(|y| (0..canvas.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| canvas.pixels[x + y * canvas.width] == red)
+            .collect();
+        assert_eq!(painted.len(), 20 * 20);
+        assert_eq!(painted[0], (10, 10));
+        assert_eq!(painted[painted.len() - 1], (29, 29));
+    }
+
+    #[test]
+    fn text_decoration_lines_cross_each_text_run() {
+        let root = crate::html::parse("<div>hello</div>".to_string());
+        let css = "div { display: block; font-size: 24px; line-height: 24px; color: red; \
+                   text-decoration: underline line-through; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout_root = layout_tree(&styled_node, viewport);
+        let list = build_display_list(&layout_root);
+        let red = Color::rgb(255, 0, 0);
+        // The baseline is 19.2px down; the lines are 2px thick.
+        let line = |y| {
+            DisplayCommand::SolidColor(
+                red,
+                Rect {
+                    x: 0.0,
+                    y,
+                    width: 60.0,
+                    height: 2.0,
+                },
+            )
+        };
+        assert!(matches!(list[0], DisplayCommand::Text(..)));
+        assert_eq!(list[1..], [line(20.6), line(11.0)]);
+    }
+
+    #[test]
+    fn toggling_display_and_visibility_on_relayout() {
+        let root = crate::html::parse("<div><p></p><p></p></div>".to_string());
+        let css = "div, p { display: block; } p { height: 10px; background: #ff0000; }";
+        let stylesheet = crate::css::parse(css.to_string());
+        let mut styled_node = style_tree(&root, &stylesheet);
+        let viewport = Dimensions::from_content(Rect {
+            width: 800.0,
+            ..Default::default()
+        });
+        let layout = |styled_node: &StyledNode| {
+            let layout_root = layout_tree(styled_node, viewport);
+            let rects = to_rects(&layout_root).len();
+            (
+                layout_root.children.len(),
+                layout_root.dimensions.content.height,
+                rects,
+            )
+        };
+        assert_eq!(layout(&styled_node), (2, 20.0, 2));
+
+        let none = Some(Value::Keyword("none".to_string()));
+        styled_node.children[1].set_value("display", none);
+        assert_eq!(layout(&styled_node), (1, 10.0, 1));
+
+        let block = Some(Value::Keyword("block".to_string()));
+        styled_node.children[1].set_value("display", block);
+        assert_eq!(layout(&styled_node), (2, 20.0, 2));
+
+        // A hidden box keeps its space but isn't painted.
+        let hidden = Some(Value::Keyword("hidden".to_string()));
+        styled_node.children[1].set_value("visibility", hidden);
+        assert_eq!(layout(&styled_node), (2, 20.0, 1));
+    }
+
+    #[test]
+    fn transparent_text_is_not_painted() {
+        let css = "div { display: block; color: transparent; }";
+        assert!(text_commands(css).is_empty());
+    }
+
+    #[test]
+    fn named_text_color() {
+        let css = "div { display: block; color: red; }";
+        assert_eq!(text_commands(css), vec![Color::rgb(255, 0, 0)]);
     }
 }
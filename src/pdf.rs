@@ -1,6 +1,7 @@
+use crate::css::Color;
 use crate::layout::{LayoutBox, Rect};
-use crate::painting::{build_display_list, DisplayCommand};
-use std::io::{self, Seek, SeekFrom, Write};
+use crate::painting::{build_display_list, glyph_rects, DisplayCommand};
+use std::io::{self, Seek, Write};
 
 fn px_to_pt(value: f32) -> f32 {
     // 96px = 1in = 72pt
@@ -27,23 +28,31 @@ pub fn render<W: Write + Seek>(
 
 fn render_item<W: Write>(item: &DisplayCommand, output: &mut W) -> io::Result<()> {
     match *item {
-        DisplayCommand::SolidColor(color, rect) => {
-            writeln!(
-                output,
-                "{} {} {} sc {} {} {} {} re f",
-                // FIMXE: alpha transparency
-                color.r,
-                color.g,
-                color.b,
-                rect.x,
-                rect.y,
-                rect.width,
-                rect.height
-            )
+        DisplayCommand::SolidColor(color, rect) => render_rect(color, rect, output),
+        DisplayCommand::Text(color, rect, ref text) => {
+            for glyph in glyph_rects(rect, text) {
+                render_rect(color, glyph, output)?;
+            }
+            Ok(())
         }
     }
 }
 
+fn render_rect<W: Write>(color: Color, rect: Rect, output: &mut W) -> io::Result<()> {
+    writeln!(
+        output,
+        "{} {} {} sc {} {} {} {} re f",
+        // FIMXE: alpha transparency
+        color.r,
+        color.g,
+        color.b,
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height
+    )
+}
+
 struct Pdf<'a, W: 'a + Write + Seek> {
     output: &'a mut W,
     object_offsets: Vec<i64>,
@@ -68,7 +77,7 @@ impl<'a, W: Write + Seek> Pdf<'a, W> {
 
     /// Return the current read/write position in the output file.
     fn tell(&mut self) -> io::Result<u64> {
-        self.output.seek(SeekFrom::Current(0))
+        self.output.stream_position()
     }
 
     fn render_page<F>(&mut self, width: f32, height: f32, render_contents: F) -> io::Result<()>
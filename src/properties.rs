@@ -0,0 +1,274 @@
+//! The CSS properties the engine knows about: whether each is inherited, its initial value, and
+//! what values it accepts. The cascade and `css::validate` look properties up here, so a new
+//! property only needs registering in `STANDARD_PROPERTIES` below.
+
+use crate::css::{parse_value_str, Value};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use ValueType::{Any, BoxShorthand, Color, Keywords, Length};
+
+/// The kind of value a property accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueType {
+    Color,
+    /// A length, or one of these keywords (in any case).
+    Length(&'static [&'static str]),
+    /// One to four values accepted by `Length` with these keywords, for the top, right, bottom,
+    /// and left sides of a box, as in `margin`.
+    BoxShorthand(&'static [&'static str]),
+    /// One of these keywords (in any case).
+    Keywords(&'static [&'static str]),
+    /// Anything; the property's own accessor in `style` makes sense of it.
+    Any,
+}
+
+impl ValueType {
+    /// Is `value` of this type? The global keywords `initial`, `inherit`, and `unset` aren't
+    /// checked here, since every property accepts them.
+    pub fn accepts(self, value: &Value) -> bool {
+        match self {
+            ValueType::Color => value.to_color().is_some(),
+            ValueType::Length(keywords) => {
+                matches!(
+                    value,
+                    Value::Length(..) | Value::Number(0.0) | Value::Math(..)
+                ) || keywords.iter().any(|k| value.keyword_eq(k))
+            }
+            ValueType::BoxShorthand(keywords) => match value {
+                Value::List(values) => {
+                    (1..=4).contains(&values.len())
+                        && values.iter().all(|v| Length(keywords).accepts(v))
+                }
+                value => Length(keywords).accepts(value),
+            },
+            ValueType::Keywords(keywords) => keywords.iter().any(|k| value.keyword_eq(k)),
+            ValueType::Any => true,
+        }
+    }
+}
+
+/// The description of a property.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyDef {
+    pub name: String,
+    pub value_type: ValueType,
+    /// Is the property inherited from the parent element when not specified?
+    pub inherited: bool,
+    /// The value the property has if it isn't specified or inherited, or `None` for shorthands
+    /// like `all` that have no single initial value. The cascade leaves a property reset to its
+    /// initial value unset. The accessors in `style` hard-code their own defaults,
+    /// which should agree with this.
+    pub initial: Option<Value>,
+}
+
+/// A set of known properties, by name.
+#[derive(Clone, Debug, Default)]
+pub struct PropertyRegistry {
+    properties: HashMap<String, PropertyDef>,
+}
+
+impl PropertyRegistry {
+    /// An empty registry.
+    pub fn new() -> PropertyRegistry {
+        PropertyRegistry::default()
+    }
+
+    /// The properties the engine implements.
+    pub fn standard() -> &'static PropertyRegistry {
+        static STANDARD: OnceLock<PropertyRegistry> = OnceLock::new();
+        STANDARD.get_or_init(|| {
+            let mut registry = PropertyRegistry::new();
+            for &(name, value_type, inherited, initial) in STANDARD_PROPERTIES {
+                registry.register(PropertyDef {
+                    name: name.to_string(),
+                    value_type,
+                    inherited,
+                    initial: initial.map(parse_value_str),
+                });
+            }
+            registry
+        })
+    }
+
+    /// Add a property, replacing any existing one of the same name.
+    pub fn register(&mut self, property: PropertyDef) {
+        self.properties.insert(property.name.clone(), property);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PropertyDef> {
+        self.properties.get(name)
+    }
+
+    pub fn is_known(&self, name: &str) -> bool {
+        self.properties.contains_key(name)
+    }
+
+    pub fn is_inherited(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|property| property.inherited)
+    }
+
+    pub fn initial_value(&self, name: &str) -> Option<&Value> {
+        self.get(name)?.initial.as_ref()
+    }
+
+    /// Is property `name` known, and `value` of the type it accepts? This is what `@supports`
+    /// asks.
+    pub fn supports(&self, name: &str, value: &Value) -> bool {
+        self.get(name)
+            .is_some_and(|property| property.value_type.accepts(value))
+    }
+
+    /// The names of the inherited properties, in no particular order.
+    pub fn inherited_properties(&self) -> impl Iterator<Item = &str> {
+        self.properties
+            .values()
+            .filter(|property| property.inherited)
+            .map(|property| property.name.as_str())
+    }
+}
+
+// The keywords length properties accept besides lengths.
+const AUTO: &[&str] = &["auto"];
+const SIZES: &[&str] = &["auto", "fit-content", "max-content", "min-content"];
+const BORDER_WIDTHS: &[&str] = &["medium", "thick", "thin"];
+const FONT_SIZES: &[&str] = &[
+    "large", "larger", "medium", "small", "smaller", "x-large", "x-small", "xx-large", "xx-small",
+];
+
+// Each property's name, value type, whether it's inherited, and initial value.
+#[rustfmt::skip]
+const STANDARD_PROPERTIES: &[(&str, ValueType, bool, Option<&str>)] = &[
+    ("align-items", Any, false, Some("stretch")),
+    ("all", Any, false, None),
+    ("aspect-ratio", Any, false, Some("auto")),
+    ("background", Color, false, Some("transparent")),
+    ("background-color", Color, false, Some("transparent")),
+    ("background-image", Any, false, Some("none")),
+    ("border", Any, false, None),
+    ("border-bottom-width", Length(BORDER_WIDTHS), false, Some("0px")),
+    ("border-color", Color, false, Some("currentcolor")),
+    ("border-left-width", Length(BORDER_WIDTHS), false, Some("0px")),
+    ("border-right-width", Length(BORDER_WIDTHS), false, Some("0px")),
+    ("border-top-width", Length(BORDER_WIDTHS), false, Some("0px")),
+    ("border-width", BoxShorthand(BORDER_WIDTHS), false, Some("0px")),
+    ("bottom", Length(AUTO), false, Some("auto")),
+    ("box-sizing", Keywords(&["border-box", "content-box"]), false, Some("content-box")),
+    ("box-shadow", Any, false, Some("none")),
+    ("color", Color, true, Some("black")),
+    ("column-gap", Length(&["normal"]), false, Some("0px")),
+    ("contain-intrinsic-size", Any, false, Some("none")),
+    ("content", Any, false, Some("normal")),
+    ("content-visibility", Keywords(&["auto", "hidden", "visible"]), false, Some("visible")),
+    ("counter-increment", Any, false, Some("none")),
+    ("counter-reset", Any, false, Some("none")),
+    ("direction", Any, true, Some("ltr")),
+    ("display", Keywords(&["block", "flex", "inline", "inline-block", "list-item", "none", "table", "table-cell", "table-row"]), false, Some("inline")),
+    ("flex-basis", Length(&["auto", "content"]), false, Some("auto")),
+    ("flex-direction", Any, false, Some("row")),
+    ("font-family", Any, true, Some("serif")),
+    ("font-size", Length(FONT_SIZES), true, Some("16px")),
+    ("font-style", Any, true, Some("normal")),
+    ("font-weight", Any, true, Some("normal")),
+    ("gap", Length(&["normal"]), false, Some("0px")),
+    ("height", Length(SIZES), false, Some("auto")),
+    ("justify-content", Any, false, Some("flex-start")),
+    ("left", Length(AUTO), false, Some("auto")),
+    // A length, a percentage, a unitless factor, or `normal`.
+    ("line-height", Any, true, Some("normal")),
+    ("list-style-type", Any, true, Some("disc")),
+    ("margin", BoxShorthand(AUTO), false, Some("0px")),
+    // Logical properties are replaced by the physical ones they map to while styling.
+    ("margin-block", Any, false, Some("0px")),
+    ("margin-block-end", Length(AUTO), false, Some("0px")),
+    ("margin-block-start", Length(AUTO), false, Some("0px")),
+    ("margin-bottom", Length(AUTO), false, Some("0px")),
+    ("margin-inline", Any, false, Some("0px")),
+    ("margin-inline-end", Length(AUTO), false, Some("0px")),
+    ("margin-inline-start", Length(AUTO), false, Some("0px")),
+    ("margin-left", Length(AUTO), false, Some("0px")),
+    ("margin-right", Length(AUTO), false, Some("0px")),
+    ("margin-top", Length(AUTO), false, Some("0px")),
+    ("max-width", Length(&["none"]), false, Some("none")),
+    ("min-width", Length(AUTO), false, Some("auto")),
+    ("outline", Any, false, None),
+    ("outline-color", Color, false, Some("currentcolor")),
+    ("outline-width", Length(BORDER_WIDTHS), false, Some("0px")),
+    ("overflow", Any, false, Some("visible")),
+    ("overflow-wrap", Any, true, Some("normal")),
+    ("overflow-x", Any, false, Some("visible")),
+    ("overflow-y", Any, false, Some("visible")),
+    ("padding", BoxShorthand(&[]), false, Some("0px")),
+    ("padding-block", Any, false, Some("0px")),
+    ("padding-block-end", Length(&[]), false, Some("0px")),
+    ("padding-block-start", Length(&[]), false, Some("0px")),
+    ("padding-bottom", Length(&[]), false, Some("0px")),
+    ("padding-inline", Any, false, Some("0px")),
+    ("padding-inline-end", Length(&[]), false, Some("0px")),
+    ("padding-inline-start", Length(&[]), false, Some("0px")),
+    ("padding-left", Length(&[]), false, Some("0px")),
+    ("padding-right", Length(&[]), false, Some("0px")),
+    ("padding-top", Length(&[]), false, Some("0px")),
+    ("position", Keywords(&["absolute", "fixed", "relative", "static", "sticky"]), false, Some("static")),
+    ("quotes", Any, true, Some("auto")),
+    ("right", Length(AUTO), false, Some("auto")),
+    ("row-gap", Length(&["normal"]), false, Some("0px")),
+    ("tab-size", Any, true, Some("8")),
+    ("text-align", Any, true, Some("left")),
+    // Not inherited in CSS, but drawn across the text of descendants, which has the same effect
+    // here.
+    ("text-decoration", Any, true, Some("none")),
+    ("text-transform", Any, true, Some("none")),
+    ("top", Length(AUTO), false, Some("auto")),
+    ("vertical-align", Any, false, Some("baseline")),
+    ("visibility", Any, true, Some("visible")),
+    ("white-space", Any, true, Some("normal")),
+    ("width", Length(SIZES), false, Some("auto")),
+    ("word-break", Any, true, Some("normal")),
+    ("writing-mode", Any, true, Some("horizontal-tb")),
+    ("z-index", Any, false, Some("auto")),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{Color as Rgba, Unit};
+
+    #[test]
+    fn standard_properties() {
+        let registry = PropertyRegistry::standard();
+        assert!(registry.is_inherited("color"));
+        let initial_color = registry.initial_value("color").and_then(Value::to_color);
+        assert_eq!(initial_color, Some(Rgba::rgb(0, 0, 0)));
+
+        assert!(registry.is_known("margin"));
+        assert!(!registry.is_inherited("margin"));
+        assert_eq!(
+            registry.initial_value("margin"),
+            Some(&Value::Length(0.0, Unit::Px))
+        );
+
+        assert!(Length(AUTO).accepts(&Value::Number(0.0)));
+        assert!(!Length(AUTO).accepts(&Value::Number(1.0)));
+        assert!(Length(AUTO).accepts(&Value::Keyword("auto".to_string())));
+        assert!(!Length(AUTO).accepts(&Value::Keyword("red".to_string())));
+
+        assert!(!registry.is_known("colour"));
+        assert_eq!(registry.initial_value("all"), None);
+    }
+
+    #[test]
+    fn registering_a_property() {
+        let mut registry = PropertyRegistry::standard().clone();
+        registry.register(PropertyDef {
+            name: "caret-color".to_string(),
+            value_type: ValueType::Color,
+            inherited: true,
+            initial: None,
+        });
+        assert!(registry.is_inherited("caret-color"));
+        assert!(registry
+            .inherited_properties()
+            .any(|name| name == "caret-color"));
+        assert!(!PropertyRegistry::standard().is_known("caret-color"));
+    }
+}
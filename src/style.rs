@@ -1,53 +1,790 @@
-use crate::css::Selector::Simple;
-use crate::css::{Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value};
+use crate::css::Color;
+use crate::css::Selector::{Child, Descendant, Simple};
+use crate::css::Unit::Px;
+use crate::css::{
+    Declaration, PseudoClass, PseudoElement, ResolutionContext, Rule, Selector, SimpleSelector,
+    Specificity, Stylesheet, Unit, Value,
+};
 use crate::dom::{ElementData, Node, NodeType};
-use std::collections::HashMap;
+use crate::properties::PropertyRegistry;
+use crate::text::{TextTransform, WhiteSpace, WrapMode};
+use std::collections::{HashMap, HashSet};
+
+/// The font size used when none is specified.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
 
 // Map from CSS property names to values.
-type PropertyMap = HashMap<String, Value>;
+pub type PropertyMap = HashMap<String, Value>;
 
 // A node with associated style data.
 pub struct StyledNode<'a> {
     pub node: &'a Node,
     pub specified_values: PropertyMap,
+    /// The specified values with lengths relative to the font size resolved to px, and
+    /// `display` lowercased. Layout reads these, through `value`.
+    pub computed_values: PropertyMap,
     pub children: Vec<StyledNode<'a>>,
+    // The values set on pseudo-elements of this node, like `::first-line`, if any.
+    pub pseudo_elements: HashMap<PseudoElement, PropertyMap>,
+    // The computed font size in px, worked out once while styling since lengths are so often
+    // resolved against it.
+    font_size: f32,
+    // The computed font size of the parent, for font sizes relative to it.
+    parent_font_size: f32,
+    // The computed font size of the root, for `rem` lengths.
+    root_font_size: f32,
+    // The origin of the stylesheet each property not inherited from the parent was set by.
+    origins: HashMap<String, CascadeOrigin>,
+}
+
+// The user agent's default styles for HTML elements.
+const USER_AGENT_CSS: &str = "
+    html, body, div, p, ul, ol, dl, dt, dd, h1, h2, h3, h4, h5, h6, pre, blockquote, hr,
+    section, article, aside, header, footer, nav, main, form, fieldset { display: block; }
+    li { display: list-item; }
+    table { display: table; }
+    tr { display: table-row; }
+    td, th { display: table-cell; }
+    q::before { content: open-quote; }
+    q::after { content: close-quote; }
+    head, link, meta, script, style, title { display: none; }
+";
+
+/// The user agent stylesheet, for use with `CascadeOrigin::UserAgent`.
+pub fn user_agent_stylesheet() -> Stylesheet {
+    crate::css::parse(USER_AGENT_CSS.to_string())
+}
+
+/// The `href`s of the `<link rel="stylesheet">` elements in a document, in document order, for
+/// the caller to fetch and parse.
+pub fn collect_linked_hrefs(root: &Node) -> Vec<String> {
+    let mut hrefs = Vec::new();
+    collect_hrefs(root, &mut hrefs);
+    hrefs
+}
+
+fn collect_hrefs(node: &Node, hrefs: &mut Vec<String>) {
+    if let Some(elem) = node.as_element() {
+        // `rel` is a space-separated list of case-insensitive link types.
+        let is_stylesheet = elem.attributes.get("rel").is_some_and(|rel| {
+            rel.split_whitespace()
+                .any(|link_type| link_type.eq_ignore_ascii_case("stylesheet"))
+        });
+        if elem.tag_name.eq_ignore_ascii_case("link") && is_stylesheet {
+            hrefs.extend(elem.attributes.get("href").cloned());
+        }
+    }
+    for child in &node.children {
+        collect_hrefs(child, hrefs);
+    }
+}
+
+/// Where a stylesheet comes from. Declarations from different origins are ordered in the cascade
+/// by origin and importance before specificity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CascadeOrigin {
+    UserAgent,
+    User,
+    Author,
+}
+
+impl CascadeOrigin {
+    // The precedence of a declaration from this origin: normal declarations from the user agent,
+    // user and author, then important ones in the reverse order.
+    fn precedence(self, important: bool) -> u8 {
+        match (important, self) {
+            (false, CascadeOrigin::UserAgent) => 0,
+            (false, CascadeOrigin::User) => 1,
+            (false, CascadeOrigin::Author) => 2,
+            (true, CascadeOrigin::Author) => 3,
+            (true, CascadeOrigin::User) => 4,
+            (true, CascadeOrigin::UserAgent) => 5,
+        }
+    }
 }
 
 pub enum Display {
     Inline,
     Block,
+    /// Laid out as a block inside, and placed on a line as a single box.
+    InlineBlock,
+    ListItem,
+    Table,
+    TableRow,
+    TableCell,
+    /// A flex container. Only single-line rows are supported.
+    Flex,
     None,
 }
 
+/// The positioning scheme of a box, from the `position` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    // Relative, but kept within the scrollport by its `top`/`bottom` offsets while its
+    // containing block allows.
+    Sticky,
+}
+
+/// The direction blocks are stacked in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WritingMode {
+    /// Top to bottom.
+    HorizontalTb,
+    /// Right to left, side by side.
+    VerticalRl,
+}
+
+/// The lines drawn across text, from `text-decoration`. Several can be combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextDecoration {
+    pub underline: bool,
+    pub line_through: bool,
+}
+
+/// How the lines of inline content are placed across their block, from `text-align`. `justify`
+/// is treated as `left`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Right,
+    Center,
+}
+
+/// How the items of a flex container share its free space along the row, from
+/// `justify-content`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JustifyContent {
+    FlexStart,
+    Center,
+    SpaceBetween,
+}
+
+/// How flex items are sized and placed across the row, from `align-items`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlignItems {
+    /// Items with an auto height are as tall as the row.
+    Stretch,
+    FlexStart,
+}
+
+/// What happens to content that overflows a box along one axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    Visible,
+    Hidden,
+    Scroll,
+    Auto,
+}
+
+impl Overflow {
+    /// Is content past the box's padding edge clipped?
+    pub fn clips(self) -> bool {
+        self != Overflow::Visible
+    }
+
+    /// Can the content be scrolled to?
+    pub fn scrolls(self) -> bool {
+        matches!(self, Overflow::Scroll | Overflow::Auto)
+    }
+}
+
+/// How an inline box is positioned vertically within its line box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerticalAlign {
+    Baseline,
+    Top,
+    Bottom,
+    Middle,
+}
+
 impl<'a> StyledNode<'a> {
-    /// Return the specified value of a property if it exists, otherwise `None`.
+    /// All computed property values of this node, including those inherited from its ancestors.
+    pub fn computed_styles(&self) -> &PropertyMap {
+        &self.computed_values
+    }
+
+    /// The value of property `name` of pseudo-element `pseudo`, if one was specified for it.
+    pub fn pseudo_element_value(&self, pseudo: PseudoElement, name: &str) -> Option<Value> {
+        self.pseudo_elements.get(&pseudo)?.get(name).cloned()
+    }
+
+    /// Return the computed value of a property if it exists, otherwise `None`.
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).cloned()
+        self.value_ref(name).cloned()
+    }
+
+    /// Like `value`, but borrows the value instead of cloning it.
+    pub fn value_ref(&self, name: &str) -> Option<&Value> {
+        self.computed_values.get(name)
+    }
+
+    /// Change property `name` of this node to `value`, or remove it if `value` is `None`, as when
+    /// a script toggles `display` or `visibility`. An inherited property also changes on the
+    /// descendants that inherited it from this node, rather than setting it themselves. Lay the
+    /// tree out again afterwards to see the change.
+    pub fn set_value(&mut self, name: &str, mut value: Option<Value>) {
+        if name == "font-size" {
+            // Without a `font-size` of its own, this node inherits its parent's.
+            let mut values = PropertyMap::new();
+            values.extend(value.map(|value| (name.to_string(), value)));
+            self.font_size = if values.is_empty() {
+                self.parent_font_size
+            } else {
+                let root_font_size = Some(self.root_font_size);
+                compute_font_size(&mut values, self.parent_font_size, root_font_size, None)
+            };
+            value = values.remove(name);
+            // Lengths relative to the font size are computed again against the new one.
+            for (name, value) in &self.specified_values {
+                let computed = compute_value(name, value, self.font_size, self.root_font_size);
+                self.computed_values.insert(name.clone(), computed);
+            }
+            for child in &mut self.children {
+                child.parent_font_size = self.font_size;
+            }
+        }
+        // Descendants inherit the computed value, such as a relative font size in px.
+        if PropertyRegistry::standard().is_inherited(name) {
+            for child in &mut self.children {
+                if !child.origins.contains_key(name) {
+                    child.set_value(name, value.clone());
+                }
+            }
+        }
+        match value {
+            Some(value) => {
+                let computed = compute_value(name, &value, self.font_size, self.root_font_size);
+                self.computed_values.insert(name.to_string(), computed);
+                self.specified_values.insert(name.to_string(), value)
+            }
+            None => {
+                self.computed_values.remove(name);
+                self.specified_values.remove(name)
+            }
+        };
+    }
+
+    /// Is this node drawn? Boxes with `visibility: hidden` (or `collapse`) still take up space.
+    pub fn is_visible(&self) -> bool {
+        !matches!(self.value("visibility"), Some(v) if v.keyword_eq("hidden") || v.keyword_eq("collapse"))
     }
 
     /// Return the specified value of property `name`, or property `fallback_name` if that doesn't
     /// exist, or value `default` if neither does.
     pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+        self.lookup_chain(&[name, fallback_name], default)
+    }
+
+    /// Like `lookup`, but borrows the value, or `default`, instead of cloning it.
+    pub fn lookup_ref<'s>(
+        &'s self,
+        name: &str,
+        fallback_name: &str,
+        default: &'s Value,
+    ) -> &'s Value {
+        self.value_ref(name)
+            .or_else(|| self.value_ref(fallback_name))
+            .unwrap_or(default)
+    }
+
+    /// Return the specified value of the first of `names` that exists, or value `default` if none
+    /// does.
+    pub fn lookup_chain(&self, names: &[&str], default: &Value) -> Value {
+        names
+            .iter()
+            .find_map(|name| self.value_ref(name))
+            .unwrap_or(default)
+            .clone()
+    }
+
+    /// The width of the border on `side` ("top", "left", ...), from `border-<side>-width`,
+    /// `border-width`, or the width in the `border` shorthand. Defaults to zero. The keywords
+    /// `thin`, `medium`, and `thick` are 1px, 3px, and 5px.
+    pub fn border_width(&self, side: &str) -> Value {
+        let zero = Value::Length(0.0, Px);
+        let names = [&format!("border-{}-width", side), "border-width", "border"];
+        match self.lookup_chain(&names, &zero) {
+            Value::List(values) => values
+                .into_iter()
+                .find_map(|value| match value {
+                    Value::Length(..) => Some(value),
+                    _ => keyword_border_width(&value),
+                })
+                .unwrap_or(zero),
+            value => keyword_border_width(&value).unwrap_or(value),
+        }
+    }
+
+    /// The space between rows, from `row-gap` or the first value of `gap`. Defaults to zero.
+    pub fn row_gap(&self) -> Value {
+        self.gap("row-gap", 0)
+    }
+
+    /// The space between columns, from `column-gap` or the second value of `gap` (or its only
+    /// value). Defaults to zero.
+    pub fn column_gap(&self) -> Value {
+        self.gap("column-gap", 1)
+    }
+
+    fn gap(&self, name: &str, index: usize) -> Value {
+        let zero = Value::Length(0.0, Px);
+        match self.lookup(name, "gap", &zero) {
+            Value::List(values) => values
+                .get(index)
+                .or(values.first())
+                .cloned()
+                .unwrap_or(zero),
+            value => value,
+        }
+    }
+
+    /// The value of color property `name`, with color keywords resolved, or `None` if it isn't
+    /// set to a color.
+    pub fn computed_color(&self, name: &str) -> Option<Color> {
+        self.value(name)?.to_color()
     }
 
-    /// The value of the `display` property (defaults to inline).
+    /// The value of the `display` property (defaults to inline). Comments are never displayed.
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match s.as_str() {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
+        if let NodeType::Comment(_) = self.node.node_type {
+            return Display::None;
+        }
+        let value = match self.value("display") {
+            Some(value) => value,
+            None => return Display::Inline,
+        };
+        if value.keyword_eq("block") {
+            Display::Block
+        } else if value.keyword_eq("inline-block") {
+            Display::InlineBlock
+        } else if value.keyword_eq("list-item") {
+            Display::ListItem
+        } else if value.keyword_eq("table") {
+            Display::Table
+        } else if value.keyword_eq("table-row") {
+            Display::TableRow
+        } else if value.keyword_eq("table-cell") {
+            Display::TableCell
+        } else if value.keyword_eq("flex") {
+            Display::Flex
+        } else if value.keyword_eq("none") {
+            Display::None
+        } else {
+            Display::Inline
+        }
+    }
+
+    /// The font size in px (defaults to `DEFAULT_FONT_SIZE`). Percentages and ems are of the
+    /// parent's font size.
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    /// The height of a line of text in px. A unitless `line-height` is a factor of the font size;
+    /// `normal` (the default) is 1.2 times the font size.
+    pub fn line_height(&self) -> f32 {
+        match self.value("line-height") {
+            Some(Value::Length(height, Px)) => height,
+            Some(Value::Number(factor)) => self.font_size() * factor,
+            _ => self.font_size() * 1.2,
+        }
+    }
+
+    /// The candidate font families from `font-family`, in order of preference. Bare names of
+    /// several words are joined with single spaces.
+    pub fn font_family(&self) -> Vec<String> {
+        fn family_name(value: &Value) -> Option<String> {
+            match value {
+                Value::Str(s) | Value::Keyword(s) => Some(s.clone()),
+                Value::List(words) => words
+                    .iter()
+                    .map(family_name)
+                    .collect::<Option<Vec<_>>>()
+                    .map(|words| words.join(" ")),
+                _ => None,
+            }
+        }
+        match self.value("font-family") {
+            Some(Value::CommaList(families)) => families.iter().filter_map(family_name).collect(),
+            Some(value) => family_name(&value).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The value of the `position` property (defaults to static).
+    pub fn position(&self) -> Position {
+        match self.value("position") {
+            Some(Value::Keyword(s)) => match s.to_ascii_lowercase().as_str() {
+                "relative" => Position::Relative,
+                "absolute" => Position::Absolute,
+                "fixed" => Position::Fixed,
+                "sticky" => Position::Sticky,
+                // Anything else is invalid, which leaves the default.
+                _ => Position::Static,
+            },
+            _ => Position::Static,
+        }
+    }
+
+    /// The value of the `writing-mode` property (defaults to horizontal-tb).
+    pub fn writing_mode(&self) -> WritingMode {
+        match self.value("writing-mode") {
+            Some(ref value) if value.keyword_eq("vertical-rl") => WritingMode::VerticalRl,
+            _ => WritingMode::HorizontalTb,
+        }
+    }
+
+    /// The `z-index` of this node, or `None` if it's `auto`. Only positioned boxes have one; it's
+    /// ignored for static boxes.
+    pub fn z_index(&self) -> Option<i32> {
+        if self.position() == Position::Static {
+            return None;
+        }
+        self.value_ref("z-index")?.to_number().map(|n| n as i32)
+    }
+
+    /// The width-to-height ratio from `aspect-ratio`, given as `W / H` or a single number, or
+    /// `None` if it's `auto` or invalid.
+    pub fn aspect_ratio(&self) -> Option<f32> {
+        let ratio = match self.value("aspect-ratio")? {
+            Value::Number(ratio) => ratio,
+            Value::List(values) => match values.as_slice() {
+                [Value::Number(w), slash, Value::Number(h)] if slash.keyword_eq("/") => w / h,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(ratio).filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+    }
+
+    /// The lines to draw across text, from `text-decoration` (defaults to none).
+    pub fn text_decoration(&self) -> TextDecoration {
+        let values = match self.value("text-decoration") {
+            Some(Value::List(values)) => values,
+            Some(value) => vec![value],
+            None => Vec::new(),
+        };
+        TextDecoration {
+            underline: values.iter().any(|v| v.keyword_eq("underline")),
+            line_through: values.iter().any(|v| v.keyword_eq("line-through")),
+        }
+    }
+
+    /// The counters named by `counter-reset` or `counter-increment` (`property`), in order, each
+    /// with the number after its name, or `default` if there isn't one.
+    pub fn counters(&self, property: &str, default: i32) -> Vec<(String, i32)> {
+        let values = match self.value(property) {
+            Some(Value::List(values)) => values,
+            Some(value) => vec![value],
+            None => return Vec::new(),
+        };
+        let mut counters: Vec<(String, i32)> = Vec::new();
+        for value in values {
+            match value {
+                Value::Keyword(name) if !name.eq_ignore_ascii_case("none") => {
+                    counters.push((name, default))
+                }
+                _ => {
+                    if let (Some(n), Some(last)) = (value.to_number(), counters.last_mut()) {
+                        last.1 = n as i32;
+                    }
+                }
+            }
+        }
+        counters
+    }
+
+    /// The (open, close) pairs of quotation marks from `quotes`, outermost first. Defaults to
+    /// typographic double quotes, then single quotes; `none` has no quotes.
+    pub fn quotes(&self) -> Vec<(String, String)> {
+        let strings = match self.value("quotes") {
+            Some(Value::List(values)) => values,
+            Some(value) if value.keyword_eq("none") => return Vec::new(),
+            _ => Vec::new(),
+        };
+        let strings: Vec<String> = strings
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Str(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+        if strings.len() < 2 {
+            return vec![
+                ("\u{201c}".to_string(), "\u{201d}".to_string()),
+                ("\u{2018}".to_string(), "\u{2019}".to_string()),
+            ];
+        }
+        strings
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+
+    /// The value of the `text-align` property (defaults to left).
+    pub fn text_align(&self) -> TextAlign {
+        match self.value_ref("text-align") {
+            Some(value) if value.keyword_eq("right") => TextAlign::Right,
+            Some(value) if value.keyword_eq("center") => TextAlign::Center,
+            _ => TextAlign::Left,
+        }
+    }
+
+    /// The value of the `justify-content` property (defaults to flex-start).
+    pub fn justify_content(&self) -> JustifyContent {
+        match self.value("justify-content") {
+            Some(Value::Keyword(s)) => match s.to_ascii_lowercase().as_str() {
+                "center" => JustifyContent::Center,
+                "space-between" => JustifyContent::SpaceBetween,
+                _ => JustifyContent::FlexStart,
+            },
+            _ => JustifyContent::FlexStart,
+        }
+    }
+
+    /// The value of the `align-items` property (defaults to stretch).
+    pub fn align_items(&self) -> AlignItems {
+        match self.value("align-items") {
+            Some(Value::Keyword(s)) if s.eq_ignore_ascii_case("flex-start") => {
+                AlignItems::FlexStart
+            }
+            _ => AlignItems::Stretch,
+        }
+    }
+
+    /// The value of the `vertical-align` property (defaults to baseline).
+    pub fn vertical_align(&self) -> VerticalAlign {
+        match self.value("vertical-align") {
+            Some(Value::Keyword(s)) => match s.to_ascii_lowercase().as_str() {
+                "top" => VerticalAlign::Top,
+                "bottom" => VerticalAlign::Bottom,
+                "middle" => VerticalAlign::Middle,
+                _ => VerticalAlign::Baseline,
+            },
+            _ => VerticalAlign::Baseline,
+        }
+    }
+
+    /// Is this a scroll container, with `overflow: scroll` or `overflow: auto` along either axis?
+    pub fn is_scroll_container(&self) -> bool {
+        let (x, y) = self.overflow();
+        x.scrolls() || y.scrolls()
+    }
+
+    /// The horizontal and vertical overflow, from `overflow-x` and `overflow-y`, or else the
+    /// first and second (or only) value of `overflow`. As in CSS, an axis left visible when the
+    /// other isn't is treated as auto, since a box can't clip along just one of them.
+    pub fn overflow(&self) -> (Overflow, Overflow) {
+        let shorthand = match self.value("overflow") {
+            Some(Value::List(values)) => values,
+            Some(value) => vec![value],
+            None => Vec::new(),
+        };
+        let axis = |name, index: usize| {
+            let value = self
+                .value(name)
+                .or_else(|| shorthand.get(index).or(shorthand.first()).cloned());
+            match value {
+                Some(Value::Keyword(s)) => match s.to_ascii_lowercase().as_str() {
+                    "hidden" => Overflow::Hidden,
+                    "scroll" => Overflow::Scroll,
+                    "auto" => Overflow::Auto,
+                    _ => Overflow::Visible,
+                },
+                _ => Overflow::Visible,
+            }
+        };
+        match (axis("overflow-x", 0), axis("overflow-y", 1)) {
+            (Overflow::Visible, y) if y.clips() => (Overflow::Auto, y),
+            (x, Overflow::Visible) if x.clips() => (x, Overflow::Auto),
+            axes => axes,
+        }
+    }
+
+    /// The size of a replaced element (only `<img>` is supported), from its `width` and `height`
+    /// properties if they're px lengths, or else its attributes of the same names, or zero.
+    /// `None` for other elements.
+    pub fn replaced_size(&self) -> Option<(f32, f32)> {
+        let elem = self
+            .node
+            .as_element()
+            .filter(|elem| elem.tag_name.eq_ignore_ascii_case("img"))?;
+        let size = |name: &str| match self.value(name) {
+            Some(length @ Value::Length(_, Unit::Px)) => length.to_px(),
+            _ => elem
+                .attributes
+                .get(name)
+                .and_then(|size| size.trim().parse().ok())
+                .unwrap_or(0.0),
+        };
+        Some((size("width"), size("height")))
+    }
+
+    /// Are the contents of this element skipped, with `content-visibility: hidden`? Its
+    /// descendants then aren't laid out or painted, but the element itself still is.
+    pub fn skips_contents(&self) -> bool {
+        self.value("content-visibility")
+            .is_some_and(|value| value.keyword_eq("hidden"))
+    }
+
+    /// The width and height from `contain-intrinsic-size`, which a box whose contents are
+    /// skipped is sized as if its contents had. A single length is used for both, and `none` (the
+    /// default) gives neither.
+    pub fn contain_intrinsic_size(&self) -> (Option<Value>, Option<Value>) {
+        match self.value("contain-intrinsic-size") {
+            Some(Value::List(values)) => match values.as_slice() {
+                [width, height] if width.is_length() && height.is_length() => {
+                    (Some(width.clone()), Some(height.clone()))
+                }
+                _ => (None, None),
+            },
+            Some(size) if size.is_length() => (Some(size.clone()), Some(size)),
+            _ => (None, None),
+        }
+    }
+
+    /// The number of spaces a tab stop is set every, from `tab-size`. Defaults to 8.
+    pub fn tab_size(&self) -> usize {
+        match self.value_ref("tab-size").and_then(Value::to_number) {
+            Some(n) if n >= 0.0 => n as usize,
+            _ => 8,
+        }
+    }
+
+    /// The value of the `text-transform` property (defaults to none).
+    pub fn text_transform(&self) -> TextTransform {
+        match self.value("text-transform") {
+            Some(Value::Keyword(s)) => match s.to_ascii_lowercase().as_str() {
+                "uppercase" => TextTransform::Uppercase,
+                "lowercase" => TextTransform::Lowercase,
+                "capitalize" => TextTransform::Capitalize,
+                _ => TextTransform::None,
             },
-            _ => Display::Inline,
+            _ => TextTransform::None,
+        }
+    }
+
+    /// How words too long for a line are broken, from `word-break` and `overflow-wrap`.
+    pub fn wrap_mode(&self) -> WrapMode {
+        let is = |name, keyword| matches!(self.value_ref(name), Some(v) if v.keyword_eq(keyword));
+        if is("word-break", "break-all") {
+            WrapMode::BreakAll
+        } else if is("overflow-wrap", "break-word") || is("overflow-wrap", "anywhere") {
+            WrapMode::BreakWord
+        } else {
+            WrapMode::Normal
         }
     }
+
+    /// The value of the `white-space` property (defaults to normal).
+    pub fn white_space(&self) -> WhiteSpace {
+        let value = match self.value_ref("white-space") {
+            Some(value) => value,
+            None => return WhiteSpace::Normal,
+        };
+        if value.keyword_eq("nowrap") {
+            WhiteSpace::Nowrap
+        } else if value.keyword_eq("pre") {
+            WhiteSpace::Pre
+        } else if value.keyword_eq("pre-wrap") {
+            WhiteSpace::PreWrap
+        } else if value.keyword_eq("pre-line") {
+            WhiteSpace::PreLine
+        } else {
+            WhiteSpace::Normal
+        }
+    }
+
+    /// A dump of this node's property values for debugging, like a browser's developer tools: a
+    /// line per property, in name order, with its computed value and where it came from, as in
+    /// `margin: 10px (author)` or `color: #ff0000 (inherited)`. Colors are shown as hex, and
+    /// lengths relative to the font size in px.
+    pub fn debug_values(&self) -> String {
+        let mut names: Vec<&String> = self.computed_values.keys().collect();
+        names.sort();
+        let mut dump = String::new();
+        for name in names {
+            let value = &self.computed_values[name];
+            let value = value.to_color().map_or(value.clone(), Value::Color);
+            let source = match self.origins.get(name) {
+                Some(CascadeOrigin::UserAgent) => "user agent",
+                Some(CascadeOrigin::User) => "user",
+                Some(CascadeOrigin::Author) => "author",
+                None => "inherited",
+            };
+            dump.push_str(&format!("{}: {} ({})\n", name, value, source));
+        }
+        dump
+    }
+
+    /// The rule in `stylesheet` whose declaration set property `name` on this node, or `None` if
+    /// no rule did (for example, because the value was inherited). `ancestors` are the elements
+    /// containing this node, outermost first, for matching selectors with combinators.
+    pub fn source_rule<'s>(
+        &self,
+        stylesheet: &'s Stylesheet,
+        ancestors: &[&ElementData],
+        name: &str,
+    ) -> Option<&'s Rule> {
+        let elem = self.node.as_element()?;
+        // Later rules in application order win, unless an earlier one is important.
+        explain(elem, ancestors, stylesheet)
+            .into_iter()
+            .flat_map(|(_, rule)| {
+                rule.declarations
+                    .iter()
+                    .filter(|declaration| declaration.name == name)
+                    .map(move |declaration| (declaration.important, rule))
+            })
+            .enumerate()
+            .max_by_key(|&(i, (important, _))| (important, i))
+            .map(|(_, (_, rule))| rule)
+    }
+}
+
+// The width of a `border-width` keyword, or `None` if `value` isn't one.
+fn keyword_border_width(value: &Value) -> Option<Value> {
+    let px = match value {
+        Value::Keyword(keyword) => match keyword.to_ascii_lowercase().as_str() {
+            "thin" => 1.0,
+            "medium" => 3.0,
+            "thick" => 5.0,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Value::Length(px, Px))
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+// Does `selector` match `elem`, or its pseudo-element `pseudo` if that's given? `ancestors` are
+// the element's ancestors, outermost first.
+fn matches(
+    elem: &ElementData,
+    ancestors: &[&ElementData],
+    selector: &Selector,
+    pseudo: Option<PseudoElement>,
+) -> bool {
     match selector {
-        Simple(s) => matches_simple_selector(elem, s),
+        Simple(s) => s.pseudo_element == pseudo && matches_simple_selector(elem, s),
+        Descendant(ancestor, s) => {
+            s.pseudo_element == pseudo
+                && matches_simple_selector(elem, s)
+                && (0..ancestors.len())
+                    .rev()
+                    .any(|i| matches(ancestors[i], &ancestors[..i], ancestor, None))
+        }
+        // Only the parent, the innermost ancestor, is checked.
+        Child(parent, s) => {
+            s.pseudo_element == pseudo
+                && matches_simple_selector(elem, s)
+                && ancestors
+                    .split_last()
+                    .is_some_and(|(&last, rest)| matches(last, rest, parent, None))
+        }
     }
 }
 
@@ -63,7 +800,25 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     if selector
         .class
         .iter()
-        .any(|class| elem.classes().contains(class.as_str()))
+        .any(|class| !elem.classes().contains(class.as_str()))
+    {
+        return false;
+    }
+
+    if selector.attributes.iter().any(|attribute| {
+        match (elem.attributes.get(&attribute.name), &attribute.value) {
+            (Some(actual), Some(value)) => actual != value,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }) {
+        return false;
+    }
+
+    if selector
+        .pseudo_classes
+        .iter()
+        .any(|pseudo_class| !matches_pseudo_class(elem, pseudo_class))
     {
         return false;
     }
@@ -71,88 +826,694 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     true
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+fn matches_pseudo_class(elem: &ElementData, pseudo_class: &PseudoClass) -> bool {
+    match pseudo_class {
+        PseudoClass::Hover => elem.state.hover,
+        PseudoClass::Active => elem.state.active,
+        PseudoClass::Focus => elem.state.focus,
+        PseudoClass::Not(inner) => !matches_simple_selector(elem, inner),
+        PseudoClass::Unsupported(_) => false,
+    }
+}
 
-// If `rule` matched `elem`, return a `MatchedRule`. Otherwise return `None`.
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    // Find the first (highest-specificity) matching selector.
+pub type MatchedRule<'a> = (Specificity, &'a Rule);
+
+// If `rule` matched `elem` (or its pseudo-element `pseudo`), return a `MatchedRule`. Otherwise
+// return `None`.
+fn match_rule<'a>(
+    elem: &ElementData,
+    ancestors: &[&ElementData],
+    rule: &'a Rule,
+    pseudo: Option<PseudoElement>,
+) -> Option<MatchedRule<'a>> {
+    // The rule applies with the specificity of its most specific matching selector.
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, selector))
-        .map(|selector| (selector.specificity(), rule))
+        .filter(|selector| matches(elem, ancestors, selector, pseudo))
+        .map(Selector::specificity)
+        .max()
+        .map(|specificity| (specificity, rule))
 }
 
-// Find all CSS rules that match the given element.
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet
-        .rules
-        .iter()
-        .filter_map(|rule| match_rule(elem, rule))
+// Rules bucketed by the id, classes, and tag name of their selectors, so that matching only has
+// to look at rules that could possibly apply to a given element.
+struct RuleIndex<'a> {
+    rules: &'a [Rule],
+    by_id: HashMap<&'a str, Vec<usize>>,
+    by_class: HashMap<&'a str, Vec<usize>>,
+    by_tag: HashMap<&'a str, Vec<usize>>,
+    universal: Vec<usize>,
+    // Whether any selector styles a pseudo-element.
+    has_pseudo_elements: bool,
+}
+
+impl<'a> RuleIndex<'a> {
+    // Index the rules of `stylesheet` that apply to a viewport `viewport_width` px wide. If the
+    // viewport width isn't known, rules in `@media` blocks are left out.
+    fn new(stylesheet: &'a Stylesheet, viewport_width: Option<f32>) -> RuleIndex<'a> {
+        let mut index = RuleIndex {
+            rules: &stylesheet.rules,
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: Vec::new(),
+            has_pseudo_elements: false,
+        };
+        for (i, rule) in stylesheet.rules.iter().enumerate() {
+            if let Some(query) = rule.media {
+                if !viewport_width.is_some_and(|width| query.matches(width)) {
+                    continue;
+                }
+            }
+            for selector in &rule.selectors {
+                index.insert(i, selector);
+            }
+        }
+        index
+    }
+
+    // File rule `i` under every key of the subject of `selector`. A rule may end up in several
+    // buckets.
+    fn insert(&mut self, i: usize, selector: &'a Selector) {
+        let simple = selector.subject();
+        self.has_pseudo_elements |= simple.pseudo_element.is_some();
+        let mut keyed = false;
+        if let Some(id) = &simple.id {
+            self.by_id.entry(id).or_default().push(i);
+            keyed = true;
+        }
+        for class in &simple.class {
+            self.by_class.entry(class).or_default().push(i);
+            keyed = true;
+        }
+        if let Some(tag_name) = &simple.tag_name {
+            self.by_tag.entry(tag_name).or_default().push(i);
+            keyed = true;
+        }
+        if !keyed {
+            self.universal.push(i);
+        }
+    }
+
+    // Rules that might match `elem`, each returned once, in source order.
+    fn candidates(&self, elem: &ElementData) -> Vec<&'a Rule> {
+        let mut indices = self.universal.clone();
+        if let Some(bucket) = elem.id().and_then(|id| self.by_id.get(id.as_str())) {
+            indices.extend(bucket);
+        }
+        for class in elem.classes() {
+            if let Some(bucket) = self.by_class.get(class) {
+                indices.extend(bucket);
+            }
+        }
+        if let Some(bucket) = self.by_tag.get(elem.tag_name.as_str()) {
+            indices.extend(bucket);
+        }
+        // A rule reachable through several buckets must only be considered once.
+        indices.sort_unstable();
+        indices.dedup();
+        indices.into_iter().map(|i| &self.rules[i]).collect()
+    }
+}
+
+// Find all CSS rules that match the given element, or its pseudo-element `pseudo`.
+fn matching_rules<'a>(
+    elem: &ElementData,
+    ancestors: &[&ElementData],
+    index: &RuleIndex<'a>,
+    pseudo: Option<PseudoElement>,
+) -> Vec<MatchedRule<'a>> {
+    index
+        .candidates(elem)
+        .into_iter()
+        .filter_map(|rule| match_rule(elem, ancestors, rule, pseudo))
         .collect()
 }
 
-// Apply styles to a single element, returning the specified values.
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+/// The rules of `stylesheet` that match `elem`, in the order they are applied: by specificity,
+/// then source order. For debugging the cascade. `ancestors` are the elements containing `elem`,
+/// outermost first.
+pub fn explain<'a>(
+    elem: &ElementData,
+    ancestors: &[&ElementData],
+    stylesheet: &'a Stylesheet,
+) -> Vec<MatchedRule<'a>> {
+    let mut rules = matching_rules(elem, ancestors, &RuleIndex::new(stylesheet, None), None);
+    rules.sort_by_key(|&(specificity, _)| specificity);
+    rules
+}
+
+impl Stylesheet {
+    /// The rules of this stylesheet that match `elem`, in the order they are applied: by
+    /// specificity, then source order. The element's ancestors aren't known, so selectors with
+    /// combinators don't match; see `explain`.
+    pub fn rules_matching(&self, elem: &ElementData) -> Vec<&Rule> {
+        explain(elem, &[], self)
+            .into_iter()
+            .map(|(_, rule)| rule)
+            .collect()
+    }
+}
+
+// The rules of each stylesheet in the cascade, with their origins.
+type Cascade<'a> = [(CascadeOrigin, RuleIndex<'a>)];
+
+// The position in the cascade of the declaration that set each property; later ones win.
+type CascadeOrder = HashMap<String, usize>;
+
+// Apply styles to a single element with the given ancestors, or its pseudo-element `pseudo`,
+// returning the specified values, the origin of the stylesheet each came from, and the position
+// in the cascade of the declaration that set each. Declarations in the element's `style`
+// attribute are author declarations more specific than any selector.
+fn specified_values(
+    elem: &ElementData,
+    ancestors: &[&ElementData],
+    cascade: &Cascade,
+    pseudo: Option<PseudoElement>,
+) -> (PropertyMap, HashMap<String, CascadeOrigin>, CascadeOrder) {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    let mut origins = HashMap::new();
+    let mut order = HashMap::new();
+    let mut declarations: Vec<((u8, bool, Specificity), CascadeOrigin, &Declaration)> = Vec::new();
+    for &(origin, ref index) in cascade {
+        for (specificity, rule) in matching_rules(elem, ancestors, index, pseudo) {
+            for declaration in &rule.declarations {
+                let precedence = origin.precedence(declaration.important);
+                declarations.push(((precedence, false, specificity), origin, declaration));
+            }
+        }
+    }
+    let inline = match (pseudo, elem.attributes.get("style")) {
+        // A malformed `style` attribute is ignored.
+        (None, Some(style)) => crate::css::try_parse_declaration_list(style).unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    for declaration in &inline {
+        let origin = CascadeOrigin::Author;
+        let precedence = origin.precedence(declaration.important);
+        declarations.push((
+            (precedence, true, Specificity::default()),
+            origin,
+            declaration,
+        ));
+    }
 
-    // Go through the rules from lowest to highest specificity
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+    // Go through the declarations from lowest to highest precedence and specificity. The sort is
+    // stable, so among equals the one that comes last in source order wins.
+    declarations.sort_by_key(|&(key, _, _)| key);
+    for (position, (_, origin, declaration)) in declarations.into_iter().enumerate() {
+        // `all` resets everything cascaded so far. It's kept so that `resolve_keywords` can
+        // apply it once the parent's values are known.
+        if declaration.name == "all" {
+            values.clear();
+            origins.clear();
+            order.clear();
+        }
+        // A box shorthand also sets its longhands, overriding any set before it.
+        for (name, value) in expand_box_shorthand(&declaration.name, &declaration.value) {
+            values.insert(name.clone(), value);
+            origins.insert(name.clone(), origin);
+            order.insert(name, position);
+        }
+        values.insert(declaration.name.clone(), declaration.value.clone());
+        origins.insert(declaration.name.clone(), origin);
+        order.insert(declaration.name.clone(), position);
+    }
+    (values, origins, order)
+}
+
+// The longhands set by `margin`, `padding`, or `border-width` with `value`: one to four values
+// for the top, right, bottom, and left sides, where a missing side copies the opposite one.
+// Other properties, and values with too many parts, have none.
+fn expand_box_shorthand(name: &str, value: &Value) -> Vec<(String, Value)> {
+    let longhand: fn(&str) -> String = match name {
+        "margin" => |side| format!("margin-{}", side),
+        "padding" => |side| format!("padding-{}", side),
+        "border-width" => |side| format!("border-{}-width", side),
+        _ => return Vec::new(),
+    };
+    let parts = match value {
+        Value::List(parts) => parts.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    let [top, right, bottom, left] = match parts {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return Vec::new(),
+    };
+    [
+        ("top", top),
+        ("right", right),
+        ("bottom", bottom),
+        ("left", left),
+    ]
+    .into_iter()
+    .map(|(side, value)| (longhand(side), value.clone()))
+    .collect()
+}
+
+// Apply the `initial`, `inherit`, and `unset` keywords, for single properties or for all of
+// them with `all`. Returns the properties reset to their initial values, which mustn't be
+// inherited.
+fn resolve_keywords(values: &mut PropertyMap, parent_values: &PropertyMap) -> HashSet<String> {
+    let mut initial = HashSet::new();
+    if let Some(all) = values.remove("all") {
+        if all.keyword_eq("initial") {
+            initial.extend(
+                PropertyRegistry::standard()
+                    .inherited_properties()
+                    .filter(|&name| !values.contains_key(name))
+                    .map(|name| name.to_string()),
+            );
+        } else if all.keyword_eq("inherit") {
+            for (name, value) in parent_values {
+                values.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    let keywords: Vec<(String, Value)> = values
+        .iter()
+        .filter(|(_, value)| {
+            ["initial", "inherit", "unset"]
+                .iter()
+                .any(|k| value.keyword_eq(k))
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    for (name, value) in keywords {
+        values.remove(&name);
+        if value.keyword_eq("initial") {
+            initial.insert(name);
+        } else if value.keyword_eq("inherit") {
+            if let Some(parent_value) = parent_values.get(&name) {
+                values.insert(name, parent_value.clone());
+            }
         }
+        // `unset` is the default behavior: inherited properties inherit, others are initial.
     }
-    values
+    initial
 }
 
 // Apply a stylesheet to an entire DOM tree, returning a StyledNode tree.
 pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
+    style_tree_with_origins(root, &[(CascadeOrigin::Author, stylesheet)])
+}
+
+/// Apply several stylesheets, each from the given origin, to an entire DOM tree. Rules in `@media`
+/// blocks are ignored; see `style_tree_for_viewport`.
+pub fn style_tree_with_origins<'a>(
+    root: &'a Node,
+    stylesheets: &[(CascadeOrigin, &'a Stylesheet)],
+) -> StyledNode<'a> {
+    style_tree_with_media(root, stylesheets, None, false)
+}
+
+/// The same as `style_tree`, but styles sibling subtrees in parallel on rayon's thread pool.
+#[cfg(feature = "rayon")]
+pub fn par_style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
+    style_tree_with_media(root, &[(CascadeOrigin::Author, stylesheet)], None, true)
+}
+
+/// Apply several stylesheets to an entire DOM tree, for a viewport `viewport_width` px wide. Rules
+/// in `@media` blocks apply only if their query holds for that width.
+pub fn style_tree_for_viewport<'a>(
+    root: &'a Node,
+    stylesheets: &[(CascadeOrigin, &'a Stylesheet)],
+    viewport_width: f32,
+) -> StyledNode<'a> {
+    style_tree_with_media(root, stylesheets, Some(viewport_width), false)
+}
+
+fn style_tree_with_media<'a>(
+    root: &'a Node,
+    stylesheets: &[(CascadeOrigin, &'a Stylesheet)],
+    viewport_width: Option<f32>,
+    parallel: bool,
+) -> StyledNode<'a> {
+    let cascade: Vec<_> = stylesheets
+        .iter()
+        .map(|&(origin, stylesheet)| (origin, RuleIndex::new(stylesheet, viewport_width)))
+        .collect();
+    let root_values = HashMap::new();
+    style_node(
+        root,
+        &cascade,
+        &root_values,
+        &mut Vec::new(),
+        None,
+        viewport_width,
+        parallel,
+    )
+}
+
+// Style `root` and its descendants. `ancestors` are the elements containing it, outermost first,
+// `root_font_size` is the font size of the document's root, or `None` if this is the root, and
+// `viewport_width` is the width of the viewport, if known. If `parallel` is set, the children are
+// styled on several threads.
+#[cfg_attr(not(feature = "rayon"), allow(clippy::only_used_in_recursion))]
+fn style_node<'a>(
+    root: &'a Node,
+    cascade: &Cascade,
+    parent_values: &PropertyMap,
+    ancestors: &mut Vec<&'a ElementData>,
+    root_font_size: Option<f32>,
+    viewport_width: Option<f32>,
+    parallel: bool,
+) -> StyledNode<'a> {
+    let (mut values, mut origins, mut order) = match root.node_type {
+        NodeType::Text(_) | NodeType::Comment(_) => Default::default(),
+        NodeType::Element(ref elem) => specified_values(elem, ancestors, cascade, None),
+    };
+    // Values from `initial`, `inherit`, and `unset` don't count as set by the stylesheet.
+    origins.retain(|name, _| {
+        let value = &values[name];
+        !["initial", "inherit", "unset"]
+            .iter()
+            .any(|k| value.keyword_eq(k))
+    });
+    let initial = resolve_keywords(&mut values, parent_values);
+    inherit(&mut values, parent_values, &initial);
+    resolve_logical_properties(&mut values, &mut origins, &mut order);
+    let parent_font_size = match parent_values.get("font-size") {
+        Some(&Value::Length(size, Px)) => size,
+        _ => DEFAULT_FONT_SIZE,
+    };
+    let font_size = compute_font_size(
+        &mut values,
+        parent_font_size,
+        root_font_size,
+        viewport_width,
+    );
+    let root_font_size = root_font_size.unwrap_or(font_size);
+    compute_line_height(&mut values, font_size);
+
+    let mut pseudo_elements = HashMap::new();
+    if let Some(elem) = root.as_element() {
+        if cascade.iter().any(|(_, index)| index.has_pseudo_elements) {
+            for pseudo in [
+                PseudoElement::FirstLine,
+                PseudoElement::FirstLetter,
+                PseudoElement::Before,
+                PseudoElement::After,
+            ] {
+                let (pseudo_values, ..) = specified_values(elem, ancestors, cascade, Some(pseudo));
+                if !pseudo_values.is_empty() {
+                    pseudo_elements.insert(pseudo, pseudo_values);
+                }
+            }
+        }
+    }
+    let depth = ancestors.len();
+    if let Some(elem) = root.as_element() {
+        ancestors.push(elem);
+    }
+    let style_child = |child, ancestors: &mut Vec<&'a ElementData>| {
+        style_node(
+            child,
+            cascade,
+            &values,
+            ancestors,
+            Some(root_font_size),
+            viewport_width,
+            parallel,
+        )
+    };
+    #[cfg(feature = "rayon")]
+    let children = if parallel {
+        use rayon::prelude::*;
+        // Each subtree only reads the shared cascade, so it can be styled on its own thread with
+        // its own copy of the ancestors.
+        let ancestors = &*ancestors;
+        root.children
+            .par_iter()
+            .map(|child| style_child(child, &mut ancestors.clone()))
+            .collect()
+    } else {
+        root.children
+            .iter()
+            .map(|child| style_child(child, ancestors))
+            .collect()
+    };
+    #[cfg(not(feature = "rayon"))]
+    let children = root
+        .children
+        .iter()
+        .map(|child| style_child(child, ancestors))
+        .collect();
+    ancestors.truncate(depth);
+    let computed_values = values
+        .iter()
+        .map(|(name, value)| {
+            let computed = compute_value(name, value, font_size, root_font_size);
+            (name.clone(), computed)
+        })
+        .collect();
     StyledNode {
         node: root,
-        specified_values: match root.node_type {
-            NodeType::Text(_) => HashMap::new(),
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-        },
-        children: root
-            .children
-            .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+        children,
+        specified_values: values,
+        computed_values,
+        pseudo_elements,
+        font_size,
+        parent_font_size,
+        root_font_size,
+        origins,
+    }
+}
+
+// The computed value of property `name` with specified value `value`: `em` and `rem` lengths,
+// including those in lists and math functions, are resolved to px, and `display` is lowercased.
+fn compute_value(name: &str, value: &Value, font_size: f32, root_font_size: f32) -> Value {
+    match value {
+        Value::Keyword(keyword) if name == "display" => Value::Keyword(keyword.to_lowercase()),
+        &Value::Length(ems, Unit::Em) => Value::px(ems * font_size),
+        &Value::Length(rems, Unit::Rem) => Value::px(rems * root_font_size),
+        Value::List(values) => Value::List(
+            values
+                .iter()
+                .map(|v| compute_value(name, v, font_size, root_font_size))
+                .collect(),
+        ),
+        Value::Math(function, args) => Value::Math(
+            *function,
+            args.iter()
+                .map(|v| compute_value(name, v, font_size, root_font_size))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+// Fill in any inherited properties missing from `values` from the parent's values, except those
+// in `initial`.
+fn inherit(values: &mut PropertyMap, parent_values: &PropertyMap, initial: &HashSet<String>) {
+    let registry = PropertyRegistry::standard();
+    for (name, value) in parent_values {
+        if registry.is_inherited(name) && !initial.contains(name) {
+            values.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+// Replace the logical margin and padding properties, like `margin-inline-start`, with the
+// physical ones they map to under the element's `direction` and `writing-mode`. Whichever of a
+// logical property and the physical one it maps to comes later in the cascade wins, and
+// `origins` and `order` are updated to match.
+fn resolve_logical_properties(
+    values: &mut PropertyMap,
+    origins: &mut HashMap<String, CascadeOrigin>,
+    order: &mut CascadeOrder,
+) {
+    let rtl = matches!(values.get("direction"), Some(v) if v.keyword_eq("rtl"));
+    let vertical = matches!(values.get("writing-mode"), Some(v) if v.keyword_eq("vertical-rl"));
+    let inline = match (vertical, rtl) {
+        (false, false) => ("left", "right"),
+        (false, true) => ("right", "left"),
+        (true, false) => ("top", "bottom"),
+        (true, true) => ("bottom", "top"),
+    };
+    let block = if vertical {
+        ("right", "left")
+    } else {
+        ("top", "bottom")
+    };
+    // Set `physical` to `value` from `logical`, unless `physical` was set later.
+    let mut apply = |values: &mut PropertyMap, logical: &str, physical: String, value| {
+        let position = order.get(logical).copied();
+        if order.get(&physical) > position.as_ref() {
+            return;
+        }
+        if let Some(origin) = origins.get(logical).copied() {
+            origins.insert(physical.clone(), origin);
+        }
+        if let Some(position) = position {
+            order.insert(physical.clone(), position);
+        }
+        values.insert(physical, value);
+    };
+    let mut logical_names = Vec::new();
+    for property in ["margin", "padding"] {
+        for (axis, (start, end)) in [("inline", inline), ("block", block)] {
+            // The shorthand, with one value for both sides or a value for each.
+            let shorthand = format!("{}-{}", property, axis);
+            if let Some(value) = values.remove(&shorthand) {
+                let (start_value, end_value) = match value {
+                    Value::List(parts) if parts.len() == 2 => (parts[0].clone(), parts[1].clone()),
+                    value => (value.clone(), value),
+                };
+                for (side, value) in [(start, start_value), (end, end_value)] {
+                    apply(values, &shorthand, format!("{}-{}", property, side), value);
+                }
+                logical_names.push(shorthand);
+            }
+            for (side, physical) in [("start", start), ("end", end)] {
+                let logical = format!("{}-{}-{}", property, axis, side);
+                if let Some(value) = values.remove(&logical) {
+                    apply(
+                        values,
+                        &logical,
+                        format!("{}-{}", property, physical),
+                        value,
+                    );
+                    logical_names.push(logical);
+                }
+            }
+        }
+    }
+    for name in logical_names {
+        origins.remove(&name);
+        order.remove(&name);
+    }
+}
+
+// The font size in px of an element with `values`, whose parent's is `parent_size`, in a
+// document whose root has font size `root_font_size` (`None` for the root itself) and a viewport
+// `viewport_width` wide, if known. A relative `font-size` is replaced with its size in px, so
+// that descendants inherit the computed size. A size relative to a viewport dimension that isn't
+// known while styling, like `vh`, is the parent's size.
+fn compute_font_size(
+    values: &mut PropertyMap,
+    parent_size: f32,
+    root_font_size: Option<f32>,
+    viewport_width: Option<f32>,
+) -> f32 {
+    let size = match values.get("font-size") {
+        Some(value) if !value.is_length() => return DEFAULT_FONT_SIZE,
+        Some(value)
+            if value.has_unit(&Unit::Vh)
+                || (value.has_unit(&Unit::Vw) && viewport_width.is_none()) =>
+        {
+            parent_size
+        }
+        // Percentages and `em` are of the parent's size, and `rem` of the root's.
+        Some(value) => value.resolve(&ResolutionContext {
+            font_size: parent_size,
+            root_font_size: root_font_size.unwrap_or(DEFAULT_FONT_SIZE),
+            viewport: (viewport_width.unwrap_or(0.0), 0.0),
+            containing_width: parent_size,
+            containing_height: None,
+        }),
+        None => return DEFAULT_FONT_SIZE,
+    };
+    values.insert("font-size".to_string(), Value::Length(size, Px));
+    size
+}
+
+// Turn a font-relative `line-height` into px, so that descendants inherit the computed height
+// rather than recomputing it against their own font size. Unitless factors are inherited as is.
+fn compute_line_height(values: &mut PropertyMap, font_size: f32) {
+    if let Some(line_height) = values.get_mut("line-height") {
+        match *line_height {
+            Value::Length(percent, Unit::Percent) => {
+                *line_height = Value::Length(percent / 100.0 * font_size, Px)
+            }
+            Value::Length(ems, Unit::Em) => *line_height = Value::Length(ems * font_size, Px),
+            _ => {}
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::css::{Declaration, Unit};
-    use crate::dom::{ElementData, Node, NodeType};
+
+    use crate::dom::{AttrMap, ElementData, Node, NodeType};
 
     #[test]
-    fn style_tree_with_empty_stylesheet() {
-        let root = Node::new(NodeType::Element(ElementData::new("div", HashMap::new())));
-        let stylesheet = Stylesheet { rules: vec![] };
+    fn cascade_orders_ids_over_classes_over_tags() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("id".to_string(), "x".to_string());
+        attributes.insert("class".to_string(), "a b".to_string());
+        let root = Node::new(NodeType::Element(ElementData::new("div", attributes)));
+        // Each rule is less specific than the one before, despite coming later.
+        let stylesheet = crate::css::parse(
+            "#x { width: 1px; } \
+             .a.b { width: 2px; height: 2px; } \
+             div.a { width: 3px; height: 3px; color: red; } \
+             div { width: 4px; height: 4px; color: blue; margin: 4px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(styled_node.value("width"), px(1.0));
+        assert_eq!(styled_node.value("height"), px(2.0));
+        assert_eq!(
+            styled_node.value("color"),
+            Some(Value::Keyword("red".into()))
+        );
+        assert_eq!(styled_node.value("margin"), px(4.0));
+    }
+
+    #[test]
+    fn rules_matching_in_cascade_order() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("class".to_string(), "note".to_string());
+        let elem = ElementData::new("p", attributes);
+        let stylesheet = crate::css::parse(
+            "p.note { color: red; } div { color: blue; } p { color: green; }".to_string(),
+        );
+        let matching = stylesheet.rules_matching(&elem);
+        assert_eq!(matching.len(), 2);
+        assert!(std::ptr::eq(matching[0], &stylesheet.rules[2]));
+        assert!(std::ptr::eq(matching[1], &stylesheet.rules[0]));
+    }
+
+    #[test]
+    fn style_tree_with_empty_stylesheet() {
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let stylesheet = Stylesheet {
+            rules: vec![],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert!(styled_node.specified_values.is_empty());
     }
 
     #[test]
     fn style_tree_with_single_rule() {
-        let mut attributes = HashMap::new();
+        let mut attributes = AttrMap::new();
         attributes.insert("id".to_string(), "main".to_string());
         let root = Node::new(NodeType::Element(ElementData::new("div", attributes)));
         let rule = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("div".to_string()),
                 id: Some("main".to_string()),
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "color".to_string(),
                 value: Value::Keyword("red".to_string()),
+                important: false,
             }],
         };
-        let stylesheet = Stylesheet { rules: vec![rule] };
+        let stylesheet = Stylesheet {
+            rules: vec![rule],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert_eq!(
             styled_node.specified_values.get("color"),
@@ -162,21 +1523,27 @@ mod tests {
 
     #[test]
     fn style_tree_with_nested_elements() {
-        let mut root = Node::new(NodeType::Element(ElementData::new("div", HashMap::new())));
-        let child = Node::new(NodeType::Element(ElementData::new("p", HashMap::new())));
+        let mut root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let child = Node::new(NodeType::Element(ElementData::new("p", AttrMap::new())));
         root.append_child(child);
         let rule = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("p".to_string()),
                 id: None,
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "margin".to_string(),
                 value: Value::Length(10.0, Unit::Px),
+                important: false,
             }],
         };
-        let stylesheet = Stylesheet { rules: vec![rule] };
+        let stylesheet = Stylesheet {
+            rules: vec![rule],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert_eq!(
             styled_node.children[0].specified_values.get("margin"),
@@ -187,38 +1554,48 @@ mod tests {
     #[test]
     fn style_tree_with_text_node() {
         let root = Node::new(NodeType::Text("Hello".to_string()));
-        let stylesheet = Stylesheet { rules: vec![] };
+        let stylesheet = Stylesheet {
+            rules: vec![],
+            ..Default::default()
+        };
         let styled_node = style_tree(&root, &stylesheet);
         assert!(styled_node.specified_values.is_empty());
     }
 
     #[test]
     fn style_tree_with_conflicting_rules() {
-        let root = Node::new(NodeType::Element(ElementData::new("div", HashMap::new())));
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
         let rule1 = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("div".to_string()),
                 id: None,
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "color".to_string(),
                 value: Value::Keyword("red".to_string()),
+                important: false,
             }],
         };
         let rule2 = Rule {
+            media: None,
             selectors: vec![Selector::Simple(SimpleSelector {
                 tag_name: Some("div".to_string()),
                 id: None,
                 class: vec![],
+                ..Default::default()
             })],
             declarations: vec![Declaration {
                 name: "color".to_string(),
                 value: Value::Keyword("blue".to_string()),
+                important: false,
             }],
         };
         let stylesheet = Stylesheet {
             rules: vec![rule1, rule2],
+            ..Default::default()
         };
         let styled_node = style_tree(&root, &stylesheet);
         assert_eq!(
@@ -226,4 +1603,898 @@ mod tests {
             Some(&Value::Keyword("blue".to_string()))
         );
     }
+
+    #[test]
+    fn rule_in_several_buckets_is_applied_once() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("class".to_string(), "foo".to_string());
+        let elem = ElementData::new("div", attributes);
+        let rule = Rule {
+            media: None,
+            selectors: vec![Selector::Simple(SimpleSelector {
+                tag_name: Some("div".to_string()),
+                id: None,
+                class: vec!["foo".to_string()],
+                ..Default::default()
+            })],
+            declarations: vec![Declaration {
+                name: "color".to_string(),
+                value: Value::Keyword("red".to_string()),
+                important: false,
+            }],
+        };
+        let stylesheet = Stylesheet {
+            rules: vec![rule],
+            ..Default::default()
+        };
+        let index = RuleIndex::new(&stylesheet, None);
+        assert!(index.by_tag["div"].contains(&0));
+        assert!(index.by_class["foo"].contains(&0));
+        let candidates = index.candidates(&elem);
+        assert_eq!(candidates.len(), 1);
+        assert!(std::ptr::eq(candidates[0], &stylesheet.rules[0]));
+    }
+
+    #[test]
+    fn candidates_come_from_the_element_buckets() {
+        let stylesheet = crate::css::parse(
+            "div.foo { color: red; } p { color: red; } #main { color: red; } \
+             .bar { color: red; } * { color: red; } .foo { color: red; } span#x { color: red; }"
+                .to_string(),
+        );
+        let index = RuleIndex::new(&stylesheet, None);
+        let candidates = |html: &str| -> Vec<String> {
+            let root = crate::html::parse(html.to_string());
+            index
+                .candidates(root.as_element().unwrap())
+                .iter()
+                .map(|rule| rule.selectors[0].to_string())
+                .collect()
+        };
+        // The buckets for other tags, ids, and classes are left out.
+        assert_eq!(
+            candidates("<div class=\"foo\"></div>"),
+            ["div.foo", "*", ".foo"]
+        );
+        assert_eq!(candidates("<p id=\"main\"></p>"), ["p", "#main", "*"]);
+        // A rule is a candidate through any one of its keys; matching rules out the rest.
+        assert_eq!(candidates("<span></span>"), ["*", "span#x"]);
+    }
+
+    #[test]
+    fn not_matches_elements_outside_inner_selector() {
+        let stylesheet = crate::css::parse("div:not(#hidden) { color: red; }".to_string());
+        let matched = |source: &str| {
+            let root = crate::html::parse(source.to_string());
+            !explain(root.as_element().unwrap(), &[], &stylesheet).is_empty()
+        };
+        assert!(matched("<div></div>"));
+        assert!(matched("<div id=\"shown\"></div>"));
+        assert!(!matched("<div id=\"hidden\"></div>"));
+        assert!(!matched("<p></p>"));
+    }
+
+    #[test]
+    fn display_keyword_is_case_insensitive() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let stylesheet = crate::css::parse("div { display: BLOCK; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        assert!(matches!(styled_node.display(), Display::Block));
+    }
+
+    #[test]
+    fn collect_stylesheet_links() {
+        let root = crate::html::parse(
+            r#"<html><head><link rel="stylesheet" href="a.css"></link><link rel="icon" href="favicon.png"></link></head><body><link rel="Alternate STYLESHEET" href="b.css"></link></body></html>"#
+                .to_string(),
+        );
+        assert_eq!(collect_linked_hrefs(&root), vec!["a.css", "b.css"]);
+    }
+
+    #[test]
+    fn font_family_list_is_inherited() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet =
+            crate::css::parse("div { font-family: \"Times New Roman\", serif; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let expected = vec!["Times New Roman".to_string(), "serif".to_string()];
+        assert_eq!(styled_node.font_family(), expected);
+        assert_eq!(styled_node.children[0].font_family(), expected);
+    }
+
+    #[test]
+    fn gaps_from_longhands_and_shorthand() {
+        let gaps = |css: &str| {
+            let root = crate::html::parse("<div></div>".to_string());
+            let stylesheet = crate::css::parse(format!("div {{ {} }}", css));
+            let styled_node = style_tree(&root, &stylesheet);
+            (styled_node.row_gap(), styled_node.column_gap())
+        };
+        let px = |n| Value::Length(n, Unit::Px);
+        assert_eq!(gaps(""), (px(0.0), px(0.0)));
+        assert_eq!(gaps("gap: 5px;"), (px(5.0), px(5.0)));
+        assert_eq!(gaps("gap: 5px 10px;"), (px(5.0), px(10.0)));
+        assert_eq!(
+            gaps("gap: 5px 10px; column-gap: 1px; row-gap: 2px;"),
+            (px(2.0), px(1.0))
+        );
+    }
+
+    #[test]
+    fn computed_values_resolve_font_relative_lengths() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { font-size: 20px; } \
+             p { display: BLOCK; font-size: 10px; width: 2em; margin: 1em 1rem; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let p = &styled.children[0];
+        assert_eq!(
+            p.specified_values.get("width"),
+            Some(&Value::Length(2.0, Unit::Em))
+        );
+        assert_eq!(p.value("width"), Some(Value::px(20.0)));
+        assert_eq!(p.value("margin-top"), Some(Value::px(10.0)));
+        assert_eq!(p.value("margin-left"), Some(Value::px(20.0)));
+        assert_eq!(
+            p.value("margin"),
+            Some(Value::List(vec![Value::px(10.0), Value::px(20.0)]))
+        );
+        assert_eq!(
+            p.value("display"),
+            Some(Value::Keyword("block".to_string()))
+        );
+    }
+
+    #[test]
+    fn box_shorthands_expand_into_longhands() {
+        let root = crate::html::parse("<div><p></p><span></span></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { margin: 10px 20px; padding: 1px 2px 3px; border-width: 4px; } \
+             p { margin-left: 5px; margin: 6px; } \
+             span { margin: 6px; margin-left: 5px; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let px = |value| Some(Value::px(value));
+        assert_eq!(styled.value("margin-top"), px(10.0));
+        assert_eq!(styled.value("margin-bottom"), px(10.0));
+        assert_eq!(styled.value("margin-left"), px(20.0));
+        assert_eq!(styled.value("margin-right"), px(20.0));
+        assert_eq!(styled.value("padding-top"), px(1.0));
+        assert_eq!(styled.value("padding-left"), px(2.0));
+        assert_eq!(styled.value("padding-bottom"), px(3.0));
+        assert_eq!(styled.value("border-right-width"), px(4.0));
+        // Whichever of the shorthand and a longhand comes last wins.
+        assert_eq!(styled.children[0].value("margin-left"), px(6.0));
+        assert_eq!(styled.children[1].value("margin-left"), px(5.0));
+        assert_eq!(styled.children[1].value("margin-top"), px(6.0));
+    }
+
+    #[test]
+    fn attribute_selectors_match_presence_and_value() {
+        let root = crate::html::parse(
+            "<div><a href=\"/x\"></a><a href=\"/y\"></a><a></a></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "a[href] { color: blue; } a[href=\"/x\"] { color: red; }".to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let colors: Vec<Option<Color>> = styled
+            .children
+            .iter()
+            .map(|a| a.computed_color("color"))
+            .collect();
+        assert_eq!(
+            colors,
+            [
+                Some(Color::rgb(255, 0, 0)),
+                Some(Color::rgb(0, 0, 255)),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn grouped_rule_uses_its_most_specific_matching_selector() {
+        let root = crate::html::parse("<h1 id=\"main\" class=\"title\"></h1>".to_string());
+        // `.title#main` beats `#main`, though `h1` alone wouldn't.
+        let stylesheet =
+            crate::css::parse("h1, .title#main { color: red; } #main { color: blue; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        assert_eq!(styled.computed_color("color"), Some(Color::rgb(255, 0, 0)));
+    }
+
+    #[test]
+    fn comments_are_styled_like_text_and_not_displayed() {
+        let root = crate::html::parse("<div><!--note--></div>".to_string());
+        let stylesheet = crate::css::parse("div { display: block; width: 10px; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        let comment = &styled.children[0];
+        assert_eq!(
+            comment.node.node_type,
+            NodeType::Comment("note".to_string())
+        );
+        assert_eq!(comment.value("width"), None);
+        assert!(matches!(comment.display(), Display::None));
+
+        let viewport = crate::layout::Dimensions::from_content(crate::layout::DEFAULT_VIEWPORT);
+        let layout_root = crate::layout::layout_tree(&styled, viewport);
+        assert!(layout_root.children.is_empty());
+        assert_eq!(layout_root.dimensions.content.height, 0.0);
+    }
+
+    #[test]
+    fn lookup_chain_falls_through_to_last_name() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let stylesheet = crate::css::parse("div { border: 3px solid black; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let zero = Value::Length(0.0, Unit::Px);
+        let value = styled_node.lookup_chain(&["border-top", "border-style", "border"], &zero);
+        assert!(matches!(value, Value::List(ref values) if values.len() == 3));
+        assert_eq!(styled_node.lookup_chain(&["a", "b"], &zero), zero);
+        assert_eq!(
+            styled_node.border_width("top"),
+            Value::Length(3.0, Unit::Px)
+        );
+    }
+
+    #[test]
+    fn overflow_scroll_and_auto_make_scroll_containers() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let is_scroll_container = |css: &str| {
+            let stylesheet = crate::css::parse(css.to_string());
+            style_tree(&root, &stylesheet).is_scroll_container()
+        };
+        assert!(is_scroll_container("div { overflow: scroll; }"));
+        assert!(is_scroll_container("div { overflow: AUTO; }"));
+        assert!(!is_scroll_container("div { overflow: hidden; }"));
+        assert!(!is_scroll_container(""));
+    }
+
+    #[test]
+    fn style_attribute_overrides_stylesheet_rules() {
+        let root = crate::html::parse(
+            r#"<div id="a" style="color: green; margin: 1px"><p style="margin: 2px"></p></div>"#
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "#a { color: red; margin: 5px !important; } p { margin: 3px; }".to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let green = Value::Keyword("green".to_string());
+        assert_eq!(styled.value("color"), Some(green.clone()));
+        // Important rules still win over normal inline declarations.
+        assert_eq!(styled.value("margin"), Some(Value::px(5.0)));
+        let p = &styled.children[0];
+        assert_eq!(p.value("margin"), Some(Value::px(2.0)));
+        assert_eq!(p.value("color"), Some(green));
+    }
+
+    #[test]
+    fn class_selector_matches_only_elements_with_the_class() {
+        let root = crate::html::parse(r#"<div><p class="highlight"></p><p></p></div>"#.to_string());
+        let stylesheet = crate::css::parse(".highlight { color: red; }".to_string());
+        let styled = style_tree(&root, &stylesheet);
+        let color = |node: &StyledNode| node.value("color").and_then(|v| v.to_color());
+        assert_eq!(color(&styled.children[0]), Some(Color::rgb(255, 0, 0)));
+        assert_eq!(color(&styled.children[1]), None);
+        assert_eq!(color(&styled), None);
+    }
+
+    #[test]
+    fn important_declarations_beat_more_specific_rules() {
+        let root = crate::html::parse(r#"<p id="a" class="b"></p>"#.to_string());
+        let stylesheet = crate::css::parse(
+            "p { color: red !important; margin: 1px !important; } \
+             #a.b { color: blue; margin: 2px; } \
+             .b { margin: 3px !important; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        assert_eq!(
+            styled.value("color").and_then(|v| v.to_color()),
+            Some(Color::rgb(255, 0, 0))
+        );
+        // Among important declarations, specificity decides again.
+        assert_eq!(styled.value("margin"), Some(Value::px(3.0)));
+    }
+
+    #[test]
+    fn descendant_selectors_match_inside_ancestors() {
+        let root = crate::html::parse(
+            "<body><p></p><div><section><p class=\"x\"></p></section></div></body>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div p { color: red; } p.x { color: blue; } body div p { margin: 1px; }".to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let color = |node: &StyledNode| node.value("color").and_then(|v| v.to_color());
+
+        // Only the `p` inside the `div` matches, however deeply nested.
+        let top_p = &styled.children[0];
+        assert_eq!(color(top_p), None);
+        assert_eq!(top_p.value("margin"), None);
+        let nested_p = &styled.children[1].children[0].children[0];
+        assert_eq!(nested_p.value("margin"), Some(Value::px(1.0)));
+        // `div p` is less specific than `p.x`, which wins.
+        assert_eq!(color(nested_p), Some(Color::rgb(0, 0, 255)));
+    }
+
+    #[test]
+    fn child_selectors_match_only_direct_children() {
+        let root = crate::html::parse(
+            "<div><p id=\"child\"></p><span><p id=\"nested\"></p></span></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div > p { color: red; } div > span > p { margin: 1px; }".to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let child = &styled.children[0];
+        let nested = &styled.children[1].children[0];
+        assert_eq!(
+            child.value("color").and_then(|v| v.to_color()),
+            Some(Color::rgb(255, 0, 0))
+        );
+        assert_eq!(child.value("margin"), None);
+        // A `p` inside a `span` inside the `div` isn't a child of the `div`.
+        assert_eq!(nested.value("color"), None);
+        assert_eq!(nested.value("margin"), Some(Value::px(1.0)));
+    }
+
+    #[test]
+    fn debug_values_show_computed_values_and_sources() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let user_agent = user_agent_stylesheet();
+        let author = crate::css::parse(
+            "div { color: red; } p { margin: 10px; padding: 2em; font-size: 10px; }".to_string(),
+        );
+        let styled = style_tree_with_origins(
+            &root,
+            &[
+                (CascadeOrigin::UserAgent, &user_agent),
+                (CascadeOrigin::Author, &author),
+            ],
+        );
+        let dump = styled.children[0].debug_values();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert!(lines.contains(&"color: #ff0000 (inherited)"));
+        assert!(lines.contains(&"margin: 10px (author)"));
+        assert!(lines.contains(&"padding: 20px (author)"));
+        assert!(lines.contains(&"display: block (user agent)"));
+    }
+
+    #[test]
+    fn logical_properties_map_to_physical_sides() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let sides = |css: &str| {
+            let stylesheet = crate::css::parse(format!(
+                "p {{ margin-inline-start: 10px; padding-block: 1px 2px; }} {}",
+                css
+            ));
+            let styled = style_tree(&root, &stylesheet);
+            let p = &styled.children[0];
+            let names = [
+                "margin-left",
+                "margin-right",
+                "margin-top",
+                "padding-top",
+                "padding-bottom",
+                "padding-right",
+            ];
+            names.map(|name| p.value(name).map(|value| value.to_px()))
+        };
+        let (px1, px2, px10) = (Some(1.0), Some(2.0), Some(10.0));
+        assert_eq!(sides(""), [px10, None, None, px1, px2, None]);
+        // `direction` is inherited from the div.
+        assert_eq!(
+            sides("div { direction: rtl; }"),
+            [None, px10, None, px1, px2, None]
+        );
+        assert_eq!(
+            sides("p { writing-mode: vertical-rl; }"),
+            [None, None, px10, None, None, px1]
+        );
+        // The logical properties themselves are gone.
+        let stylesheet = crate::css::parse("p { margin-inline: 3px; }".to_string());
+        let p = &style_tree(&root, &stylesheet).children[0];
+        assert_eq!(p.value("margin-inline"), None);
+        assert_eq!(p.value("margin-right"), Some(Value::px(3.0)));
+
+        // Whichever of a logical and a physical property comes last wins.
+        let stylesheet = crate::css::parse(
+            "p { margin-inline-start: 10px; margin-left: 5px; \
+                 margin-right: 5px; margin-inline-end: 10px; \
+                 margin-block: 1px; margin-block-start: 2px; }"
+                .to_string(),
+        );
+        let p = &style_tree(&root, &stylesheet).children[0];
+        let margins = ["left", "right", "top", "bottom"]
+            .map(|side| p.value(&format!("margin-{}", side)).map(|v| v.to_px()));
+        assert_eq!(margins, [Some(5.0), Some(10.0), Some(2.0), Some(1.0)]);
+    }
+
+    #[test]
+    fn overflow_per_axis() {
+        let root = crate::html::parse("<div></div>".to_string());
+        let overflow = |css: &str| {
+            let stylesheet = crate::css::parse(css.to_string());
+            style_tree(&root, &stylesheet).overflow()
+        };
+        use Overflow::*;
+        assert_eq!(overflow(""), (Visible, Visible));
+        assert_eq!(
+            overflow("div { overflow: hidden scroll; }"),
+            (Hidden, Scroll)
+        );
+        assert_eq!(
+            overflow("div { overflow: scroll; overflow-x: hidden; }"),
+            (Hidden, Scroll)
+        );
+        // Visible can't be combined with anything else.
+        assert_eq!(overflow("div { overflow-x: hidden; }"), (Hidden, Auto));
+    }
+
+    #[test]
+    fn wrap_mode_keywords_ignore_case() {
+        let root = crate::html::parse("<p></p>".to_string());
+        let wrap_mode = |css: &str| {
+            let stylesheet = crate::css::parse(format!("p {{ {} }}", css));
+            style_tree(&root, &stylesheet).wrap_mode()
+        };
+        assert_eq!(wrap_mode("word-break: Break-All;"), WrapMode::BreakAll);
+        assert_eq!(wrap_mode("overflow-wrap: ANYWHERE;"), WrapMode::BreakWord);
+        assert_eq!(wrap_mode("overflow-wrap: normal;"), WrapMode::Normal);
+    }
+
+    #[test]
+    fn white_space_keywords_ignore_case() {
+        let root = crate::html::parse("<p></p>".to_string());
+        let white_space = |css: &str| {
+            let stylesheet = crate::css::parse(format!("p {{ white-space: {}; }}", css));
+            style_tree(&root, &stylesheet).white_space()
+        };
+        assert_eq!(white_space("NoWrap"), WhiteSpace::Nowrap);
+        assert_eq!(white_space("PRE-line"), WhiteSpace::PreLine);
+        assert_eq!(white_space("bogus"), WhiteSpace::Normal);
+    }
+
+    #[test]
+    fn text_align_keywords_ignore_case() {
+        let root = crate::html::parse("<p></p>".to_string());
+        let text_align = |css: &str| {
+            let stylesheet = crate::css::parse(format!("p {{ text-align: {}; }}", css));
+            style_tree(&root, &stylesheet).text_align()
+        };
+        assert_eq!(text_align("Center"), TextAlign::Center);
+        assert_eq!(text_align("RIGHT"), TextAlign::Right);
+        assert_eq!(text_align("justify"), TextAlign::Left);
+    }
+
+    #[test]
+    fn hover_matches_only_in_hover_state() {
+        let stylesheet = crate::css::parse("a:hover { color: red; }".to_string());
+        let mut root = Node::new(NodeType::Element(ElementData::new("a", AttrMap::new())));
+        assert!(style_tree(&root, &stylesheet).specified_values.is_empty());
+
+        if let NodeType::Element(ref mut elem) = root.node_type {
+            elem.state.hover = true;
+        }
+        assert_eq!(
+            style_tree(&root, &stylesheet).specified_values.get("color"),
+            Some(&Value::Keyword("red".to_string()))
+        );
+    }
+
+    #[test]
+    fn malformed_style_attributes_are_ignored() {
+        let root = crate::html::parse("<p style=\"color: #12\"></p>".to_string());
+        let stylesheet = crate::css::parse("p { color: red; }".to_string());
+        assert_eq!(
+            style_tree(&root, &stylesheet).value("color"),
+            Some(Value::Keyword("red".to_string()))
+        );
+    }
+
+    #[test]
+    fn unsupported_pseudo_classes_never_match() {
+        let stylesheet = crate::css::parse(
+            "a { color: blue; } a:visited, a:nth-child(1) { color: red; }".to_string(),
+        );
+        let root = Node::new(NodeType::Element(ElementData::new("a", AttrMap::new())));
+        assert_eq!(
+            style_tree(&root, &stylesheet).value("color"),
+            Some(Value::Keyword("blue".to_string()))
+        );
+    }
+
+    #[test]
+    fn cascade_orders_by_origin_and_importance() {
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let user_agent =
+            crate::css::parse("div#main, div { color: red; margin: 1px; }".to_string());
+        let user = crate::css::parse("div { margin: 2px !important; }".to_string());
+        let author = crate::css::parse("div { color: blue; margin: 3px !important; }".to_string());
+        let styled_node = style_tree_with_origins(
+            &root,
+            &[
+                (CascadeOrigin::UserAgent, &user_agent),
+                (CascadeOrigin::User, &user),
+                (CascadeOrigin::Author, &author),
+            ],
+        );
+        assert_eq!(
+            styled_node.value("color"),
+            Some(Value::Keyword("blue".to_string()))
+        );
+        assert_eq!(
+            styled_node.value("margin"),
+            Some(Value::Length(2.0, Unit::Px))
+        );
+    }
+
+    #[test]
+    fn media_rules_depend_on_viewport_width() {
+        let root = Node::new(NodeType::Element(ElementData::new("div", AttrMap::new())));
+        let stylesheet = crate::css::parse(
+            "div { color: blue; } @media (max-width: 600px) { div { color: red; } }".to_string(),
+        );
+        let sheets = [(CascadeOrigin::Author, &stylesheet)];
+        let narrow = style_tree_for_viewport(&root, &sheets, 500.0);
+        assert_eq!(
+            narrow.value("color"),
+            Some(Value::Keyword("red".to_string()))
+        );
+        let wide = style_tree_for_viewport(&root, &sheets, 800.0);
+        assert_eq!(
+            wide.value("color"),
+            Some(Value::Keyword("blue".to_string()))
+        );
+    }
+
+    #[test]
+    fn all_initial_resets_inherited_values() {
+        let root = crate::html::parse(
+            "<div><p></p><p class=\"reset\"></p><p class=\"reset blue\"></p></div>".to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div { color: red; font-size: 20px; } p { margin: 1px; } \
+             .reset { all: initial; } .reset.blue { color: blue; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let [plain, reset, blue] = [0, 1, 2].map(|i| &styled_node.children[i]);
+        assert_eq!(plain.value("color"), Some(Value::Keyword("red".into())));
+        assert_eq!(reset.value("color"), None);
+        assert_eq!(reset.value("font-size"), None);
+        assert_eq!(reset.value("margin"), None);
+        assert_eq!(reset.value("all"), None);
+        // Declarations that come after `all` in the cascade still apply.
+        assert_eq!(blue.value("color"), Some(Value::Keyword("blue".into())));
+        assert_eq!(blue.value("font-size"), None);
+    }
+
+    #[test]
+    fn inherit_initial_and_unset_keywords() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { color: red; width: 10px; line-height: 3px; } p { width: 5px; } \
+             p { all: unset; color: initial; line-height: unset; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let p = &styled_node.children[0];
+        assert_eq!(p.value("color"), None);
+        assert_eq!(p.value("line-height"), Some(Value::Length(3.0, Unit::Px)));
+        // `width` isn't inherited, so `all: unset` resets it to its initial value.
+        assert_eq!(p.value("width"), None);
+
+        let stylesheet = crate::css::parse(
+            "div { color: red; width: 10px; } p { width: inherit; color: INITIAL; }".to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let p = &styled_node.children[0];
+        assert_eq!(p.value("width"), Some(Value::Length(10.0, Unit::Px)));
+        assert_eq!(p.value("color"), None);
+    }
+
+    #[test]
+    fn unset_inherits_inherited_properties_and_resets_others() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { color: red; margin: 10px; } p { color: blue; margin: 5px; } \
+             p { color: unset; margin: Unset; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let p = &styled_node.children[0];
+        assert_eq!(p.value("color"), Some(Value::Keyword("red".into())));
+        assert_eq!(p.value("margin"), None);
+        let zero = Value::Length(0.0, Unit::Px);
+        assert_eq!(p.lookup("margin-left", "margin", &zero), zero);
+    }
+
+    #[test]
+    fn line_height_normal_percentage_and_factor() {
+        let line_height = |css: &str| {
+            let root = crate::html::parse("<div><p></p></div>".to_string());
+            let stylesheet = crate::css::parse(format!(
+                "div {{ font-size: 20px; {} }} p {{ font-size: 10px; }}",
+                css
+            ));
+            let styled_node = style_tree(&root, &stylesheet);
+            (
+                styled_node.line_height(),
+                styled_node.children[0].line_height(),
+            )
+        };
+        assert_eq!(line_height(""), (24.0, 12.0));
+        assert_eq!(line_height("line-height: normal;"), (24.0, 12.0));
+        // A percentage is computed against the font size where it's declared, and inherited as px.
+        assert_eq!(line_height("line-height: 120%;"), (24.0, 24.0));
+        // A factor is inherited as a factor.
+        assert_eq!(line_height("line-height: 1.5;"), (30.0, 15.0));
+    }
+
+    #[test]
+    fn font_size_is_computed_once_against_the_parent() {
+        let root = crate::html::parse("<div><p><a><b><i></i></b></a></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { font-size: 20px; } p { font-size: 150%; } a { font-size: 50%; } \
+             b { font-size: 2em; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        // Recompute the chain from the declared values, from the root down.
+        let mut node = &styled_node;
+        let mut expected = DEFAULT_FONT_SIZE;
+        let mut sizes = Vec::new();
+        loop {
+            let declared = stylesheet
+                .rules_matching(node.node.as_element().unwrap())
+                .iter()
+                .find_map(|rule| rule.declarations.first().map(|d| d.value.clone()));
+            expected = match declared {
+                Some(Value::Length(size, Unit::Px)) => size,
+                Some(Value::Length(percent, Unit::Percent)) => percent / 100.0 * expected,
+                Some(Value::Length(ems, Unit::Em)) => ems * expected,
+                _ => expected,
+            };
+            assert_eq!(node.font_size(), expected);
+            sizes.push(node.font_size());
+            match node.children.first() {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        assert_eq!(sizes, [20.0, 30.0, 15.0, 30.0, 30.0]);
+    }
+
+    #[test]
+    fn rem_font_sizes_are_relative_to_the_root() {
+        let root = crate::html::parse("<html><div><p></p></div></html>".to_string());
+        let stylesheet = crate::css::parse(
+            "html { font-size: 20px; } div { font-size: 10px; } p { font-size: 2rem; width: 2em; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let p = &styled.children[0].children[0];
+        assert_eq!(p.font_size(), 40.0);
+        assert_eq!(
+            p.computed_styles().get("font-size"),
+            Some(&Value::Length(40.0, Unit::Px))
+        );
+        assert_eq!(
+            p.computed_styles().get("width"),
+            Some(&Value::Length(80.0, Unit::Px))
+        );
+    }
+
+    #[test]
+    fn set_value_changes_inherited_values_of_descendants() {
+        let root = crate::html::parse("<div><p><b></b></p><i></i><em></em></div>".to_string());
+        let stylesheet =
+            crate::css::parse("i { visibility: visible; } div, em { color: red; }".to_string());
+        let mut styled_node = style_tree(&root, &stylesheet);
+        let hidden = Some(Value::Keyword("hidden".into()));
+        styled_node.set_value("visibility", hidden.clone());
+        assert!(!styled_node.is_visible());
+        assert_eq!(
+            styled_node.children[0].children[0].value("visibility"),
+            hidden
+        );
+        // A descendant with its own value keeps it.
+        assert!(styled_node.children[1].is_visible());
+        // Even if it's the same as the value it would have inherited.
+        let blue = Some(Value::Keyword("blue".into()));
+        styled_node.set_value("color", blue.clone());
+        assert_eq!(styled_node.children[0].children[0].value("color"), blue);
+        assert_eq!(
+            styled_node.children[2].value("color"),
+            Some(Value::Keyword("red".into()))
+        );
+
+        styled_node.set_value("visibility", None);
+        assert!(styled_node.children[0].children[0].is_visible());
+        styled_node.children[0].set_value("display", Some(Value::Keyword("none".into())));
+        assert!(matches!(styled_node.children[0].display(), Display::None));
+        assert_eq!(styled_node.children[0].children[0].value("display"), None);
+    }
+
+    #[test]
+    fn set_value_recomputes_font_sizes() {
+        let root = crate::html::parse("<div><section><p></p></section></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { font-size: 10px; } section { width: 2em; } p { width: 2em; }".to_string(),
+        );
+        let mut styled = style_tree(&root, &stylesheet);
+        let section = &mut styled.children[0];
+        section.set_value("font-size", Some(Value::Length(2.0, Unit::Em)));
+        assert_eq!(section.font_size(), 20.0);
+        assert_eq!(section.value("width"), Some(Value::px(40.0)));
+        // The child inherits the computed size, rather than doubling it again.
+        let p = &section.children[0];
+        assert_eq!(p.font_size(), 20.0);
+        assert_eq!(p.value("font-size"), Some(Value::px(20.0)));
+        assert_eq!(p.value("width"), Some(Value::px(40.0)));
+
+        // Without its own size, the section inherits its parent's again.
+        section.set_value("font-size", None);
+        assert_eq!(section.font_size(), 10.0);
+        assert_eq!(section.value("width"), Some(Value::px(20.0)));
+        assert_eq!(section.children[0].font_size(), 10.0);
+    }
+
+    #[test]
+    fn pseudo_element_rules_style_only_the_pseudo_element() {
+        let root = crate::html::parse("<p></p>".to_string());
+        let stylesheet = crate::css::parse(
+            "p { color: blue; } p::first-line { color: red; } p::First-Letter { width: 1px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        assert_eq!(
+            styled_node.value("color"),
+            Some(Value::Keyword("blue".into()))
+        );
+        assert_eq!(styled_node.value("width"), None);
+        assert_eq!(
+            styled_node.pseudo_element_value(PseudoElement::FirstLine, "color"),
+            Some(Value::Keyword("red".into()))
+        );
+        assert_eq!(
+            styled_node.pseudo_element_value(PseudoElement::FirstLetter, "width"),
+            Some(Value::Length(1.0, Unit::Px))
+        );
+    }
+
+    #[test]
+    fn borrowing_lookups_agree_with_cloning_ones() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { margin: 4px; padding-left: 2em; color: blue; } p { margin-top: 1px; }"
+                .to_string(),
+        );
+        let styled_node = style_tree(&root, &stylesheet);
+        let zero = Value::Length(0.0, Px);
+        for node in [&styled_node, &styled_node.children[0]] {
+            for name in ["margin", "padding-left", "color", "width"] {
+                assert_eq!(node.value_ref(name), node.value(name).as_ref());
+            }
+            for (name, fallback) in [("margin-top", "margin"), ("padding-left", "padding")] {
+                assert_eq!(
+                    node.lookup_ref(name, fallback, &zero),
+                    &node.lookup(name, fallback, &zero)
+                );
+            }
+        }
+        // The default is borrowed when neither property is set.
+        let p = &styled_node.children[0];
+        assert!(std::ptr::eq(
+            p.lookup_ref("padding-left", "padding", &zero),
+            &zero
+        ));
+        assert_eq!(
+            styled_node.lookup_ref("padding-left", "padding", &zero),
+            &Value::Length(32.0, Px)
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_styling_matches_sequential() {
+        fn assert_same(a: &StyledNode, b: &StyledNode) {
+            assert!(std::ptr::eq(a.node, b.node));
+            assert_eq!(a.specified_values, b.specified_values);
+            assert_eq!(a.computed_values, b.computed_values);
+            assert_eq!(a.pseudo_elements, b.pseudo_elements);
+            assert_eq!(a.children.len(), b.children.len());
+            for (a, b) in a.children.iter().zip(&b.children) {
+                assert_same(a, b);
+            }
+        }
+        let sections: String = (0..20)
+            .map(|i| {
+                format!(
+                    "<section class=\"s{}\"><div><p>a</p><p class=\"x\">b</p></div></section>",
+                    i % 3
+                )
+            })
+            .collect();
+        let root = crate::html::parse(format!("<main>{}</main>", sections));
+        let stylesheet = crate::css::parse(
+            "main { font-size: 20px; color: red; } .s1 p { margin: 1em; } \
+             section div .x { color: blue; } .s2 div { padding: 2rem; } p::before { content: 'x'; }"
+                .to_string(),
+        );
+        assert_same(
+            &style_tree(&root, &stylesheet),
+            &par_style_tree(&root, &stylesheet),
+        );
+    }
+
+    #[test]
+    fn computed_styles_include_inherited_values() {
+        let root = crate::html::parse("<div><p>text</p></div>".to_string());
+        let stylesheet = crate::css::parse("div { color: red; margin: 10px; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let p = &styled_node.children[0];
+        let red = Value::Keyword("red".to_string());
+        assert_eq!(p.computed_styles().get("color"), Some(&red));
+        assert_eq!(p.computed_styles().get("margin"), None);
+        assert_eq!(p.children[0].computed_styles().get("color"), Some(&red));
+    }
+
+    #[test]
+    fn text_properties_inherit_and_box_properties_do_not() {
+        let root = crate::html::parse("<div><section><p></p></section></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "div { font-size: 20px; font-family: serif; line-height: 30px; text-align: center; \
+             width: 100px; padding: 5px; } p { text-align: left; }"
+                .to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let p = &styled.children[0].children[0];
+        assert_eq!(p.font_size(), 20.0);
+        assert_eq!(p.line_height(), 30.0);
+        assert_eq!(p.value("font-family"), Some(Value::Keyword("serif".into())));
+        // The element's own value wins over the inherited one.
+        assert_eq!(p.value("text-align"), Some(Value::Keyword("left".into())));
+        assert_eq!(p.value("width"), None);
+        assert_eq!(p.value("padding"), None);
+    }
+
+    #[test]
+    fn explain_lists_matched_rules_in_application_order() {
+        let root = crate::html::parse("<div id=\"main\"></div>".to_string());
+        let stylesheet = crate::css::parse(
+            "#main { color: red; } div { color: blue; } p { color: green; }".to_string(),
+        );
+        let explanation = explain(root.as_element().unwrap(), &[], &stylesheet);
+        assert_eq!(explanation.len(), 2);
+        assert!(std::ptr::eq(explanation[0].1, &stylesheet.rules[1]));
+        assert!(std::ptr::eq(explanation[1].1, &stylesheet.rules[0]));
+
+        let styled_node = style_tree(&root, &stylesheet);
+        let source = styled_node.source_rule(&stylesheet, &[], "color").unwrap();
+        assert!(std::ptr::eq(source, &stylesheet.rules[0]));
+        assert!(styled_node
+            .source_rule(&stylesheet, &[], "margin")
+            .is_none());
+    }
+
+    #[test]
+    fn source_rule_matches_descendant_selectors() {
+        let root = crate::html::parse("<div><p></p></div>".to_string());
+        let stylesheet = crate::css::parse("p { color: red; } div p { color: blue; }".to_string());
+        let styled_node = style_tree(&root, &stylesheet);
+        let p = &styled_node.children[0];
+        let ancestors = [root.as_element().unwrap()];
+        let source = p.source_rule(&stylesheet, &ancestors, "color").unwrap();
+        assert!(std::ptr::eq(source, &stylesheet.rules[1]));
+        // Without its ancestors, only the plain `p` rule matches.
+        let source = p.source_rule(&stylesheet, &[], "color").unwrap();
+        assert!(std::ptr::eq(source, &stylesheet.rules[0]));
+    }
 }
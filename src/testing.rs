@@ -0,0 +1,43 @@
+//! Helpers for writing layout tests.
+
+use crate::layout::{layout_tree, Dimensions, Rect, DEFAULT_VIEWPORT};
+use crate::style::StyledNode;
+
+/// Lay out `styled` in a viewport of each of the given widths, and return the height of the root
+/// box's content at each. The viewport keeps the default height. The styles aren't recomputed, so
+/// media queries stay as they were matched when `styled` was built.
+pub fn layout_at_widths(styled: &StyledNode, widths: &[u32]) -> Vec<f32> {
+    let mut root = layout_tree(styled, Dimensions::default());
+    widths
+        .iter()
+        .map(|&width| {
+            root.layout_root(Dimensions::from_content(Rect {
+                width: width as f32,
+                ..DEFAULT_VIEWPORT
+            }));
+            root.dimensions.content.height
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::style::style_tree;
+
+    #[test]
+    fn percentage_width_text_wraps_less_in_wider_viewports() {
+        let root = crate::html::parse(
+            "<div><p>some words that wrap onto more lines when the box is narrow</p></div>"
+                .to_string(),
+        );
+        let stylesheet = crate::css::parse(
+            "div { display: block; } p { display: block; width: 50%; }".to_string(),
+        );
+        let styled = style_tree(&root, &stylesheet);
+        let heights = layout_at_widths(&styled, &[400, 800, 1200]);
+        // Lines of 16px text are 19.2px tall.
+        let lines: Vec<f32> = heights.iter().map(|h| (h / 19.2).round()).collect();
+        assert_eq!(lines, [3.0, 2.0, 1.0]);
+    }
+}
@@ -0,0 +1,461 @@
+//! Text measurement and line breaking.
+//!
+//! We don't have real fonts, so all text is measured as if it were set in a monospace face whose
+//! glyphs are half as wide as the font size.
+
+const GLYPH_ADVANCE: f32 = 0.5;
+// The height of the face above its baseline, as a fraction of the font size.
+const ASCENT: f32 = 0.8;
+
+/// The width of `text` when set at `font_size`.
+pub fn text_width(text: &str, font_size: f32) -> f32 {
+    text.chars().count() as f32 * font_size * GLYPH_ADVANCE
+}
+
+/// The distance from the top of a line of text at `font_size` to its baseline, when the line is
+/// `line_height` tall. The extra leading is split evenly above and below the text.
+pub fn baseline_offset(font_size: f32, line_height: f32) -> f32 {
+    (line_height - font_size) / 2.0 + font_size * ASCENT
+}
+
+/// The distance from the baseline down to an underline, and up to a line-through, as fractions of
+/// the font size.
+pub const UNDERLINE_OFFSET: f32 = 0.1;
+pub const LINE_THROUGH_OFFSET: f32 = 0.3;
+
+/// A change of case applied to text before it is laid out, from `text-transform`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    /// Uppercase the first letter of each word. A run of text is taken to start a new word.
+    Capitalize,
+}
+
+impl TextTransform {
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            TextTransform::None => text.to_string(),
+            TextTransform::Uppercase => text.to_uppercase(),
+            TextTransform::Lowercase => text.to_lowercase(),
+            TextTransform::Capitalize => {
+                let mut result = String::with_capacity(text.len());
+                let mut word_start = true;
+                for c in text.chars() {
+                    if word_start {
+                        result.extend(c.to_uppercase());
+                    } else {
+                        result.push(c);
+                    }
+                    word_start = c.is_whitespace();
+                }
+                result
+            }
+        }
+    }
+}
+
+/// How a word that doesn't fit on a line is handled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    /// Never break inside a word; long words overflow the line.
+    Normal,
+    /// `overflow-wrap: break-word`: break a word only if it can't fit on a line of its own.
+    BreakWord,
+    /// `word-break: break-all`: break between any two characters.
+    BreakAll,
+}
+
+/// How white space in text is handled, from `white-space`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhiteSpace {
+    Normal,
+    /// Collapse white space, but never wrap.
+    Nowrap,
+    /// Preserve spaces and newlines, and never wrap.
+    Pre,
+    /// Preserve spaces and newlines, but wrap as needed.
+    PreWrap,
+    /// Collapse spaces, but preserve newlines.
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// Are runs of spaces collapsed to one?
+    pub fn collapses_spaces(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine
+        )
+    }
+
+    fn preserves_newlines(self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Nowrap | WhiteSpace::Pre)
+    }
+}
+
+/// Replace each tab in `text` with spaces up to the next tab stop, with stops every `tab_size`
+/// characters from the start of each line.
+pub fn expand_tabs(text: &str, tab_size: usize) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' if tab_size > 0 => {
+                let spaces = tab_size - column % tab_size;
+                result.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            '\t' => {}
+            '\n' => {
+                result.push(c);
+                column = 0;
+            }
+            _ => {
+                result.push(c);
+                column += 1;
+            }
+        }
+    }
+    result
+}
+
+/// A run of text placed on a line by a `LineBreaker`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Piece {
+    pub line: usize,
+    pub x: f32,
+    pub width: f32,
+    pub text: String,
+}
+
+/// Greedy line breaker. Text from several boxes can be pushed in turn, and continues on the line
+/// where the previous run left off.
+pub struct LineBreaker {
+    width: f32,
+    line: usize,
+    x: f32,
+    pending_space: bool,
+    // The width of the start edges of the inline boxes opened since content was last placed,
+    // which go on the line with the next content.
+    start_edges: f32,
+    // The width of the content on each line before this one.
+    line_widths: Vec<f32>,
+}
+
+impl LineBreaker {
+    pub fn new(width: f32) -> LineBreaker {
+        LineBreaker {
+            width,
+            line: 0,
+            x: 0.0,
+            pending_space: false,
+            start_edges: 0.0,
+            line_widths: Vec::new(),
+        }
+    }
+
+    /// Start an inline box whose margin, border, and padding before its content are `edge` wide.
+    /// The edge is placed with the box's first content, so they wrap together.
+    pub fn open_box(&mut self, edge: f32) {
+        self.start_edges += edge;
+    }
+
+    /// End an inline box whose margin, border, and padding after its content are `edge` wide.
+    /// The edge never wraps, and white space before it still separates what comes after.
+    pub fn close_box(&mut self, edge: f32) {
+        self.x += std::mem::take(&mut self.start_edges) + edge;
+    }
+
+    /// The number of lines used so far.
+    pub fn line_count(&self) -> usize {
+        if self.x > 0.0 {
+            self.line + 1
+        } else {
+            self.line
+        }
+    }
+
+    /// The width of the content placed on line `line` so far, not counting collapsed space at
+    /// its end.
+    pub fn line_width(&self, line: usize) -> f32 {
+        match self.line_widths.get(line) {
+            Some(&width) => width,
+            None if line == self.line => self.x,
+            None => 0.0,
+        }
+    }
+
+    /// Place a run of text, handling its white space as `white_space` says, and return the pieces
+    /// it was broken into.
+    pub fn push(
+        &mut self,
+        text: &str,
+        font_size: f32,
+        mode: WrapMode,
+        white_space: WhiteSpace,
+    ) -> Vec<Piece> {
+        let width = if white_space.wraps() {
+            self.width
+        } else {
+            f32::INFINITY
+        };
+        let segments: Vec<&str> = if white_space.preserves_newlines() {
+            text.split('\n').collect()
+        } else {
+            vec![text]
+        };
+        let mut pieces: Vec<Piece> = Vec::new();
+        for (i, segment) in segments.into_iter().enumerate() {
+            if i > 0 {
+                // A preserved newline always ends the line, even an empty one.
+                self.new_line();
+                self.pending_space = false;
+            }
+            if white_space.collapses_spaces() {
+                self.push_collapsed(&mut pieces, segment, font_size, mode, width);
+            } else {
+                self.push_preserved(&mut pieces, segment, font_size, mode, width);
+            }
+        }
+        pieces
+    }
+
+    /// Place an unbreakable box `width` wide, like an image, after any collapsed space left by
+    /// the text before it (in `font_size`), and return the line it's on and its x position. It
+    /// moves to a new line if it doesn't fit, unless `white_space` doesn't wrap.
+    pub fn push_atomic(
+        &mut self,
+        width: f32,
+        font_size: f32,
+        white_space: WhiteSpace,
+    ) -> (usize, f32) {
+        let mut gap = if self.pending_space && self.x > 0.0 {
+            text_width(" ", font_size)
+        } else {
+            0.0
+        };
+        let edges = std::mem::take(&mut self.start_edges);
+        if white_space.wraps() && self.x > 0.0 && self.x + gap + edges + width > self.width {
+            self.new_line();
+            gap = 0.0;
+        }
+        let x = self.x + gap + edges;
+        self.x = x + width;
+        self.pending_space = false;
+        (self.line, x)
+    }
+
+    // Place the words of `text`, collapsing each run of white space to a single space.
+    fn push_collapsed(
+        &mut self,
+        pieces: &mut Vec<Piece>,
+        text: &str,
+        font_size: f32,
+        mode: WrapMode,
+        width: f32,
+    ) {
+        let space = text_width(" ", font_size);
+        if text.starts_with(char::is_whitespace) {
+            self.pending_space = true;
+        }
+        for word in text.split_whitespace() {
+            let gap = if self.pending_space && self.x > 0.0 {
+                space
+            } else {
+                0.0
+            };
+            self.pending_space = true;
+            self.place_word(pieces, word, gap, font_size, mode, width);
+        }
+        self.pending_space = text.ends_with(char::is_whitespace);
+    }
+
+    // Place `text` with its spaces intact. Lines only break between words, and spaces at the
+    // end of a line hang past its edge rather than wrapping.
+    fn push_preserved(
+        &mut self,
+        pieces: &mut Vec<Piece>,
+        text: &str,
+        font_size: f32,
+        mode: WrapMode,
+        width: f32,
+    ) {
+        self.pending_space = false;
+        let mut rest = text;
+        while !rest.is_empty() {
+            let is_space = rest.starts_with(char::is_whitespace);
+            let end = rest
+                .find(|c: char| c.is_whitespace() != is_space)
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(end);
+            if is_space {
+                self.place(pieces, run, 0.0, font_size);
+            } else {
+                self.place_word(pieces, run, 0.0, font_size, mode, width);
+            }
+            rest = tail;
+        }
+    }
+
+    // Place a word `gap` after the end of the line so far, moving it to a new line or breaking
+    // it up if it doesn't fit in `width`.
+    fn place_word(
+        &mut self,
+        pieces: &mut Vec<Piece>,
+        word: &str,
+        mut gap: f32,
+        font_size: f32,
+        mode: WrapMode,
+        width: f32,
+    ) {
+        let mut rest = word;
+        while !rest.is_empty() {
+            let fits = self.x + gap + self.start_edges + text_width(rest, font_size) <= width;
+            if !fits && self.x > 0.0 && mode != WrapMode::BreakAll {
+                // Try the word on a line of its own before breaking it up.
+                self.new_line();
+                gap = 0.0;
+                continue;
+            }
+            let chunk = if fits || mode == WrapMode::Normal {
+                rest
+            } else {
+                // Take as many characters as fit in the remaining space, but at least one.
+                let room = ((width - self.x - gap - self.start_edges) / text_width("x", font_size))
+                    as usize;
+                if room == 0 && self.x > 0.0 {
+                    self.new_line();
+                    gap = 0.0;
+                    continue;
+                }
+                let end = rest
+                    .char_indices()
+                    .nth(room.max(1))
+                    .map_or(rest.len(), |(i, _)| i);
+                &rest[..end]
+            };
+            self.place(pieces, chunk, gap, font_size);
+            rest = &rest[chunk.len()..];
+            gap = 0.0;
+            if !rest.is_empty() {
+                self.new_line();
+            }
+        }
+    }
+
+    fn place(&mut self, pieces: &mut Vec<Piece>, text: &str, mut gap: f32, font_size: f32) {
+        let width = text_width(text, font_size);
+        // Any start edges go between the space and the text.
+        gap += std::mem::take(&mut self.start_edges);
+        match pieces.last_mut() {
+            // Continue the previous piece if it's on the same line.
+            Some(piece) if piece.line == self.line => {
+                if gap > 0.0 {
+                    piece.text.push(' ');
+                }
+                piece.text.push_str(text);
+                piece.width += gap + width;
+            }
+            _ => pieces.push(Piece {
+                line: self.line,
+                x: self.x + gap,
+                width,
+                text: text.to_string(),
+            }),
+        }
+        self.x += gap + width;
+    }
+
+    fn new_line(&mut self) {
+        self.line_widths.push(self.x);
+        self.line += 1;
+        self.x = 0.0;
+    }
+}
+
+/// Break a single run of text into lines no wider than `width`, where possible.
+pub fn break_lines(text: &str, width: f32, font_size: f32, mode: WrapMode) -> Vec<String> {
+    // Each line of a single run is one piece.
+    LineBreaker::new(width)
+        .push(text, font_size, mode, WhiteSpace::Normal)
+        .into_iter()
+        .map(|piece| piece.text)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_transforms() {
+        assert_eq!(TextTransform::Uppercase.apply("hello world"), "HELLO WORLD");
+        assert_eq!(TextTransform::Lowercase.apply("Hello World"), "hello world");
+        assert_eq!(
+            TextTransform::Capitalize.apply("hello  wide world"),
+            "Hello  Wide World"
+        );
+        assert_eq!(TextTransform::None.apply("hello"), "hello");
+    }
+
+    #[test]
+    fn wrap_at_word_boundaries() {
+        // At 10px each glyph is 5px wide, so a 50px line holds ten characters.
+        let lines = break_lines("aaaa bbbb cccc", 50.0, 10.0, WrapMode::Normal);
+        assert_eq!(lines, vec!["aaaa bbbb", "cccc"]);
+    }
+
+    #[test]
+    fn long_word_overflows_by_default() {
+        let word = "a".repeat(30);
+        let lines = break_lines(&word, 50.0, 10.0, WrapMode::Normal);
+        assert_eq!(lines, vec![word]);
+    }
+
+    #[test]
+    fn break_all_splits_long_word() {
+        let word = "abcdefghij".repeat(3);
+        let lines = break_lines(&word, 50.0, 10.0, WrapMode::BreakAll);
+        assert_eq!(lines, vec!["abcdefghij"; 3]);
+    }
+
+    #[test]
+    fn break_all_splits_multibyte_word_on_char_boundaries() {
+        let word = "é😀ü".repeat(5);
+        let lines = break_lines(&word, 30.0, 10.0, WrapMode::BreakAll);
+        assert_eq!(lines, vec!["é😀üé😀ü", "é😀üé😀ü", "é😀ü"]);
+    }
+
+    #[test]
+    fn break_word_moves_word_to_new_line_before_splitting() {
+        let text = format!("ab {}", "c".repeat(12));
+        let break_word = break_lines(&text, 50.0, 10.0, WrapMode::BreakWord);
+        assert_eq!(break_word, vec!["ab", "cccccccccc", "cc"]);
+        let break_all = break_lines(&text, 50.0, 10.0, WrapMode::BreakAll);
+        assert_eq!(break_all, vec!["ab ccccccc", "ccccc"]);
+    }
+
+    #[test]
+    fn preserved_newlines_end_empty_lines_too() {
+        let mut breaker = LineBreaker::new(100.0);
+        let pieces = breaker.push("a\n\nb", 10.0, WrapMode::Normal, WhiteSpace::Pre);
+        let lines: Vec<usize> = pieces.iter().map(|piece| piece.line).collect();
+        assert_eq!(lines, vec![0, 2]);
+        assert_eq!(breaker.line_count(), 3);
+    }
+
+    #[test]
+    fn line_widths_leave_out_collapsed_spaces() {
+        let mut breaker = LineBreaker::new(50.0);
+        breaker.push("aaaa bbbbb cc ", 10.0, WrapMode::Normal, WhiteSpace::Normal);
+        // "aaaa bbbbb" fills the first line exactly, and the space after "cc" isn't counted.
+        let widths: Vec<f32> = (0..3).map(|line| breaker.line_width(line)).collect();
+        assert_eq!(widths, [50.0, 10.0, 0.0]);
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn robinson() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_robinson"));
+    command.current_dir(env!("CARGO_MANIFEST_DIR"));
+    command
+}
+
+// A path named `name` in the temp directory, unique to this process so that concurrent test runs
+// don't write over each other's files.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("robinson-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn ppm_output_from_extension() {
+    let output = temp_path("cli-test.ppm");
+    let status = robinson().arg("-o").arg(&output).status().unwrap();
+    assert!(status.success());
+
+    let bytes = fs::read(&output).unwrap();
+    assert!(bytes.starts_with(b"P6\n800 600\n255\n"));
+    assert_eq!(bytes.len(), "P6\n800 600\n255\n".len() + 800 * 600 * 3);
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn viewport_size_from_options() {
+    let output = temp_path("cli-size-test.ppm");
+    let status = robinson()
+        .args(["--width", "400", "--height", "300", "-o"])
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let bytes = fs::read(&output).unwrap();
+    assert!(bytes.starts_with(b"P6\n400 300\n255\n"));
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn unsupported_extension_errors() {
+    let output = temp_path("cli-test.gif");
+    let result = robinson().arg("-o").arg(&output).output().unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Unknown format: gif"));
+    assert!(stderr.contains("png, ppm, pdf"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn renders_the_given_html_and_css() {
+    let output = temp_path("cli-inputs-test.ppm");
+    let status = robinson()
+        .args([
+            "--html",
+            "examples/index.html",
+            "--css",
+            "examples/style.css",
+        ])
+        .args(["--width", "120", "--height", "90", "--output"])
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let bytes = fs::read(&output).unwrap();
+    let header = b"P6\n120 90\n255\n";
+    assert!(bytes.starts_with(header));
+    assert_eq!(bytes.len(), header.len() + 120 * 90 * 3);
+    // Something was painted over the blank canvas.
+    assert!(bytes[header.len()..].iter().any(|&b| b != 255));
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn missing_input_errors() {
+    let output = temp_path("cli-missing-test.ppm");
+    let result = robinson()
+        .args(["--html", "examples/missing.html", "-o"])
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert_eq!(result.status.code(), Some(1));
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.starts_with("Can't read examples/missing.html"));
+    assert!(!stderr.contains("panicked"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn malformed_stylesheet_errors() {
+    let css = temp_path("cli-malformed.css");
+    fs::write(&css, "div { width: 10px; } ??? {").unwrap();
+    let output = temp_path("cli-malformed-test.ppm");
+    let result = robinson()
+        .arg("--css")
+        .arg(&css)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert_eq!(result.status.code(), Some(1));
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("Error parsing"));
+    assert!(!stderr.contains("panicked"));
+    assert!(!output.exists());
+    fs::remove_file(&css).unwrap();
+}
@@ -0,0 +1,71 @@
+//! Full-pipeline layout tests. Each `tests/fixtures/NAME.html` is styled with the user agent
+//! stylesheet and `NAME.css` (if present), laid out in an 800x600 viewport, and compared with the
+//! layout in `NAME.json`. Run with `UPDATE_FIXTURES=1` to write the expected layouts instead.
+
+use robinson::layout::{layout_tree, Dimensions, Rect};
+use robinson::style::{style_tree_for_viewport, user_agent_stylesheet, CascadeOrigin};
+use robinson::{css, html};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn layout_json(html_path: &Path) -> String {
+    let source = fs::read_to_string(html_path).unwrap();
+    let css_source = fs::read_to_string(html_path.with_extension("css")).unwrap_or_default();
+    let root = html::parse(source);
+    let user_agent = user_agent_stylesheet();
+    let author = css::parse(css_source);
+    let viewport = Dimensions::from_content(Rect {
+        width: 800.0,
+        height: 600.0,
+        ..Default::default()
+    });
+    let style_root = style_tree_for_viewport(
+        &root,
+        &[
+            (CascadeOrigin::UserAgent, &user_agent),
+            (CascadeOrigin::Author, &author),
+        ],
+        viewport.content.width,
+    );
+    layout_tree(&style_root, viewport).to_json()
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn layout_fixtures() {
+    let update = std::env::var_os("UPDATE_FIXTURES").is_some();
+    let mut failures = Vec::new();
+    for path in fixtures() {
+        let actual = layout_json(&path);
+        let expected_path = path.with_extension("json");
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}:\nexpected:\n{}\nactual:\n{}",
+                path.display(),
+                expected,
+                actual
+            )),
+            Err(_) => failures.push(format!(
+                "{}: missing {}",
+                path.display(),
+                expected_path.display()
+            )),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}